@@ -0,0 +1,44 @@
+use backtester::{series::DEFAULT_BUFFER_CAPACITY, timeseries::TimeSeries};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+const ROWS: u64 = 500_000;
+
+// Writes a synthetic minute-bar feed that is large enough for read throughput to dominate.
+fn synthetic_feed() -> PathBuf {
+    let path = std::env::temp_dir().join(format!("backtester_bench_series_{}.csv", std::process::id()));
+    let mut file = BufWriter::new(File::create(&path).expect("Failed to create feed"));
+    writeln!(file, "open,close,high,low,volume,datetime").unwrap();
+    for row in 0..ROWS {
+        let close = 100.0 + (row % 1000) as f32 / 100.0;
+        writeln!(file, "{},{},{},{},{},{}", close - 0.01, close, close + 0.02, close - 0.02, row % 5000, 1654781400 + 60 * row).unwrap();
+    }
+    path
+}
+
+// Testing the parse throughput of a large feed for different read buffer sizes.
+// `DEFAULT_BUFFER_CAPACITY` is the 8 KiB default of the underlying CSV reader.
+pub fn buffer_capacity(c: &mut Criterion) {
+    let path = synthetic_feed();
+    let mut group = c.benchmark_group("series");
+    group.throughput(Throughput::Bytes(std::fs::metadata(&path).unwrap().len()));
+    group.sample_size(10);
+    for capacity in [DEFAULT_BUFFER_CAPACITY, 1 << 16, 1 << 20] {
+        group.bench_with_input(BenchmarkId::from_parameter(capacity), &capacity, |b, capacity| {
+            b.iter(|| {
+                let timeseries = TimeSeries::from_csv(&path).with_buffer_capacity(*capacity);
+                for ticker in timeseries {
+                    ticker.expect("Failed to parse ticker");
+                }
+            })
+        });
+    }
+    group.finish();
+    std::fs::remove_file(&path).ok();
+}
+
+criterion_group!(benches, buffer_capacity);
+criterion_main!(benches);
@@ -1,11 +1,17 @@
 use crate::{
-    broker::Broker,
+    broker::{Broker, CommissionModel},
+    feed::{Feed, MultiFeed},
     prelude::BrokerError,
+    series::SeriesError,
     strategy::{Strategy, StrategyError},
     timeseries::TimeSeries,
     indicators::Indicator,
+    metrics,
+    types::{CorporateAction, Order, OrderId, OrderSide, Ticker},
 };
-use std::ffi::OsString;
+use chrono::{DateTime, Utc};
+use std::any::Any;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::time::{Duration, Instant};
 use std::collections::HashMap;
@@ -14,6 +20,8 @@ pub struct BacktestBuilder {
     feeds: Vec<TimeSeries>,
     brokers: Vec<Broker>,
     strategies: Vec<Box<dyn Strategy>>,
+    seed: Option<u64>,
+    unfilled_orders: UnfilledOrders,
 }
 
 impl BacktestBuilder {
@@ -22,6 +30,8 @@ impl BacktestBuilder {
             feeds: Vec::new(),
             brokers: Vec::new(),
             strategies: Vec::new(),
+            seed: None,
+            unfilled_orders: UnfilledOrders::Leave,
         }
     }
 
@@ -47,37 +57,94 @@ impl BacktestBuilder {
         self
     }
 
+    /// Seeds every backtest with `seed`. See `Backtest::with_seed`.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Handles the orders of every backtest that are still active when it ends with `policy`.
+    /// See `Backtest::with_unfilled_orders`.
+    pub fn unfilled_orders(mut self, policy: UnfilledOrders) -> Self {
+        self.unfilled_orders = policy;
+        self
+    }
+
     /// Perform a cartesian product of the brokers and strategies. This will
     /// result in a vector of runs that will be executed in parallel.
-    pub fn build(mut self) -> Vec<Backtest> {
+    ///
+    /// Fails if no feed, broker or strategy was added, since there would be nothing to run.
+    pub fn build(self) -> Result<Vec<Backtest>, BuildError> {
+        if self.feeds.is_empty() {
+            return Err(BuildError::MissingFeed);
+        }
+        if self.brokers.is_empty() {
+            return Err(BuildError::MissingBroker);
+        }
+        if self.strategies.is_empty() {
+            return Err(BuildError::MissingStrategy);
+        }
+
         let mut backtests = Vec::new();
         for strategy in self.strategies {
             for broker in &self.brokers {
                 for feed in &self.feeds {
-                    let backtest = Backtest::new(
+                    let mut backtest = Backtest::new(
                         feed.clone(),
                         broker.clone(),
                         dyn_clone::clone_box(&*strategy),
                     );
+                    backtest.seed = self.seed;
+                    backtest.unfilled_orders = self.unfilled_orders;
                     backtests.push(backtest);
                 }
             }
         }
-        backtests
+        Ok(backtests)
     }
 }
 
+/// A `BacktestBuilder` that is missing a component. See `BacktestBuilder::build`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    MissingFeed,
+    MissingBroker,
+    MissingStrategy,
+}
+
+/// What happens to the orders that are still active when a backtest ends.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum UnfilledOrders {
+    /// Leaves them in the broker's order book. This is the default.
+    #[default]
+    Leave,
+    /// Cancels them, which calls their `on_cancel` callbacks.
+    Cancel,
+}
+
 pub struct Backtest {
     feed: TimeSeries,
     broker: Broker,
     strategy: Box<dyn Strategy>,
+    seed: Option<u64>,
+    unfilled_orders: UnfilledOrders,
 }
 
 #[derive(Debug)]
 pub enum BacktestError {
-    TickerParseError,
+    /// A row of the feed could not be parsed into a `Ticker`.
+    /// Contains the line number of the offending row and the underlying error.
+    TickerParseError(u64, csv::Error),
+    FeedError(SeriesError),
     BrokerError(BrokerError),
     StrategyError(StrategyError),
+    BuildError(BuildError),
+}
+
+impl From<SeriesError> for BacktestError {
+    fn from(err: SeriesError) -> Self {
+        BacktestError::FeedError(err)
+    }
 }
 
 impl From<StrategyError> for BacktestError {
@@ -86,6 +153,12 @@ impl From<StrategyError> for BacktestError {
     }
 }
 
+impl From<BuildError> for BacktestError {
+    fn from(err: BuildError) -> Self {
+        BacktestError::BuildError(err)
+    }
+}
+
 impl From<BrokerError> for BacktestError {
     fn from(err: BrokerError) -> Self {
         BacktestError::BrokerError(err)
@@ -98,21 +171,131 @@ impl Backtest {
             feed,
             broker,
             strategy,
+            seed: None,
+            unfilled_orders: UnfilledOrders::Leave,
         }
     }
 
-    pub fn run(mut self) -> Result<BacktestResult, BacktestError> {
+    /// Passes `seed` to `Strategy::seed` before the first ticker, so that
+    /// stochastic strategies produce the same results on every run.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Handles the orders that are still active when the feed ends with `policy`. Either way,
+    /// they are listed by `BacktestResult::get_unfilled_orders`.
+    pub fn with_unfilled_orders(mut self, policy: UnfilledOrders) -> Self {
+        self.unfilled_orders = policy;
+        self
+    }
+
+    /// Applies the dividends and splits of the feed's symbols during the run.
+    /// See `Broker::add_corporate_actions`.
+    pub fn with_corporate_actions(mut self, actions: Vec<CorporateAction>) -> Self {
+        self.broker.add_corporate_actions(actions);
+        self
+    }
+
+    pub fn run(self) -> Result<BacktestResult, BacktestError> {
+        self.run_with_observer(|_, _| {})
+    }
+
+    /// Runs the backtest like `run`, calling `observer` with every ticker and the broker once
+    /// both the broker and the strategy have processed it, e.g. to record custom metrics or
+    /// plot the run as it progresses.
+    pub fn run_with_observer(mut self, mut observer: impl FnMut(&Ticker, &Broker)) -> Result<BacktestResult, BacktestError> {
         let start = Instant::now();
-        let feed_path = self.feed.get_path().as_os_str().into();
+        let feed_path = match self.feed.get_path() {
+            Some(path) => path.as_os_str().into(),
+            None => OsString::from("<memory>"),
+        };
+        let feed = std::mem::replace(&mut self.feed, TimeSeries::from_vec(Vec::new()));
+        let tickers = feed.try_into_iter()?.enumerate().map(|(row, ticker)| {
+            // Rows are 1-indexed and preceded by the header line.
+            ticker.map(|ticker| (None, ticker)).map_err(|err| {
+                let line = err.position().map(|pos| pos.line()).unwrap_or(row as u64 + 2);
+                BacktestError::TickerParseError(line, err)
+            })
+        });
+        self.drive(start, feed_path, tickers, &mut observer)
+    }
+
+    /// Runs the strategy against `feed` instead of the backtest's `TimeSeries`, e.g. to paper
+    /// trade on a live source. Tickers are processed exactly as in `run` until the feed ends.
+    pub fn run_feed(self, mut feed: impl Feed) -> Result<BacktestResult, BacktestError> {
+        let start = Instant::now();
+        let tickers = std::iter::from_fn(|| feed.next_ticker().map(|ticker| Ok((None, ticker))));
+        self.drive(start, OsString::from("<feed>"), tickers, &mut |_, _| {})
+    }
+
+    /// Runs the strategy against the merged tickers of several symbols instead of the
+    /// backtest's `TimeSeries`. Every event goes to `Broker::next_symbol` and
+    /// `Strategy::on_symbol_ticker`, and the equity curve gains a point per event.
+    /// Orders for a symbol outside of the feed are rejected with `BrokerError::UnknownSymbol`.
+    pub fn run_multi_feed(mut self, feed: MultiFeed) -> Result<BacktestResult, BacktestError> {
+        let start = Instant::now();
+        self.broker.set_symbols(&feed.get_symbols());
+        let feed_path = OsString::from(format!("<{}>", feed.get_symbols().join(", ")));
+        let tickers = feed.map(|(symbol, ticker)| Ok((Some(symbol), ticker)));
+        self.drive(start, feed_path, tickers, &mut |_, _| {})
+    }
+
+    /// The event loop shared by every way of running a backtest.
+    fn drive(
+        mut self,
+        start: Instant,
+        feed_path: OsString,
+        tickers: impl Iterator<Item = Result<(Option<String>, Ticker), BacktestError>>,
+        observer: &mut dyn FnMut(&Ticker, &Broker),
+    ) -> Result<BacktestResult, BacktestError> {
+        let strategy_name = self.strategy.to_string();
+        let broker_name = self.broker.get_name().to_string();
+        if let Some(seed) = self.seed {
+            self.strategy.seed(seed);
+        }
+        let mut tickers = tickers.peekable();
+        if let Some(Ok((_, ticker))) = tickers.peek() {
+            self.broker.start_clock(ticker.datetime);
+        }
+        self.strategy.prepare(&mut self.broker)?;
+        let mut equity_curve = Vec::new();
+        let mut in_market = Vec::new();
+        for ticker in tickers {
+            let (symbol, ticker) = ticker?;
+            match &symbol {
+                Some(symbol) => {
+                    self.broker.next_symbol(symbol, &ticker)?;
+                    self.strategy.on_symbol_ticker(symbol, &ticker, &mut self.broker)?;
+                }
+                None => {
+                    self.broker.next(&ticker)?;
+                    self.strategy.on_ticker(&ticker, &mut self.broker)?;
+                }
+            }
+            equity_curve.push((ticker.datetime, self.broker.get_equity()));
+            in_market.push(self.broker.has_open_positions());
+            observer(&ticker, &self.broker);
+        }
 
-        for ticker in self.feed {
-            let ticker = ticker.expect("Failed to parse ticker.");
-            self.broker.next(&ticker)?;
-            self.strategy.on_ticker(&ticker, &mut self.broker)?;
+        let unfilled_orders = self
+            .broker
+            .get_active_orders()
+            .map(|(id, order)| (*id, order.clone()))
+            .collect::<Vec<_>>();
+        if self.unfilled_orders == UnfilledOrders::Cancel {
+            for (id, _) in &unfilled_orders {
+                self.broker.cancel_order(*id)?;
+            }
         }
 
         Ok(BacktestResult {
-            feed_path: feed_path,
+            feed_path,
+            strategy_name,
+            broker_name,
+            equity_curve,
+            in_market,
+            unfilled_orders,
             broker: self.broker,
             strategy: self.strategy,
             runtime: start.elapsed(),
@@ -122,18 +305,1007 @@ impl Backtest {
 
 pub struct BacktestResult {
     feed_path: OsString,
+    /// The strategy's `Display` before the run, e.g. `SMA Crossover(Period: 10, ...)`.
+    strategy_name: String,
+    broker_name: String,
+    /// The broker's equity after each ticker was processed.
+    equity_curve: Vec<(DateTime<Utc>, f32)>,
+    /// Whether the broker held any position after each ticker was processed.
+    in_market: Vec<bool>,
+    /// The orders that were still active when the feed ended.
+    unfilled_orders: Vec<(OrderId, Order)>,
     broker: Broker,
     strategy: Box<dyn Strategy>,
     runtime: Duration,
 }
 
+impl BacktestResult {
+    /// The path of the feed the backtest ran on, or a placeholder such as `<memory>`.
+    pub fn get_feed_path(&self) -> &OsStr {
+        &self.feed_path
+    }
+
+    /// Describes the strategy as it was configured before the run, by its `Display`.
+    pub fn get_strategy_name(&self) -> &str {
+        &self.strategy_name
+    }
+
+    /// The name of the broker the backtest ran with.
+    pub fn get_broker_name(&self) -> &str {
+        &self.broker_name
+    }
+
+    pub fn get_broker(&self) -> &Broker {
+        &self.broker
+    }
+
+    /// The strategy in its state at the end of the run.
+    pub fn get_strategy(&self) -> &dyn Strategy {
+        &*self.strategy
+    }
+
+    /// The strategy at the end of the run as its concrete type `S`, e.g. to read its indicators,
+    /// or `None` if the backtest ran a different type of strategy.
+    pub fn get_strategy_as<S: Strategy>(&self) -> Option<&S> {
+        let strategy: &dyn Any = &*self.strategy;
+        strategy.downcast_ref()
+    }
+
+    /// The orders that were still active when the feed ended, in the order they were submitted.
+    /// Strategies that leave many orders behind may be placing orders they never manage.
+    pub fn get_unfilled_orders(&self) -> &[(OrderId, Order)] {
+        &self.unfilled_orders
+    }
+
+    pub fn get_equity_curve(&self) -> &[(DateTime<Utc>, f32)] {
+        &self.equity_curve
+    }
+
+    fn equity(&self) -> Vec<f32> {
+        self.equity_curve.iter().map(|(_, equity)| *equity).collect()
+    }
+
+    /// The fractional change from the initial cash to the final equity.
+    pub fn total_return(&self) -> f32 {
+        let initial = self.broker.get_initial_cash();
+        let last = match self.equity_curve.last() {
+            Some((_, equity)) => *equity,
+            None => initial,
+        };
+        (last - initial) / initial
+    }
+
+    /// The number of tickers in a year, inferred from the spacing of the equity curve's
+    /// datetimes. See `metrics::periods_per_year`.
+    pub fn periods_per_year(&self) -> f32 {
+        let datetimes = self.equity_curve.iter().map(|(datetime, _)| *datetime).collect::<Vec<_>>();
+        metrics::periods_per_year(&datetimes)
+    }
+
+    /// The time-weighted return: the per-ticker returns of the equity curve chained together,
+    /// starting from the initial cash. Deposits and withdrawals (see `Broker::deposit`) are
+    /// taken out of the return of the ticker they were made at, so the result only reflects
+    /// the strategy's performance and not the timing of the cash flows.
+    pub fn twr(&self) -> f32 {
+        let flows = self.broker.get_cash_flows();
+        let mut previous = self.broker.get_initial_cash();
+        let mut growth = 1.0;
+        let mut flow = flows.iter().peekable();
+        for (datetime, equity) in &self.equity_curve {
+            let mut deposited = 0.0;
+            while let Some(cash_flow) = flow.next_if(|cash_flow| cash_flow.datetime <= *datetime) {
+                deposited += cash_flow.amount;
+            }
+            growth *= (equity - deposited) / previous;
+            previous = *equity;
+        }
+        growth - 1.0
+    }
+
+    /// The money-weighted return: the internal rate of return of the initial cash, every
+    /// deposit and withdrawal, and the final equity, over the whole run so that it compares
+    /// with `twr`. Unlike `twr`, it is weighted towards the periods with the most money at stake.
+    ///
+    /// Returns NaN if the rate cannot be found, e.g. if the run has no tickers.
+    pub fn mwr(&self) -> f32 {
+        let (start, end) = match (self.equity_curve.first(), self.equity_curve.last()) {
+            (Some((start, _)), Some((end, equity))) if end > start => (*start, (*end, *equity)),
+            _ => return f32::NAN,
+        };
+        let duration = (end.0 - start).num_seconds() as f32;
+        let time = |datetime: DateTime<Utc>| ((datetime - start).num_seconds() as f32 / duration).max(0.0);
+        let mut flows = vec![(0.0, -self.broker.get_initial_cash())];
+        flows.extend(self.broker.get_cash_flows().iter().map(|flow| (time(flow.datetime), -flow.amount)));
+        flows.push((1.0, end.1));
+        metrics::irr(&flows)
+    }
+
+    /// The annualized Sharpe ratio of the per-ticker returns of the equity curve.
+    /// `periods_per_year` is the number of tickers in a year, e.g. `252.0` for daily bars.
+    pub fn sharpe_ratio(&self, periods_per_year: f32) -> f32 {
+        metrics::sharpe_ratio(&metrics::returns(&self.equity()), periods_per_year)
+    }
+
+    /// The annualized standard deviation of the per-ticker returns of the equity curve.
+    /// `periods_per_year` is the number of tickers in a year, e.g. `252.0` for daily bars.
+    pub fn annualized_volatility(&self, periods_per_year: f32) -> f32 {
+        metrics::annualized_volatility(&metrics::returns(&self.equity()), periods_per_year)
+    }
+
+    /// The beta of the equity curve's returns against those of a `benchmark` equity curve,
+    /// e.g. `get_equity_curve()` of a buy-and-hold run over the same feed.
+    ///
+    /// Only the timestamps that appear in both curves are compared.
+    pub fn beta(&self, benchmark: &[(DateTime<Utc>, f32)]) -> f32 {
+        let benchmark = benchmark.iter().copied().collect::<HashMap<_, _>>();
+        let (equity, benchmark): (Vec<f32>, Vec<f32>) = self
+            .equity_curve
+            .iter()
+            .filter_map(|(datetime, equity)| Some((*equity, *benchmark.get(datetime)?)))
+            .unzip();
+        metrics::beta(&metrics::returns(&equity), &metrics::returns(&benchmark))
+    }
+
+    /// The fraction of tickers after which the broker held a nonzero position in any symbol,
+    /// from `0.0` for a strategy that never trades to `1.0` for one that is always invested.
+    pub fn exposure(&self) -> f32 {
+        if self.in_market.is_empty() {
+            return 0.0;
+        }
+        self.in_market.iter().filter(|held| **held).count() as f32 / self.in_market.len() as f32
+    }
+
+    /// The return over every `window` consecutive points of the equity curve, at the
+    /// timestamp of the window's last point. Empty if the curve has fewer than `window` points.
+    pub fn rolling_return(&self, window: usize) -> Vec<(DateTime<Utc>, f32)> {
+        self.rolling(window, |equity| (equity[equity.len() - 1] - equity[0]) / equity[0])
+    }
+
+    /// The annualized Sharpe ratio of the returns within every `window` consecutive points
+    /// of the equity curve, at the timestamp of the window's last point.
+    /// Empty if the curve has fewer than `window` points.
+    pub fn rolling_sharpe(&self, window: usize, periods_per_year: f32) -> Vec<(DateTime<Utc>, f32)> {
+        self.rolling(window, |equity| metrics::sharpe_ratio(&metrics::returns(equity), periods_per_year))
+    }
+
+    fn rolling(&self, window: usize, f: impl Fn(&[f32]) -> f32) -> Vec<(DateTime<Utc>, f32)> {
+        if window == 0 {
+            return Vec::new();
+        }
+        self.equity()
+            .windows(window)
+            .zip(&self.equity_curve[window - 1..])
+            .map(|(equity, (datetime, _))| (*datetime, f(equity)))
+            .collect()
+    }
+
+    /// The drawdown from the running peak of the equity curve at every point, as a negative
+    /// fraction or `0.0` at a new high, i.e. the "underwater" curve.
+    pub fn drawdown_curve(&self) -> Vec<(DateTime<Utc>, f32)> {
+        let drawdowns = metrics::drawdowns(&self.equity());
+        self.equity_curve
+            .iter()
+            .zip(drawdowns)
+            .map(|((datetime, _), drawdown)| (*datetime, drawdown))
+            .collect()
+    }
+
+    /// The largest peak-to-trough decline of the equity curve, as a negative fraction.
+    pub fn max_drawdown(&self) -> f32 {
+        metrics::max_drawdown(&self.equity())
+    }
+
+    /// Summarizes the final state of the run as it was executed.
+    pub fn summary(&self) -> BacktestSummary {
+        let initial = self.broker.get_initial_cash();
+        let equity = self.broker.get_equity();
+        BacktestSummary {
+            commission: self.broker.get_commission().clone(),
+            slippage: self.broker.get_slippage(),
+            total_commission: self
+                .broker
+                .get_trades()
+                .iter()
+                .map(|trade| trade.commission * trade.fx_rate)
+                .sum(),
+            cash: self.broker.get_cash(),
+            equity,
+            total_return: (equity - initial) / initial,
+        }
+    }
+
+    /// Recomputes the final state of the run under different trading costs by replaying
+    /// the recorded trades, without iterating the feed or the strategy again. Each trade is
+    /// refilled from its market price with the new slippage and the broker's tick size, and
+    /// charged the new commission as a maker or a taker like it was, at its FX rate.
+    /// Cash that did not come from trades, e.g. dividends, financing and deposits, carries over.
+    ///
+    /// The result matches a full re-run as long as the strategy's decisions do not depend
+    /// on the account's cash, e.g. when it trades fixed quantities.
+    pub fn reprice(&self, commission: CommissionModel, slippage: f32) -> BacktestSummary {
+        let initial = self.broker.get_initial_cash();
+        let mut cash = self.broker.get_cash();
+        let mut total_commission = 0.0;
+        for trade in self.broker.get_trades() {
+            let quantity = match trade.side {
+                OrderSide::Buy => trade.quantity,
+                OrderSide::Sell => -trade.quantity,
+            };
+            let price = self.broker.execution_price(&trade.side, trade.market_price, slippage, trade.limit);
+            let fee = commission.commission_for(trade.quantity, price, trade.maker);
+            // Only the difference in costs is applied, to keep the rounding error small.
+            cash += (quantity * (trade.price - price) + trade.commission - fee) * trade.fx_rate;
+            total_commission += fee * trade.fx_rate;
+        }
+
+        // Costs do not change the positions, so their marked value carries over.
+        let equity = cash + self.broker.get_equity() - self.broker.get_cash();
+        BacktestSummary {
+            commission,
+            slippage,
+            total_commission,
+            cash,
+            equity,
+            total_return: (equity - initial) / initial,
+        }
+    }
+
+    /// Reprices the run at each of `values` of the cost `param`, keeping the other costs as
+    /// executed, to show how sensitive the strategy is to trading costs. See `reprice`.
+    ///
+    /// Sweeping `Commission` replaces the broker's commission model with a percentage one,
+    /// so a fixed or maker/taker fee schedule is not kept.
+    ///
+    /// ```no_run
+    /// # use backtester::prelude::*;
+    /// # fn sweep(result: &BacktestResult) {
+    /// for (commission, summary) in result.sensitivity(CostParameter::Commission, &[0.0, 0.001, 0.005]) {
+    ///     println!("{}: {}", commission, summary.total_return);
+    /// }
+    /// # }
+    /// ```
+    pub fn sensitivity(&self, param: CostParameter, values: &[f32]) -> Vec<(f32, BacktestSummary)> {
+        let commission = self.broker.get_commission();
+        let slippage = self.broker.get_slippage();
+        values
+            .iter()
+            .map(|&value| {
+                let summary = match param {
+                    CostParameter::Commission => self.reprice(CommissionModel::Percentage(value), slippage),
+                    CostParameter::Slippage => self.reprice(commission.clone(), value),
+                    CostParameter::Spread => self.reprice(commission.clone(), slippage + value / 2.0),
+                };
+                (value, summary)
+            })
+            .collect()
+    }
+}
+
+/// A trading cost that `BacktestResult::sensitivity` can sweep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CostParameter {
+    /// A commission of the given fraction of each trade's value, as `CommissionModel::Percentage`,
+    /// in place of the broker's commission model.
+    Commission,
+    /// The fraction by which every fill is worse than the market price.
+    Slippage,
+    /// The bid-ask spread as a fraction of the price. Every fill crosses half of it,
+    /// on top of the executed slippage.
+    Spread,
+}
+
+/// The final state of an account under a given commission and slippage.
+/// See `BacktestResult::reprice`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktestSummary {
+    pub commission: CommissionModel,
+    pub slippage: f32,
+    /// The sum of the commission paid on every trade, in the account currency.
+    pub total_commission: f32,
+    pub cash: f32,
+    pub equity: f32,
+    pub total_return: f32,
+}
+
 impl fmt::Display for BacktestResult {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut result = String::new();
         result.push_str(&format!("Feed: {}\n", self.feed_path.to_str().unwrap()));
         result.push_str(&format!("Broker: {}\n", self.broker));
         result.push_str(&format!("Strategy: {}\n", self.strategy));
+        result.push_str(&format!("Total Return: {}\n", self.total_return()));
+        result.push_str(&format!("Max Drawdown: {}\n", self.max_drawdown()));
+        result.push_str(&format!("Exposure: {}\n", self.exposure()));
+        result.push_str(&format!("Unfilled Orders: {}\n", self.unfilled_orders.len()));
         result.push_str(&format!("Runtime: {:?}\n", self.runtime));
         write!(f, "{}", result)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::{BuyAndHold, SMACrossover};
+    use crate::broker::BrokerBuilder;
+    use crate::types::{CorporateAction, Order, OrderExecutionStrategy, OrderId, OrderType, Ticker};
+    use chrono::{TimeZone, Utc};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    /// Flips a coin on every ticker to decide whether to buy or sell.
+    #[derive(Clone)]
+    struct CoinFlip {
+        rng: ChaCha8Rng,
+    }
+
+    impl fmt::Display for CoinFlip {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Coin Flip")
+        }
+    }
+
+    impl Strategy for CoinFlip {
+        fn prepare(&mut self, _broker: &mut Broker) -> Result<(), StrategyError> {
+            Ok(())
+        }
+
+        fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+            let side = if self.rng.gen_bool(0.5) {
+                OrderSide::Buy
+            } else {
+                OrderSide::Sell
+            };
+            broker.submit_order(
+                Order {
+                    symbol: "AAPL".to_string(),
+                    quantity: 10.0,
+                    side,
+                    order_type: OrderType::Market,
+                    datetime: ticker.datetime,
+                    execution: OrderExecutionStrategy::GTC,
+                    reduce_only: false,
+                    on_execute: None,
+                    on_cancel: None,
+                },
+            )?;
+            Ok(())
+        }
+
+        fn seed(&mut self, seed: u64) {
+            self.rng = ChaCha8Rng::seed_from_u64(seed);
+        }
+    }
+
+    /// Buys `quantity` shares, halving the quantity every time the order is rejected.
+    #[derive(Clone)]
+    struct Resizing {
+        quantity: f32,
+        pending: Option<OrderId>,
+        rejections: Vec<BrokerError>,
+    }
+
+    impl fmt::Display for Resizing {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Resizing")
+        }
+    }
+
+    impl Strategy for Resizing {
+        fn prepare(&mut self, _broker: &mut Broker) -> Result<(), StrategyError> {
+            Ok(())
+        }
+
+        fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+            if let Some(rejection) = broker.get_last_rejection() {
+                if Some(rejection.id) == self.pending {
+                    self.rejections.push(rejection.reason.clone());
+                    self.quantity /= 2.0;
+                    self.pending = None;
+                }
+            }
+            if self.pending.is_none() {
+                self.pending = Some(broker.submit_order(Order {
+                    symbol: "AAPL".to_string(),
+                    quantity: self.quantity,
+                    side: OrderSide::Buy,
+                    order_type: OrderType::Market,
+                    datetime: ticker.datetime,
+                    execution: OrderExecutionStrategy::GTC,
+                    reduce_only: false,
+                    on_execute: None,
+                    on_cancel: None,
+                })?);
+            }
+            Ok(())
+        }
+    }
+
+    /// Records the broker's clock when it is prepared.
+    #[derive(Clone)]
+    struct Clock {
+        prepared_at: Option<DateTime<Utc>>,
+    }
+
+    impl fmt::Display for Clock {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Clock")
+        }
+    }
+
+    impl Strategy for Clock {
+        fn prepare(&mut self, broker: &mut Broker) -> Result<(), StrategyError> {
+            self.prepared_at = Some(broker.get_datetime());
+            Ok(())
+        }
+
+        fn on_ticker(&mut self, _ticker: &Ticker, _broker: &mut Broker) -> Result<(), StrategyError> {
+            Ok(())
+        }
+    }
+
+    /// Buys on the first ticker and sells on the `exit`-th, so the position
+    /// is held from the second ticker until the `exit`-th.
+    #[derive(Clone)]
+    struct RoundTrip {
+        exit: usize,
+        tickers: usize,
+    }
+
+    impl fmt::Display for RoundTrip {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Round Trip")
+        }
+    }
+
+    impl Strategy for RoundTrip {
+        fn prepare(&mut self, _broker: &mut Broker) -> Result<(), StrategyError> {
+            Ok(())
+        }
+
+        fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+            let side = match self.tickers {
+                0 => Some(OrderSide::Buy),
+                tickers if tickers == self.exit => Some(OrderSide::Sell),
+                _ => None,
+            };
+            if let Some(side) = side {
+                broker.submit_order(Order {
+                    symbol: "AAPL".to_string(),
+                    quantity: 10.0,
+                    side,
+                    order_type: OrderType::Market,
+                    datetime: ticker.datetime,
+                    execution: OrderExecutionStrategy::GTC,
+                    reduce_only: false,
+                    on_execute: None,
+                    on_cancel: None,
+                })?;
+            }
+            self.tickers += 1;
+            Ok(())
+        }
+    }
+
+    /// Keeps a single limit buy working, only submitting a new one once the last one is gone.
+    #[derive(Clone)]
+    struct WorkingOrder {
+        limit: f32,
+        id: Option<OrderId>,
+        submitted: usize,
+    }
+
+    impl fmt::Display for WorkingOrder {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Working Order")
+        }
+    }
+
+    impl Strategy for WorkingOrder {
+        fn prepare(&mut self, _broker: &mut Broker) -> Result<(), StrategyError> {
+            Ok(())
+        }
+
+        fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+            if self.id.is_some_and(|id| broker.has_active_order(id)) {
+                return Ok(());
+            }
+            let id = broker.submit_order(Order {
+                symbol: "AAPL".to_string(),
+                quantity: 1.0,
+                side: OrderSide::Buy,
+                order_type: OrderType::Limit(self.limit),
+                datetime: ticker.datetime,
+                execution: OrderExecutionStrategy::GTC,
+                reduce_only: false,
+                on_execute: None,
+                on_cancel: None,
+            })?;
+            self.id = Some(id);
+            self.submitted += 1;
+            Ok(())
+        }
+    }
+
+    fn run_coin_flip(seed: u64) -> BacktestResult {
+        let coin_flip = CoinFlip {
+            rng: ChaCha8Rng::seed_from_u64(0),
+        };
+        Backtest::new(
+            oscillating_feed(),
+            BrokerBuilder::new("Coin Flip").allow_short(true).build(),
+            Box::new(coin_flip),
+        )
+        .with_seed(seed)
+        .run()
+        .unwrap()
+    }
+
+    #[test]
+    fn seeded_runs_are_reproducible() {
+        let first = run_coin_flip(42);
+        let second = run_coin_flip(42);
+        assert_eq!(first.get_broker().get_equity(), second.get_broker().get_equity());
+        assert_eq!(first.get_equity_curve(), second.get_equity_curve());
+
+        let other = run_coin_flip(7);
+        assert_ne!(first.get_equity_curve(), other.get_equity_curve());
+    }
+
+    #[test]
+    fn strategy_state_after_run() {
+        let strategy = RoundTrip { exit: 60, tickers: 0 };
+        let broker = Broker::new("Round Trip", 100_000.0, 0.0, 1.0, false, false);
+        let result = Backtest::new(oscillating_feed(), broker, Box::new(strategy)).run().unwrap();
+        assert_eq!(result.get_strategy_as::<RoundTrip>().unwrap().tickers, 120);
+        assert!(result.get_strategy_as::<BuyAndHold>().is_none());
+        assert_eq!(result.get_strategy().to_string(), "Round Trip");
+    }
+
+    #[test]
+    fn strategy_avoids_duplicate_orders() {
+        let run = |limit| {
+            let strategy = WorkingOrder { limit, id: None, submitted: 0 };
+            let broker = Broker::new("Working Order", 100_000.0, 0.0, 1.0, false, false);
+            Backtest::new(oscillating_feed(), broker, Box::new(strategy)).run().unwrap()
+        };
+
+        // The limit is never reached, so the first order stays active for the whole run.
+        let result = run(50.0);
+        assert_eq!(result.get_strategy_as::<WorkingOrder>().unwrap().submitted, 1);
+        let active = result.get_broker().get_active_orders().collect::<Vec<_>>();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].1.order_type.to_string(), OrderType::Limit(50.0).to_string());
+
+        // A limit above every close fills on the next ticker, before the strategy sees it,
+        // so a new order is submitted on every ticker.
+        let result = run(200.0);
+        assert_eq!(result.get_strategy_as::<WorkingOrder>().unwrap().submitted, 120);
+        assert_eq!(result.get_broker().get_trades().len(), 119);
+    }
+
+    #[test]
+    fn unfilled_orders_at_end_of_run() {
+        let run = |policy| {
+            let strategy = WorkingOrder { limit: 50.0, id: None, submitted: 0 };
+            let broker = Broker::new("Unfilled", 100_000.0, 0.0, 1.0, false, false);
+            Backtest::new(oscillating_feed(), broker, Box::new(strategy))
+                .with_unfilled_orders(policy)
+                .run()
+                .unwrap()
+        };
+
+        let left = run(UnfilledOrders::Leave);
+        let unfilled = left.get_unfilled_orders();
+        assert_eq!(unfilled.len(), 1);
+        assert_eq!(unfilled[0].0, 0);
+        assert_eq!(unfilled[0].1.order_type.to_string(), OrderType::Limit(50.0).to_string());
+        assert!(left.get_broker().has_active_order(0));
+        assert!(left.to_string().contains("Unfilled Orders: 1"));
+
+        let canceled = run(UnfilledOrders::Cancel);
+        assert_eq!(canceled.get_unfilled_orders().len(), 1);
+        assert_eq!(canceled.get_broker().get_active_orders().count(), 0);
+    }
+
+    #[test]
+    fn build_missing_components() {
+        let feed = || TimeSeries::from_vec(Vec::new());
+        let broker = || Broker::new("Builder", 100_000.0, 0.0, 1.0, false, false);
+        let strategy = || Box::new(BuyAndHold::default()) as Box<dyn Strategy>;
+
+        let missing_feed = BacktestBuilder::new().add_broker(broker()).add_strategy(strategy()).build();
+        assert!(matches!(missing_feed, Err(BuildError::MissingFeed)));
+        let missing_broker = BacktestBuilder::new().add_feed(feed()).add_strategy(strategy()).build();
+        assert!(matches!(missing_broker, Err(BuildError::MissingBroker)));
+        let missing_strategy = BacktestBuilder::new().add_feed(feed()).add_broker(broker()).build();
+        assert!(matches!(missing_strategy, Err(BuildError::MissingStrategy)));
+
+        let complete = BacktestBuilder::new()
+            .add_feeds(vec![feed(), feed()])
+            .add_broker(broker())
+            .add_strategy(strategy())
+            .build();
+        assert_eq!(complete.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn results_carry_strategy_and_broker_names() {
+        let backtests = BacktestBuilder::new()
+            .add_feed(oscillating_feed())
+            .add_broker(Broker::idealized("Idealized", 100_000.0))
+            .add_broker(Broker::realistic("Realistic", 100_000.0))
+            .add_strategy(Box::new(SMACrossover::new(5)))
+            .add_strategy(Box::new(BuyAndHold::default()))
+            .build()
+            .unwrap();
+        let mut names = backtests
+            .into_iter()
+            .map(|backtest| {
+                let result = backtest.run().unwrap();
+                assert_eq!(result.get_broker_name(), result.get_broker().get_name());
+                (result.get_strategy_name().to_string(), result.get_broker_name().to_string())
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        let sma = SMACrossover::new(5).to_string();
+        let buy_and_hold = BuyAndHold::default().to_string();
+        assert_eq!(
+            names,
+            vec![
+                (buy_and_hold.clone(), "Idealized".to_string()),
+                (buy_and_hold, "Realistic".to_string()),
+                (sma.clone(), "Idealized".to_string()),
+                (sma, "Realistic".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn run_missing_feed() {
+        let backtest = Backtest::new(
+            TimeSeries::from_csv("./does/not/exist.csv"),
+            Broker::new("Missing Feed", 100_000.0, 0.0, 1.0, false, false),
+            Box::new(BuyAndHold::default()),
+        );
+        assert!(matches!(backtest.run(), Err(BacktestError::FeedError(_))));
+    }
+
+    #[test]
+    fn run_from_vec() {
+        let tickers = (0..3)
+            .map(|day| Ticker {
+                open: 100.0,
+                high: 101.0,
+                low: 99.0,
+                close: 100.0 + day as f32,
+                volume: 1000,
+                datetime: Utc.timestamp_opt(day * 86_400, 0).unwrap(),
+            })
+            .collect();
+        let backtest = Backtest::new(
+            TimeSeries::from_vec(tickers),
+            Broker::new("In Memory", 100_000.0, 0.0, 1.0, false, false),
+            Box::new(BuyAndHold::default()),
+        );
+        let result = backtest.run().unwrap();
+        let position = result.broker.get_position("AAPL").unwrap();
+        assert_eq!(position.amount, 100.0);
+        assert_eq!(position.price, 101.0);
+    }
+
+    #[test]
+    fn broker_clock_starts_at_first_ticker() {
+        let first = Utc.with_ymd_and_hms(2020, 3, 2, 14, 30, 0).unwrap();
+        let tickers = vec![Ticker {
+            open: 100.0,
+            high: 100.0,
+            low: 100.0,
+            close: 100.0,
+            volume: 1000,
+            datetime: first,
+        }];
+        let run = |broker| {
+            let backtest = Backtest::new(TimeSeries::from_vec(tickers.clone()), broker, Box::new(Clock { prepared_at: None }));
+            backtest.run().unwrap().get_strategy_as::<Clock>().unwrap().prepared_at
+        };
+        assert_eq!(run(BrokerBuilder::new("Clock").build()), Some(first));
+
+        let start = Utc.with_ymd_and_hms(2020, 3, 1, 0, 0, 0).unwrap();
+        assert_eq!(run(BrokerBuilder::new("Clock").start_datetime(start).build()), Some(start));
+        assert_eq!(BrokerBuilder::new("Clock").build().get_datetime(), DateTime::UNIX_EPOCH);
+    }
+
+    #[test]
+    fn observer_sees_every_ticker() {
+        let backtest = Backtest::new(
+            oscillating_feed(),
+            BrokerBuilder::new("Observed").build(),
+            Box::new(RoundTrip { exit: 60, tickers: 0 }),
+        );
+        let mut observed = Vec::new();
+        let result = backtest
+            .run_with_observer(|ticker, broker| observed.push((ticker.datetime, broker.get_equity())))
+            .unwrap();
+        assert_eq!(observed.len(), 120);
+        assert_eq!(observed, result.get_equity_curve());
+    }
+
+    #[test]
+    fn strategy_resizes_rejected_order() {
+        let feed = || {
+            TimeSeries::from_vec(
+                (0..5)
+                    .map(|day| Ticker {
+                        open: 100.0,
+                        high: 100.0,
+                        low: 100.0,
+                        close: 100.0,
+                        volume: 1000,
+                        datetime: Utc.timestamp_opt(day * 86_400, 0).unwrap(),
+                    })
+                    .collect(),
+            )
+        };
+        let strategy = || Box::new(Resizing { quantity: 1500.0, pending: None, rejections: Vec::new() });
+
+        // By default, the rejection ends the backtest.
+        let aborted = Backtest::new(feed(), BrokerBuilder::new("Aborting").build(), strategy()).run();
+        assert!(matches!(aborted, Err(BacktestError::BrokerError(BrokerError::InsufficientMargin))));
+
+        let broker = BrokerBuilder::new("Resizing").abort_on_rejection(false).build();
+        let result = Backtest::new(feed(), broker, strategy()).run().unwrap();
+        let strategy = result.get_strategy_as::<Resizing>().unwrap();
+        assert_eq!(strategy.rejections, vec![BrokerError::InsufficientMargin]);
+        let trades = result.get_broker().get_trades();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity, 750.0);
+    }
+
+    fn oscillating_feed() -> TimeSeries {
+        TimeSeries::from_vec(
+            (0..120)
+                .map(|day| {
+                    let close = 100.0 + 5.0 * (day as f32 / 5.0).sin();
+                    Ticker {
+                        open: close,
+                        high: close,
+                        low: close,
+                        close,
+                        volume: 1000,
+                        datetime: Utc.timestamp_opt(day * 86_400, 0).unwrap(),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    fn run_sma_crossover(broker: Broker) -> BacktestResult {
+        Backtest::new(oscillating_feed(), broker, Box::new(SMACrossover::new(5)))
+            .run()
+            .unwrap()
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() <= 1e-2, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn reprice_matches_rerun() {
+        let result = run_sma_crossover(Broker::new("Free", 100_000.0, 0.0, 1.0, false, false));
+        assert!(result.get_broker().get_trades().len() > 2);
+
+        let repriced = result.reprice(CommissionModel::Percentage(0.01), 0.002);
+        let rerun = run_sma_crossover(
+            BrokerBuilder::new("Costly").commission(0.01).slippage(0.002).build(),
+        )
+        .summary();
+        assert!(repriced.total_commission > 0.0);
+        assert_close(repriced.total_commission, rerun.total_commission);
+        assert_close(repriced.cash, rerun.cash);
+        assert_close(repriced.equity, rerun.equity);
+        assert!(repriced.total_return < result.summary().total_return);
+    }
+
+    #[test]
+    fn spread_adds_to_executed_slippage() {
+        let result = run_sma_crossover(BrokerBuilder::new("Slippage").slippage(0.002).build());
+        let sweep = result.sensitivity(CostParameter::Spread, &[0.0, 0.004]);
+        assert_close(sweep[0].1.equity, result.summary().equity);
+        assert_close(sweep[1].1.equity, result.reprice(CommissionModel::Percentage(0.0), 0.004).equity);
+    }
+
+    #[test]
+    fn commission_sensitivity() {
+        let result = run_sma_crossover(Broker::new("Free", 100_000.0, 0.0, 1.0, false, false));
+        let sweep = result.sensitivity(CostParameter::Commission, &[0.0, 0.001, 0.005, 0.01]);
+        assert_eq!(sweep.iter().map(|(value, _)| *value).collect::<Vec<_>>(), vec![0.0, 0.001, 0.005, 0.01]);
+        assert_close(sweep[0].1.total_return, result.summary().total_return);
+        assert!(sweep.windows(2).all(|pair| pair[1].1.total_return < pair[0].1.total_return));
+    }
+
+    #[test]
+    fn realistic_preset_is_costlier_than_idealized() {
+        let idealized = run_sma_crossover(Broker::idealized("Idealized", 100_000.0));
+        let realistic = run_sma_crossover(Broker::realistic("Realistic", 100_000.0));
+        assert!(realistic.get_broker().get_trades().len() > 2);
+        assert!(realistic.get_broker().get_equity() < idealized.get_broker().get_equity());
+    }
+
+    #[test]
+    fn reprice_reproduces_run_with_ticks_fx_and_dividends() {
+        let commission = CommissionModel::MakerTaker { maker: -0.001, taker: 0.002 };
+        let mut broker = BrokerBuilder::new("Frictions")
+            .commission_model(commission.clone())
+            .slippage(0.002)
+            .tick_size(0.05)
+            .fx_rate("AAPL", 1.1)
+            .build();
+        let dates = (1..120).map(|day| Utc.timestamp_opt(day * 86_400, 0).unwrap().date_naive());
+        broker.add_corporate_actions(
+            dates
+                .map(|date| CorporateAction::Dividend { symbol: "AAPL".to_string(), date, per_share: 0.1 })
+                .collect(),
+        );
+        let result = run_sma_crossover(broker);
+        let broker = result.get_broker();
+        let traded = broker
+            .get_trades()
+            .iter()
+            .map(|trade| match trade.side {
+                OrderSide::Buy => -(trade.quantity * trade.price + trade.commission) * trade.fx_rate,
+                OrderSide::Sell => (trade.quantity * trade.price - trade.commission) * trade.fx_rate,
+            })
+            .sum::<f32>();
+        assert!(broker.get_cash() - broker.get_initial_cash() - traded > 1.0, "no dividends were paid");
+
+        let summary = result.summary();
+        let repriced = result.reprice(commission, 0.002);
+        assert_close(repriced.total_commission, summary.total_commission);
+        assert_close(repriced.cash, summary.cash);
+        assert_close(repriced.equity, summary.equity);
+    }
+
+    #[test]
+    fn reprice_at_original_costs() {
+        let result = run_sma_crossover(
+            BrokerBuilder::new("Costly").commission(0.01).slippage(0.002).build(),
+        );
+        let summary = result.summary();
+        let repriced = result.reprice(CommissionModel::Percentage(0.01), 0.002);
+        assert_close(repriced.cash, summary.cash);
+        assert_close(repriced.equity, summary.equity);
+    }
+
+    #[test]
+    fn volatility_and_beta() {
+        let result = run_sma_crossover(Broker::new("Free", 100_000.0, 0.0, 1.0, false, false));
+        let returns = metrics::returns(&result.equity());
+        assert_eq!(result.annualized_volatility(252.0), metrics::std_dev(&returns) * 252.0_f32.sqrt());
+        assert!(result.annualized_volatility(252.0) > 0.0);
+
+        assert!((result.beta(result.get_equity_curve()) - 1.0).abs() < 1e-4);
+        // A curve that is scaled by a constant has the same returns.
+        let scaled = result
+            .get_equity_curve()
+            .iter()
+            .map(|(datetime, equity)| (*datetime, 2.0 * equity))
+            .collect::<Vec<_>>();
+        assert!((result.beta(&scaled) - 1.0).abs() < 1e-4);
+        // Timestamps outside of the run are ignored, and a flat benchmark has no variance.
+        let flat = vec![(Utc.timestamp_opt(-86_400, 0).unwrap(), 1.0), (result.get_equity_curve()[0].0, 1.0)];
+        assert_eq!(result.beta(&flat), 0.0);
+    }
+
+    #[test]
+    fn exposure_of_half_the_run() {
+        // Orders fill on the next ticker, so the position is held after tickers 1 to 60 of 120.
+        let strategy = RoundTrip { exit: 60, tickers: 0 };
+        let broker = Broker::new("Half Time", 100_000.0, 0.0, 1.0, false, false);
+        let result = Backtest::new(oscillating_feed(), broker, Box::new(strategy)).run().unwrap();
+        assert_eq!(result.get_broker().get_trades().len(), 2);
+        assert!(!result.get_broker().has_open_positions());
+        assert!((result.exposure() - 0.5).abs() < 1e-6);
+
+        let idle = RoundTrip { exit: 0, tickers: 1 };
+        let broker = Broker::new("Idle", 100_000.0, 0.0, 1.0, false, false);
+        let result = Backtest::new(oscillating_feed(), broker, Box::new(idle)).run().unwrap();
+        assert_eq!(result.exposure(), 0.0);
+    }
+
+    /// A result with a daily equity curve of `equity`, as if it had been run.
+    fn synthetic_result(equity: &[f32]) -> BacktestResult {
+        BacktestResult {
+            feed_path: OsString::from("<memory>"),
+            strategy_name: BuyAndHold::default().to_string(),
+            broker_name: String::from("Synthetic"),
+            equity_curve: equity
+                .iter()
+                .enumerate()
+                .map(|(day, equity)| (Utc.timestamp_opt(day as i64 * 86_400, 0).unwrap(), *equity))
+                .collect(),
+            in_market: vec![true; equity.len()],
+            unfilled_orders: Vec::new(),
+            broker: Broker::new("Synthetic", equity[0], 0.0, 1.0, false, false),
+            strategy: Box::new(BuyAndHold::default()),
+            runtime: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn rolling_metrics() {
+        let result = synthetic_result(&[100.0, 110.0, 99.0, 99.0, 121.0]);
+        assert_eq!(result.periods_per_year(), 252.0);
+
+        let returns = result.rolling_return(3);
+        assert_eq!(returns.len(), 3);
+        // The window ending on day 4 runs from 99 to 121.
+        assert_eq!(returns[2].0, Utc.timestamp_opt(4 * 86_400, 0).unwrap());
+        assert!((returns[2].1 - 22.0 / 99.0).abs() < 1e-6);
+        assert!((returns[0].1 + 0.01).abs() < 1e-6);
+
+        let sharpe = result.rolling_sharpe(3, 252.0);
+        assert_eq!(sharpe.len(), 3);
+        assert_eq!(sharpe[1].1, metrics::sharpe_ratio(&[-0.1, 0.0], 252.0));
+        assert_eq!(result.rolling_return(5).len(), 1);
+        assert!(result.rolling_return(6).is_empty());
+        assert!(result.rolling_sharpe(0, 252.0).is_empty());
+    }
+
+    #[test]
+    fn time_and_money_weighted_returns() {
+        // The account doubles, then receives a deposit of 200, and then halves.
+        let mut result = synthetic_result(&[100.0, 200.0, 400.0, 200.0]);
+        let datetime = result.get_equity_curve()[2].0;
+        let ticker = Ticker { open: 1.0, high: 1.0, low: 1.0, close: 1.0, volume: 100, datetime };
+        result.broker.next(&ticker).unwrap();
+        result.broker.deposit(200.0);
+        assert_eq!(result.broker.get_cash_flows()[0].datetime, datetime);
+
+        assert!(result.twr().abs() < 1e-6);
+        // 100 * (1 + r) + 200 * (1 + r)^(1/3) = 200, with x = (1 + r)^(1/3) = 0.7709.
+        let x: f32 = 0.770_917;
+        assert!((result.mwr() - (x.powi(3) - 1.0)).abs() < 1e-4);
+        assert!(result.mwr() < result.twr());
+
+        // Without cash flows, both match the total return.
+        let result = synthetic_result(&[100.0, 110.0, 121.0]);
+        assert!((result.twr() - 0.21).abs() < 1e-6);
+        assert!((result.mwr() - 0.21).abs() < 1e-5);
+        assert!((result.total_return() - 0.21).abs() < 1e-6);
+    }
+
+    #[test]
+    fn drawdown_curve() {
+        let result = synthetic_result(&[100.0, 120.0, 90.0, 108.0, 130.0, 117.0]);
+        let drawdowns = result.drawdown_curve().into_iter().map(|(_, drawdown)| drawdown).collect::<Vec<_>>();
+        let expected = [0.0, 0.0, -0.25, -0.1, 0.0, -0.1];
+        for (drawdown, expected) in drawdowns.iter().zip(expected) {
+            assert!((drawdown - expected).abs() < 1e-6, "{} != {}", drawdown, expected);
+        }
+        assert_eq!(drawdowns.len(), expected.len());
+        assert_eq!(result.drawdown_curve()[2].0, result.get_equity_curve()[2].0);
+        assert_eq!(result.max_drawdown(), drawdowns[2]);
+    }
+
+    #[test]
+    fn run_malformed_row() {
+        let path = std::env::temp_dir().join(format!("backtester_malformed_row_{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "open,close,high,low,volume,datetime\n\
+             9.83,9.82,9.83,9.82,26,1654781400\n\
+             9.83,not_a_price,9.84,9.82,1997,1654867800\n\
+             9.84,9.83,9.84,9.82,100,1654954200\n",
+        )
+        .unwrap();
+        let backtest = Backtest::new(
+            TimeSeries::from_csv(&path),
+            Broker::new("Malformed Feed", 100_000.0, 0.0, 1.0, false, false),
+            Box::new(BuyAndHold::default()),
+        );
+        match backtest.run() {
+            Err(BacktestError::TickerParseError(line, _)) => assert_eq!(line, 3),
+            _ => panic!("Expected a TickerParseError"),
+        }
+    }
+}
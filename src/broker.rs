@@ -1,25 +1,179 @@
 //! The main entity that a strategy interacts with throughout the core event loop.
+use crate::calendar::TradingCalendar;
+use crate::indicators::Indicator;
 use crate::types::*;
+use crate::util::serde_ext::yyyy_mm_dd_hh_mm_ss;
+use dyn_clone::DynClone;
 
 use serde_derive::{Deserialize, Serialize};
 
 use log::info;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
 use std::fmt;
-use chrono::{DateTime, Duration, Utc, Date};
+use std::path::Path;
+use chrono::{DateTime, Duration, NaiveDate, Utc, Date};
 
 type Symbol = String;
+type TradeCallback = Box<dyn FnMut(&Trade)>;
+type EventCallback = Box<dyn FnMut(&BrokerEvent)>;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BrokerError {
     InsufficientFundsForPurchase,
     OutOfMoneyError,
     InsufficientMargin,
     OrderIdNotFound,
+    /// An order with the same id is already active. Cancel it before reusing its id.
+    DuplicateOrderId,
+    /// The order would breach the broker's `max_position_fraction` or `max_open_positions`.
+    RiskLimitExceeded,
+    /// Trading is halted until the next day because the `daily_loss_limit` was hit.
+    DailyLossLimit,
+    /// The sell exceeds the current long position and short selling is disabled.
+    ShortNotAllowed,
+    /// The order is for less than one share and fractional shares are disabled.
+    FractionalQuantity,
+    /// The ticker has a NaN price, against which no order could ever trigger.
+    /// The ticker is skipped and the broker's state is left untouched.
+    InvalidPrice,
+    /// The order would open or add to a position within the broker's `cooldown` of the
+    /// last trade in its symbol.
+    CooldownActive,
+    /// The order is for a symbol that the broker is not fed, so it has no price to fill at.
+    UnknownSymbol,
 }
 
 pub type BrokerResult<T> = Result<T, BrokerError>;
 
+/// How the commission of a trade is charged.
+/// Negative values represent rebates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CommissionModel {
+    /// A fraction of the traded value.
+    Percentage(f32),
+    /// A flat amount for every share traded.
+    PerShare(f32),
+    /// A flat amount for every trade, regardless of its size.
+    Fixed(f32),
+    /// The sum of a flat fee, a per-share fee, and a fraction of the traded value.
+    Combined { fixed: f32, per_share: f32, pct: f32 },
+    /// A fraction of the traded value that depends on whether the order
+    /// [made or took](https://www.investopedia.com/terms/m/maker-taker.asp) liquidity:
+    /// `maker` for limit orders that rested in the order book, and `taker` for all others.
+    /// Exchanges usually pay a rebate (a negative `maker`) for resting orders.
+    MakerTaker { maker: f32, taker: f32 },
+}
+
+impl CommissionModel {
+    /// Returns the commission charged for trading `quantity` shares at `price`.
+    /// Under `MakerTaker`, the trade is assumed to take liquidity.
+    pub fn commission(&self, quantity: f32, price: f32) -> f32 {
+        self.commission_for(quantity, price, false)
+    }
+
+    /// Returns the commission charged for trading `quantity` shares at `price` by an order
+    /// that made liquidity if `maker` is `true`, or took it otherwise.
+    pub fn commission_for(&self, quantity: f32, price: f32, maker: bool) -> f32 {
+        match self {
+            CommissionModel::Percentage(pct) => (quantity * price).abs() * pct,
+            CommissionModel::PerShare(per_share) => quantity.abs() * per_share,
+            CommissionModel::Fixed(fixed) => *fixed,
+            CommissionModel::Combined { fixed, per_share, pct } => {
+                fixed + quantity.abs() * per_share + (quantity * price).abs() * pct
+            }
+            CommissionModel::MakerTaker { maker: rate, .. } if maker => (quantity * price).abs() * rate,
+            CommissionModel::MakerTaker { taker, .. } => (quantity * price).abs() * taker,
+        }
+    }
+}
+
+/// How the cost basis of closed shares is determined, which decides the realized profit.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Accounting {
+    /// Nets every fill into a single position at its weighted-average entry price. This is the default.
+    Averaged,
+    /// Keeps every fill as a separate `Lot` and closes lots in the given order.
+    Lots(LotMatching),
+}
+
+/// The order in which lots are closed under `Accounting::Lots`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LotMatching {
+    /// First in, first out: the oldest lot is closed first.
+    Fifo,
+    /// Last in, first out: the newest lot is closed first.
+    Lifo,
+}
+
+/// The quantity and price at which an order fills. See `Broker::fill_for`.
+struct Fill {
+    quantity: f32,
+    /// The price given by the fill model, before slippage and tick rounding.
+    market_price: f32,
+    /// The executed price, in the currency of the order's symbol.
+    price: f32,
+    commission: f32,
+    maker: bool,
+    /// The FX rate of the order's symbol. See `BrokerBuilder::fx_rate`.
+    fx_rate: f32,
+    /// The rounded limit price of the order, which `price` does not cross.
+    limit: Option<f32>,
+}
+
+/// Decides the market price at which an order fills within a ticker, before slippage.
+pub trait FillModel: DynClone {
+    fn fill_price(&self, order: &Order, ticker: &Ticker) -> f32;
+}
+
+dyn_clone::clone_trait_object!(FillModel);
+
+/// Fills at the close of the ticker. This is the default.
+#[derive(Debug, Clone, Copy)]
+pub struct CloseFill;
+
+impl FillModel for CloseFill {
+    fn fill_price(&self, _order: &Order, ticker: &Ticker) -> f32 {
+        ticker.close
+    }
+}
+
+/// Fills at the open of the ticker.
+#[derive(Debug, Clone, Copy)]
+pub struct OpenFill;
+
+impl FillModel for OpenFill {
+    fn fill_price(&self, _order: &Order, ticker: &Ticker) -> f32 {
+        ticker.open
+    }
+}
+
+/// Fills halfway between the high and the low of the ticker.
+#[derive(Debug, Clone, Copy)]
+pub struct MidpointFill;
+
+impl FillModel for MidpointFill {
+    fn fill_price(&self, _order: &Order, ticker: &Ticker) -> f32 {
+        ticker.median_price()
+    }
+}
+
+/// Fills market orders at the average of the ticker's open, high, low and close, a rough
+/// stand-in for the bar's VWAP that suits orders too large to fill at a single price.
+/// Other orders fill at the close.
+#[derive(Debug, Clone, Copy)]
+pub struct OhlcAverageFill;
+
+impl FillModel for OhlcAverageFill {
+    fn fill_price(&self, order: &Order, ticker: &Ticker) -> f32 {
+        match order.order_type {
+            OrderType::Market => ticker.ohlc_average(),
+            _ => ticker.close,
+        }
+    }
+}
+
 /// The Broker is responsible for maintaining bookkeeping of all `active_orders` placed,
 /// providing the strategy with information about the current state of the market,
 /// and managing the strategy's portfolio.
@@ -33,19 +187,59 @@ pub type BrokerResult<T> = Result<T, BrokerError>;
 pub struct Broker {
     name: String,
     initial_cash: f32,
-    commission: f32,
+    commission: CommissionModel,
+    slippage: f32,
     leverage: f32,
     exclusive_orders: bool,
     hedging: bool,
+    maintenance_margin: f32,
+    borrow_rate: f32,
+    margin_rate: f32,
+    margin_rate_indicator: Option<Box<dyn Indicator<Result = f32>>>,
+    fx_rates: HashMap<Symbol, f32>,
+    fx_rate_indicators: HashMap<Symbol, Box<dyn Indicator<Result = f32>>>,
+    max_position_fraction: Option<f32>,
+    max_open_positions: Option<usize>,
+    daily_loss_limit: Option<f32>,
+    max_volume_participation: Option<f32>,
+    tick_size: Option<f32>,
+    cooldown: Option<Duration>,
+    allow_short: bool,
+    allow_fractional: bool,
+    fill_model: Box<dyn FillModel>,
+    accounting: Accounting,
+    calendar: Option<Box<dyn TradingCalendar>>,
+    start_datetime: Option<DateTime<Utc>>,
+    abort_on_rejection: bool,
+    logging: bool,
     datetime: DateTime<Utc>,
 
     /// Internal bookkeeping
-    active_orders: HashMap<OrderId, Order>,
+    next_order_id: OrderId,
+    trading_day: Option<NaiveDate>,
+    day_start_equity: f32, // The equity at the close of the previous trading day
+    halted: bool, // Whether the daily loss limit was hit
+    realized_pnl: f32, // The profit of every closed share, before costs and FX moves
+    last_rejection: Option<Rejection>,
+    on_trade: Option<Rc<RefCell<TradeCallback>>>,
+    on_event: Option<Rc<RefCell<EventCallback>>>,
+
+    active_orders: BTreeMap<OrderId, Order>, // Ordered by id, i.e. by submission
     canceled_orders: HashMap<OrderId, Order>, // Keeps track of all the orders that were cancelled.
-    trades: HashMap<OrderId, Order>, // Keeps track of all the trades that were executed (orders that were filled)
+    trailing_marks: BTreeMap<OrderId, f32>, // The best close since submission of every trailing stop
+    trades: Vec<Trade>, // Keeps track of all the trades that were executed (orders that were filled), in order
+    last_trades: HashMap<Symbol, DateTime<Utc>>, // The datetime of the latest trade in every symbol, for the cooldown
+    cash_flows: Vec<CashFlow>, // Deposits and withdrawals, in order
     current_cash: f32,
     positions: HashMap<Symbol, Position>, // Keeps track of all the active positions
-    previous_ticker: Option<Ticker>
+    lots: HashMap<Symbol, Vec<Lot>>, // The open lots of every position, oldest first. Only kept under `Accounting::Lots`
+    allocations: HashMap<usize, Allocation>,
+    active_allocation: Option<usize>, // The allocation that orders are submitted against
+    order_allocations: HashMap<OrderId, usize>, // The allocation slot that each active order was submitted against
+    previous_ticker: Option<Ticker>,
+    prices: HashMap<Symbol, Ticker>, // The latest ticker of every symbol, when fed per symbol with `next_symbol`
+    symbols: Option<Vec<Symbol>>, // The symbols that the broker is fed, if known
+    corporate_actions: Vec<CorporateAction>, // Pending corporate actions, latest first
 }
 
 impl fmt::Display for Broker {
@@ -53,7 +247,8 @@ impl fmt::Display for Broker {
         let mut result = String::new();
         result.push_str(&format!("{}(\n", self.name));
         result.push_str(&format!("Initial Cash: {}\n", self.initial_cash));
-        result.push_str(&format!("Commission: {}\n", self.commission));
+        result.push_str(&format!("Commission: {:?}\n", self.commission));
+        result.push_str(&format!("Slippage: {}\n", self.slippage));
         result.push_str(&format!("Leverage: {}\n", self.leverage));
         result.push_str(&format!("Exclusive Orders: {}\n", self.exclusive_orders));
         result.push_str(&format!("Hedging: {}\n", self.hedging));
@@ -64,15 +259,574 @@ impl fmt::Display for Broker {
     }
 }
 
+/// The order and position updates that a `Broker` logs (with the `info` level and the
+/// `backtester::broker` target) when logging is enabled.
+///
+/// Each event is logged on a single line as its name followed by `key=value` fields, e.g.
+/// `OrderExecuted id=0 symbol=AAPL side=Buy quantity=100 price=101.5 commission=0 datetime=2023-01-03T00:00:00+00:00`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BrokerEvent {
+    OrderSubmitted {
+        id: OrderId,
+        symbol: Symbol,
+        side: OrderSide,
+        quantity: f32,
+        datetime: DateTime<Utc>,
+    },
+    OrderExecuted {
+        id: OrderId,
+        symbol: Symbol,
+        side: OrderSide,
+        quantity: f32,
+        price: f32,
+        commission: f32,
+        datetime: DateTime<Utc>,
+    },
+    OrderCancelled {
+        id: OrderId,
+        symbol: Symbol,
+        side: OrderSide,
+        quantity: f32,
+        datetime: DateTime<Utc>,
+    },
+    /// The order with `id` was swapped for a new one. See `Broker::replace_order`.
+    OrderReplaced {
+        id: OrderId,
+        symbol: Symbol,
+        side: OrderSide,
+        quantity: f32,
+        datetime: DateTime<Utc>,
+    },
+    /// The order with `id` was dropped from the order book because it failed a check when it
+    /// came to execute. See `BrokerBuilder::abort_on_rejection`.
+    OrderRejected {
+        id: OrderId,
+        symbol: Symbol,
+        side: OrderSide,
+        quantity: f32,
+        reason: BrokerError,
+        datetime: DateTime<Utc>,
+    },
+    /// The position in `symbol` after a fill. `amount` is `0` once the position is closed.
+    PositionChanged {
+        symbol: Symbol,
+        amount: f32,
+        price: f32,
+        datetime: DateTime<Utc>,
+    },
+}
+
+impl fmt::Display for BrokerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrokerEvent::OrderSubmitted { id, symbol, side, quantity, datetime } => write!(
+                f,
+                "OrderSubmitted id={} symbol={} side={} quantity={} datetime={}",
+                id, symbol, side, quantity, datetime.to_rfc3339()
+            ),
+            BrokerEvent::OrderExecuted { id, symbol, side, quantity, price, commission, datetime } => write!(
+                f,
+                "OrderExecuted id={} symbol={} side={} quantity={} price={} commission={} datetime={}",
+                id, symbol, side, quantity, price, commission, datetime.to_rfc3339()
+            ),
+            BrokerEvent::OrderCancelled { id, symbol, side, quantity, datetime } => write!(
+                f,
+                "OrderCancelled id={} symbol={} side={} quantity={} datetime={}",
+                id, symbol, side, quantity, datetime.to_rfc3339()
+            ),
+            BrokerEvent::OrderReplaced { id, symbol, side, quantity, datetime } => write!(
+                f,
+                "OrderReplaced id={} symbol={} side={} quantity={} datetime={}",
+                id, symbol, side, quantity, datetime.to_rfc3339()
+            ),
+            BrokerEvent::OrderRejected { id, symbol, side, quantity, reason, datetime } => write!(
+                f,
+                "OrderRejected id={} symbol={} side={} quantity={} reason={:?} datetime={}",
+                id, symbol, side, quantity, reason, datetime.to_rfc3339()
+            ),
+            BrokerEvent::PositionChanged { symbol, amount, price, datetime } => write!(
+                f,
+                "PositionChanged symbol={} amount={} price={} datetime={}",
+                symbol, amount, price, datetime.to_rfc3339()
+            ),
+        }
+    }
+}
+
+/// An order that the broker rejected when it came to execute. See `Broker::get_last_rejection`.
+#[derive(Clone)]
+pub struct Rejection {
+    pub id: OrderId,
+    pub order: Order,
+    pub reason: BrokerError,
+    pub datetime: DateTime<Utc>,
+}
+
+/// A slice of the account's equity reserved for one strategy of a `PortfolioStrategy`.
+#[derive(Debug, Clone)]
+struct Allocation {
+    weight: f32,  // The fraction of equity that may be held, long or short
+    amounts: HashMap<Symbol, f32>, // The net shares bought or sold through the allocation, per symbol
+}
+
+/// The state of a `Broker`'s account at a point in time. See `Broker::snapshot`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountSnapshot {
+    #[serde(with = "yyyy_mm_dd_hh_mm_ss")]
+    pub datetime: DateTime<Utc>,
+    pub cash: f32,
+    /// Cash plus every position marked at the latest close.
+    pub equity: f32,
+    /// The profit of every share that was closed, before commission and financing.
+    /// For a symbol quoted in a foreign currency, the profit is taken in that currency and
+    /// converted at the rate of the closing fill, so it leaves out gains and losses on the
+    /// rate itself while the position was open. Those show in `cash` and `equity` only.
+    pub realized_pnl: f32,
+    /// The profit that closing every open position at the latest close would realize.
+    pub unrealized_pnl: f32,
+    /// The open positions, ordered by symbol.
+    pub positions: Vec<Position>,
+    /// The number of orders that have neither been executed nor cancelled.
+    pub active_orders: usize,
+}
+
+/// The serializable state of a `Broker`'s account, to checkpoint a long run and resume it later.
+/// See `Broker::get_state` and `Broker::with_state`.
+///
+/// Only the account is captured, not the broker's configuration (commission, fill model,
+/// calendar, ...), allocations or pending corporate actions. The callbacks of orders cannot
+/// be serialized either, so restored orders have none.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BrokerState {
+    pub name: String,
+    pub initial_cash: f32,
+    #[serde(with = "yyyy_mm_dd_hh_mm_ss")]
+    pub datetime: DateTime<Utc>,
+    pub cash: f32,
+    pub realized_pnl: f32,
+    /// The equity at the start of the current trading day, for the daily loss limit.
+    pub day_start_equity: f32,
+    pub halted: bool,
+    pub next_order_id: OrderId,
+    /// The open positions, ordered by symbol.
+    pub positions: Vec<Position>,
+    /// The open lots of every position, ordered by symbol.
+    pub lots: Vec<(Symbol, Vec<Lot>)>,
+    /// Ordered by id.
+    pub active_orders: Vec<(OrderId, Order)>,
+    /// Ordered by id.
+    pub canceled_orders: Vec<(OrderId, Order)>,
+    /// The high- or low-water mark of every active trailing stop, ordered by id.
+    pub trailing_marks: Vec<(OrderId, f32)>,
+    pub trades: Vec<Trade>,
+    pub cash_flows: Vec<CashFlow>,
+    pub previous_ticker: Option<Ticker>,
+    /// The latest ticker of every symbol fed with `next_symbol`, ordered by symbol.
+    pub prices: Vec<(Symbol, Ticker)>,
+}
+
+/// Constructs a `Broker` with named parameters.
+///
+/// Defaults to $100,000 of initial cash, no commission or slippage, a margin of `1.0`
+/// (no leverage), no exclusive orders, no hedging, no short selling, and logging enabled.
+///
+/// ```
+/// use backtester::prelude::*;
+///
+/// let broker = BrokerBuilder::new("Simple Backtest")
+///     .initial_cash(50_000.0)
+///     .commission_model(CommissionModel::Fixed(1.0))
+///     .margin(0.5)
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct BrokerBuilder {
+    name: String,
+    initial_cash: f32,
+    commission: CommissionModel,
+    slippage: f32,
+    margin: f32,
+    exclusive_orders: bool,
+    hedging: bool,
+    maintenance_margin: f32,
+    borrow_rate: f32,
+    margin_rate: f32,
+    margin_rate_indicator: Option<Box<dyn Indicator<Result = f32>>>,
+    fx_rates: HashMap<Symbol, f32>,
+    fx_rate_indicators: HashMap<Symbol, Box<dyn Indicator<Result = f32>>>,
+    max_position_fraction: Option<f32>,
+    max_open_positions: Option<usize>,
+    daily_loss_limit: Option<f32>,
+    max_volume_participation: Option<f32>,
+    tick_size: Option<f32>,
+    cooldown: Option<Duration>,
+    allow_short: bool,
+    allow_fractional: bool,
+    fill_model: Box<dyn FillModel>,
+    accounting: Accounting,
+    calendar: Option<Box<dyn TradingCalendar>>,
+    start_datetime: Option<DateTime<Utc>>,
+    abort_on_rejection: bool,
+    logging: bool,
+}
+
+impl BrokerBuilder {
+    /// `name` is useful for identifying the broker within a Backtest.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            initial_cash: 100_000.0,
+            commission: CommissionModel::Percentage(0.0),
+            slippage: 0.0,
+            margin: 1.0,
+            exclusive_orders: false,
+            hedging: false,
+            maintenance_margin: 0.0,
+            borrow_rate: 0.0,
+            margin_rate: 0.0,
+            margin_rate_indicator: None,
+            fx_rates: HashMap::new(),
+            fx_rate_indicators: HashMap::new(),
+            max_position_fraction: None,
+            max_open_positions: None,
+            daily_loss_limit: None,
+            max_volume_participation: None,
+            tick_size: None,
+            cooldown: None,
+            allow_short: false,
+            allow_fractional: true,
+            fill_model: Box::new(CloseFill),
+            accounting: Accounting::Averaged,
+            calendar: None,
+            start_datetime: None,
+            abort_on_rejection: true,
+            logging: true,
+        }
+    }
+
+    /// The amount of cash to start with.
+    pub fn initial_cash(mut self, initial_cash: f32) -> Self {
+        self.initial_cash = initial_cash;
+        self
+    }
+
+    /// The percentage of the traded value to be paid as commission for each trade.
+    /// This value should be in [-0.1, 0.1].
+    pub fn commission(mut self, commission: f32) -> Self {
+        self.commission = CommissionModel::Percentage(commission);
+        self
+    }
+
+    /// Charges commission according to `commission`, e.g. a flat fee per trade.
+    pub fn commission_model(mut self, commission: CommissionModel) -> Self {
+        self.commission = commission;
+        self
+    }
+
+    /// The fraction of the price lost to [slippage](https://www.investopedia.com/terms/s/slippage.asp)
+    /// on each fill: buys fill at `price * (1 + slippage)` and sells at `price * (1 - slippage)`.
+    /// This value should be in [0, 0.1].
+    pub fn slippage(mut self, slippage: f32) -> Self {
+        self.slippage = slippage;
+        self
+    }
+
+    /// The [margin](https://www.interactivebrokers.com/en/trading/margin.php) requirement as
+    /// a fraction of the position's value. This value should be in [0, 1], and leverage is `1 / margin`.
+    pub fn margin(mut self, margin: f32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// If `true`, each new order auto-closes the previous trade/position, making at most a
+    /// single trade (long or short) in effect at each time.
+    pub fn exclusive_orders(mut self, exclusive_orders: bool) -> Self {
+        self.exclusive_orders = exclusive_orders;
+        self
+    }
+
+    /// If `true`, allow trades in both directions simultaneously.
+    /// If `false`, opposite-facing orders first close existing trades in a [FIFO] manner.
+    pub fn hedging(mut self, hedging: bool) -> Self {
+        self.hedging = hedging;
+        self
+    }
+
+    /// The fraction of the gross exposure that the equity must cover. If the equity drops
+    /// below it, every position is liquidated. This value should be in [0, 1].
+    /// The default of `0.0` only liquidates once the equity turns negative.
+    pub fn maintenance_margin(mut self, maintenance_margin: f32) -> Self {
+        self.maintenance_margin = maintenance_margin;
+        self
+    }
+
+    /// The annual rate charged on the market value of short positions, accrued every bar.
+    pub fn borrow_rate(mut self, borrow_rate: f32) -> Self {
+        self.borrow_rate = borrow_rate;
+        self
+    }
+
+    /// The annual rate charged on the cash borrowed to hold leveraged long positions, accrued every bar.
+    pub fn margin_rate(mut self, margin_rate: f32) -> Self {
+        self.margin_rate = margin_rate;
+        self
+    }
+
+    /// Sources the margin-interest rate from `indicator`, which is updated with every ticker.
+    /// Its value is read as an annual percentage, as reported by `EFFR`, and replaces the
+    /// `margin_rate` whenever the indicator has data.
+    ///
+    /// ```no_run
+    /// use backtester::prelude::*;
+    ///
+    /// let broker = BrokerBuilder::new("EFFR Financing")
+    ///     .margin(0.5)
+    ///     .margin_rate_indicator(Box::new(EFFR::from_csv("./benches/datasets/indicators/DFF.csv")))
+    ///     .build();
+    /// ```
+    pub fn margin_rate_indicator(mut self, indicator: Box<dyn Indicator<Result = f32>>) -> Self {
+        self.margin_rate_indicator = Some(indicator);
+        self
+    }
+
+    /// Quotes `symbol` in a foreign currency worth `rate` units of the account currency,
+    /// e.g. `1.1` for a symbol priced in EUR in a USD account. Fills, commissions and position
+    /// values in `symbol` are converted to the account currency at the latest rate, while its
+    /// trades keep their price and commission in the symbol's own currency.
+    /// Realized profit leaves out the moves of the rate, see `AccountSnapshot::realized_pnl`.
+    /// Symbols without a rate are quoted in the account currency.
+    pub fn fx_rate(mut self, symbol: &str, rate: f32) -> Self {
+        self.fx_rates.insert(symbol.to_string(), rate);
+        self
+    }
+
+    /// Sources the FX rate of `symbol` from `indicator`, which is updated with every ticker and
+    /// replaces the rate whenever it has data. See `fx_rate`.
+    pub fn fx_rate_indicator(mut self, symbol: &str, indicator: Box<dyn Indicator<Result = f32>>) -> Self {
+        self.fx_rate_indicators.insert(symbol.to_string(), indicator);
+        self
+    }
+
+    /// Rejects orders that would grow a position beyond `fraction` of the equity.
+    /// Unlimited by default.
+    pub fn max_position_fraction(mut self, fraction: f32) -> Self {
+        self.max_position_fraction = Some(fraction);
+        self
+    }
+
+    /// Rejects orders that would open more than `positions` positions at once.
+    /// Unlimited by default.
+    pub fn max_open_positions(mut self, positions: usize) -> Self {
+        self.max_open_positions = Some(positions);
+        self
+    }
+
+    /// Once the equity has fallen by more than `fraction` of its value at the start of the
+    /// (UTC) day, new orders are rejected with `DailyLossLimit` until the next day.
+    /// Unlimited by default.
+    pub fn daily_loss_limit(mut self, fraction: f32) -> Self {
+        self.daily_loss_limit = Some(fraction);
+        self
+    }
+
+    /// Only fills an order on a ticker whose volume is at least `quantity / fraction`, so that
+    /// the order takes at most `fraction` of the ticker's volume. Larger orders stay active until
+    /// such a ticker arrives, and zero-volume tickers (e.g. trading halts) fill nothing.
+    /// Unlimited by default.
+    pub fn max_volume_participation(mut self, fraction: f32) -> Self {
+        self.max_volume_participation = Some(fraction);
+        self
+    }
+
+    /// Rounds fill prices and stop prices to the nearest multiple of `tick`, as on an exchange
+    /// that quotes in fixed increments. Limit prices are rounded to the multiple that does not
+    /// loosen them: down for a buy and up for a sell. Fill prices are rounded after slippage,
+    /// and never cross the order's limit. Prices are not rounded by default.
+    pub fn tick_size(mut self, tick: f32) -> Self {
+        self.tick_size = Some(tick);
+        self
+    }
+
+    /// Rejects orders that would open or add to a position within `cooldown` of the last trade
+    /// in the same symbol with `CooldownActive`, to keep noisy strategies from overtrading.
+    /// Orders are checked both when they are submitted and when they would fill, so resting
+    /// orders and several orders on the same ticker are held to it too.
+    /// Orders that reduce a position are always accepted. No cooldown by default.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = Some(cooldown);
+        self
+    }
+
+    /// If `true`, sells that exceed the current long position open a short position.
+    /// If `false` (the default), such sells are rejected with `ShortNotAllowed`.
+    pub fn allow_short(mut self, allow_short: bool) -> Self {
+        self.allow_short = allow_short;
+        self
+    }
+
+    /// If `true` (the default), orders may be for fractional shares. If `false`, order quantities
+    /// are floored to whole shares when they execute, and orders for less than one share are
+    /// rejected with `FractionalQuantity`.
+    pub fn allow_fractional(mut self, allow_fractional: bool) -> Self {
+        self.allow_fractional = allow_fractional;
+        self
+    }
+
+    /// Decides the price within each ticker at which orders fill. Defaults to `CloseFill`.
+    pub fn fill_model(mut self, fill_model: Box<dyn FillModel>) -> Self {
+        self.fill_model = fill_model;
+        self
+    }
+
+    /// Decides the cost basis of closed shares, and so the realized profit. Defaults to `Accounting::Averaged`.
+    pub fn accounting(mut self, accounting: Accounting) -> Self {
+        self.accounting = accounting;
+        self
+    }
+
+    /// The sessions in which on-open and on-close orders fill. Without a calendar, a gap of
+    /// more than 8 hours between two tickers is taken as the boundary between trading days.
+    pub fn calendar(mut self, calendar: Box<dyn TradingCalendar>) -> Self {
+        self.calendar = Some(calendar);
+        self
+    }
+
+    /// The broker's clock before the first ticker, e.g. for orders placed in `Strategy::prepare`.
+    /// Otherwise, a backtest starts the clock at the datetime of its first ticker, and a broker
+    /// fed by hand starts at the Unix epoch.
+    pub fn start_datetime(mut self, datetime: DateTime<Utc>) -> Self {
+        self.start_datetime = Some(datetime);
+        self
+    }
+
+    /// If `true` (the default), an order that is rejected when it comes to execute, e.g. for
+    /// `InsufficientMargin`, fails `next` with the rejection, which ends a backtest. If `false`,
+    /// the order is dropped from the order book instead, and strategies can find out why with
+    /// `Broker::get_last_rejection` and resubmit it, e.g. with a smaller quantity.
+    pub fn abort_on_rejection(mut self, abort_on_rejection: bool) -> Self {
+        self.abort_on_rejection = abort_on_rejection;
+        self
+    }
+
+    /// If `true`, log all the broker's activity. Useful for debugging.
+    pub fn logging(mut self, logging: bool) -> Self {
+        self.logging = logging;
+        self
+    }
+
+    /// # Panics
+    /// If any of the parameters are outside of their documented ranges.
+    pub fn build(self) -> Broker {
+        let name = self.name;
+        if self.initial_cash < 0.0 {
+            panic!("Broker: {} initial_cash should be positive.", name);
+        }
+
+        if let CommissionModel::Percentage(pct) | CommissionModel::Combined { pct, .. } = self.commission {
+            if !(-0.1..=0.1).contains(&pct) {
+                panic!("Broker: {} commission should between -10% (market-maker's rebates) and 10% (fees).", name);
+            }
+        }
+
+        if let CommissionModel::MakerTaker { maker, taker } = self.commission {
+            if !(-0.1..=0.1).contains(&maker) || !(-0.1..=0.1).contains(&taker) {
+                panic!("Broker: {} commission should between -10% (market-maker's rebates) and 10% (fees).", name);
+            }
+        }
+
+        if !(0.0..=0.1).contains(&self.slippage) {
+            panic!("Broker: {} slippage should be between 0% and 10%.", name);
+        }
+
+        if !(0.0..=1.0).contains(&self.margin) {
+            panic!("Broker: {} margin should be between 0 and 1.", name);
+        }
+
+        if !(0.0..=1.0).contains(&self.maintenance_margin) {
+            panic!("Broker: {} maintenance_margin should be between 0 and 1.", name);
+        }
+
+        if self.max_volume_participation.is_some_and(|fraction| fraction <= 0.0) {
+            panic!("Broker: {} max_volume_participation should be positive.", name);
+        }
+
+        if self.cooldown.is_some_and(|cooldown| cooldown < Duration::zero()) {
+            panic!("Broker: {} cooldown should not be negative.", name);
+        }
+
+        if self.fx_rates.values().any(|rate| *rate <= 0.0) {
+            panic!("Broker: {} fx_rate should be positive.", name);
+        }
+
+        if self.tick_size.is_some_and(|tick| tick <= 0.0) {
+            panic!("Broker: {} tick_size should be positive.", name);
+        }
+
+        Broker {
+            name,
+            initial_cash: self.initial_cash,
+            commission: self.commission,
+            slippage: self.slippage,
+            leverage: 1.0 / self.margin,
+            exclusive_orders: self.exclusive_orders,
+            hedging: self.hedging,
+            maintenance_margin: self.maintenance_margin,
+            borrow_rate: self.borrow_rate,
+            margin_rate: self.margin_rate,
+            margin_rate_indicator: self.margin_rate_indicator,
+            fx_rates: self.fx_rates,
+            fx_rate_indicators: self.fx_rate_indicators,
+            max_position_fraction: self.max_position_fraction,
+            max_open_positions: self.max_open_positions,
+            daily_loss_limit: self.daily_loss_limit,
+            max_volume_participation: self.max_volume_participation,
+            tick_size: self.tick_size,
+            cooldown: self.cooldown,
+            allow_short: self.allow_short,
+            allow_fractional: self.allow_fractional,
+            fill_model: self.fill_model,
+            accounting: self.accounting,
+            calendar: self.calendar,
+            start_datetime: self.start_datetime,
+            abort_on_rejection: self.abort_on_rejection,
+            logging: self.logging,
+            datetime: self.start_datetime.unwrap_or(DateTime::UNIX_EPOCH),
+            next_order_id: 0,
+            trading_day: None,
+            day_start_equity: self.initial_cash,
+            halted: false,
+            realized_pnl: 0.0,
+            last_rejection: None,
+            on_trade: None,
+            on_event: None,
+            active_orders: BTreeMap::new(),
+            canceled_orders: HashMap::new(),
+            trailing_marks: BTreeMap::new(),
+            trades: Vec::new(),
+            last_trades: HashMap::new(),
+            cash_flows: Vec::new(),
+            current_cash: self.initial_cash,
+            positions: HashMap::new(),
+            lots: HashMap::new(),
+            allocations: HashMap::new(),
+            active_allocation: None,
+            order_allocations: HashMap::new(),
+            previous_ticker: None,
+            prices: HashMap::new(),
+            symbols: None,
+            corporate_actions: Vec::new(),
+        }
+    }
+}
+
 impl Broker {
-    /// Creates a new Broker instance.
+    /// Creates a new Broker instance. See `BrokerBuilder` for named parameters and further options.
     /// - `name` - Useful for identifying the broker within a Backtest.
     /// - `initial_cash` - The amount of cash to start with.
     /// - `commission` - The percentage of cash to be paid as commission for each trade. This value should be in [-0.1, 0.1].
     /// - [`margin`](https://www.interactivebrokers.com/en/trading/margin.php) - The percentage of cash to be used as margin for each trade. This value should be in [0, 1].
     /// - `exclusive_orders` - If `true`, each new order auto-closes the previous trade/position, making at most a single trade (long or short) in effect at each time.
     /// - [`hedging`](https://www.investopedia.com/terms/h/hedge.asp) - If `true`, allow trades in both directions simultaneously. If `false`, opposite-facing orders first close existing trades in a [FIFO] manner.
-    /// - `logging` - If `true`, log all the broker's activity. Useful for debugging.
     pub fn new(
         name: &str,
         initial_cash: f32,
@@ -81,160 +835,1008 @@ impl Broker {
         exclusive_orders: bool,
         hedging: bool,
     ) -> Self {
-        if initial_cash < 0.0 {
-            panic!("Broker: {} initial_cash should be positive.", name);
-        }
+        BrokerBuilder::new(name)
+            .initial_cash(initial_cash)
+            .commission(commission)
+            .margin(margin)
+            .exclusive_orders(exclusive_orders)
+            .hedging(hedging)
+            .build()
+    }
 
-        if commission > 0.1 || commission < -0.1 {
-            panic!("Broker: {} commission should between -10% (market-maker's rebates) and 10% (fees).", name);
-        }
+    /// A broker with conservative frictions, to check that a strategy survives realistic costs:
+    /// a commission of 0.1% of each trade, a slippage of 0.05% on each fill, and fills capped
+    /// at 10% of a ticker's volume. Market orders fill on the ticker after they are submitted.
+    /// Use `BrokerBuilder` to tune the frictions individually.
+    pub fn realistic(name: &str, initial_cash: f32) -> Self {
+        BrokerBuilder::new(name)
+            .initial_cash(initial_cash)
+            .commission(0.001)
+            .slippage(0.0005)
+            .max_volume_participation(0.1)
+            .build()
+    }
 
-        if margin < 0.0 || margin > 1.0 {
-            panic!("Broker: {} margin should be between 0 and 1.", name);
+    /// A frictionless broker, without commission, slippage or volume limits,
+    /// to test a strategy's logic under optimistic assumptions.
+    pub fn idealized(name: &str, initial_cash: f32) -> Self {
+        BrokerBuilder::new(name).initial_cash(initial_cash).build()
+    }
+
+    /// Logs `args` if logging is enabled for this broker.
+    fn log(&self, args: fmt::Arguments) {
+        if self.logging {
+            info!(target: "backtester::broker", "{}", args);
         }
+    }
 
-        Self {
-            name: name.to_string(),
-            initial_cash,
-            commission,
-            leverage: 1.0 / margin,
-            exclusive_orders,
-            hedging,
-            datetime: Utc::now(),
-            active_orders: HashMap::new(),
-            canceled_orders: HashMap::new(),
-            trades: HashMap::new(),
-            current_cash: initial_cash,
-            positions: HashMap::new(),
-            previous_ticker: None,
+    /// Passes `event` to the `on_event` callback, and logs it if logging is enabled for this broker.
+    fn emit(&self, event: BrokerEvent) {
+        if let Some(on_event) = &self.on_event {
+            (on_event.borrow_mut())(&event);
         }
+        self.log(format_args!("{}", event));
     }
 
     pub fn next(&mut self, ticker: &Ticker) -> Result<(), BrokerError> {
-        info!("Ticker: {}\nBroker State: {}\n", ticker, self);
+        self.step(None, ticker)
+    }
+
+    /// Advances the broker with the latest `ticker` of `symbol`, for feeds that interleave
+    /// several symbols (see `MultiFeed`). Only orders for `symbol` are processed, and positions
+    /// are marked at the latest ticker of their own symbol.
+    pub fn next_symbol(&mut self, symbol: &str, ticker: &Ticker) -> Result<(), BrokerError> {
+        self.step(Some(symbol), ticker)
+    }
 
+    /// Declares the symbols that the broker will be fed with `next_symbol`. From then on,
+    /// orders for any other symbol are rejected with `UnknownSymbol` when they are submitted,
+    /// instead of resting in the order book without a price to fill at.
+    /// `Backtest::run_multi_feed` declares the symbols of its feed.
+    pub fn set_symbols(&mut self, symbols: &[&str]) {
+        self.symbols = Some(symbols.iter().map(|symbol| symbol.to_string()).collect());
+    }
+
+    fn step(&mut self, symbol: Option<&str>, ticker: &Ticker) -> Result<(), BrokerError> {
+        check_prices(ticker)?;
+        self.log(format_args!("Ticker: {}\nBroker State: {}\n", ticker, self));
+
+        self.accrue_financing(ticker);
+        self.update_margin_rate(ticker);
+        self.update_fx_rates(ticker);
+        self.roll_trading_day(ticker);
+        self.apply_corporate_actions(ticker);
         self.datetime = DateTime::from(ticker.datetime);
-        self.process_active_orders(ticker)?;
+        self.process_active_orders(ticker, symbol)?;
         self.previous_ticker = Some(ticker.clone());
+        if let Some(symbol) = symbol {
+            self.prices.insert(symbol.to_string(), ticker.clone());
+        }
+
+        let exposure = self.gross_exposure();
+        if exposure > 0.0 && self.get_equity() < self.maintenance_margin * exposure {
+            self.log(format_args!("Margin call: equity {} for exposure {}", self.get_equity(), exposure));
+            self.flatten_all(ticker)?;
+            return Err(BrokerError::OutOfMoneyError);
+        }
+
+        if let Some(limit) = self.daily_loss_limit {
+            if !self.halted && self.get_equity() < self.day_start_equity * (1.0 - limit) {
+                self.log(format_args!("Daily loss limit: equity {} from {}", self.get_equity(), self.day_start_equity));
+                self.halted = true;
+            }
+        }
 
         Ok(())
     }
 
-    pub fn submit_order(&mut self, id: OrderId, order: Order) -> Result<(), BrokerError> {
-        info!("Order (submit): {}\n", order);
+    /// Charges the borrow cost of short positions and the interest on margin loans
+    /// for the time elapsed since the previous ticker.
+    fn accrue_financing(&mut self, ticker: &Ticker) {
+        let previous = match &self.previous_ticker {
+            Some(previous) => previous,
+            None => return,
+        };
+        let years = (ticker.datetime - previous.datetime).num_seconds() as f32 / (365.0 * 86_400.0);
+        if years <= 0.0 {
+            return;
+        }
 
-        self.active_orders.insert(id, order);
+        let short_value = self
+            .positions
+            .values()
+            .filter(|position| position.amount < 0.0)
+            .map(|position| -self.position_value(position))
+            .sum::<f32>();
+        let long_value = self
+            .positions
+            .values()
+            .filter(|position| position.amount > 0.0)
+            .map(|position| self.position_value(position))
+            .sum::<f32>();
+        let borrowed = (long_value - self.get_equity()).max(0.0);
 
-        Ok(())
+        let financing = (self.borrow_rate * short_value + self.margin_rate * borrowed) * years;
+        if financing != 0.0 {
+            self.log(format_args!("Financing: {}", financing));
+            self.current_cash -= financing;
+        }
     }
 
-    pub fn cancel_order(&mut self, id: OrderId) -> Result<(), BrokerError> {
-        info!("Order (cancel): {}\n", id);
+    /// Reads the margin-interest rate for the coming bar from the `margin_rate_indicator`, if any.
+    /// The previous rate is kept while the indicator has no data.
+    fn update_margin_rate(&mut self, ticker: &Ticker) {
+        if let Some(indicator) = &mut self.margin_rate_indicator {
+            if indicator.update(ticker).is_ok() {
+                if let Ok(rate) = indicator.get_value() {
+                    self.margin_rate = rate / 100.0;
+                }
+            }
+        }
+    }
 
-        if let Some(order) = self.active_orders.remove(&id) {
-            if let Some(callback) = order.on_cancel {
-                callback(self)?;
+    /// Reads the FX rates for the coming bar from the `fx_rate_indicators`, if any.
+    /// The previous rate of a symbol is kept while its indicator has no data.
+    fn update_fx_rates(&mut self, ticker: &Ticker) {
+        for (symbol, indicator) in self.fx_rate_indicators.iter_mut() {
+            if indicator.update(ticker).is_ok() {
+                if let Ok(rate) = indicator.get_value() {
+                    self.fx_rates.insert(symbol.clone(), rate);
+                }
             }
-        } else {
-            return Err(BrokerError::OrderIdNotFound);
         }
+    }
 
-        Ok(())
+    /// Overrides the FX rate of `symbol`, e.g. from a strategy that follows an FX feed.
+    /// See `BrokerBuilder::fx_rate`.
+    pub fn set_fx_rate(&mut self, symbol: &str, rate: f32) {
+        self.fx_rates.insert(symbol.to_string(), rate);
     }
 
-    /// Processes a single order.
-    fn execute_order(&mut self, order: Order, ticker: &Ticker) -> Result<(), BrokerError> {
-        match order.side {
-            OrderSide::Buy => {
-                if let Some(position) = self.positions.remove(&order.symbol) {
-                    // We already have a position in this symbol. We need to update the position.
-                    self.positions.insert(
-                        order.symbol.clone(),
-                        Position {
-                            symbol: order.symbol,
-                            amount: position.amount + order.quantity,
-                            price: (position.amount * position.price
-                                + order.quantity * ticker.close)
-                                / (position.amount + order.quantity),
-                        },
-                    );
-                } else {
-                    self.positions.insert(
-                        order.symbol.clone(),
-                        Position {
-                            symbol: order.symbol,
-                            amount: order.quantity,
-                            price: ticker.close,
-                        },
-                    );
+    /// The value of one unit of the currency of `symbol` in the account currency.
+    pub fn get_fx_rate(&self, symbol: &str) -> f32 {
+        self.fx_rates.get(symbol).copied().unwrap_or(1.0)
+    }
+
+    /// Starts a new trading day if `ticker` falls on a later date, lifting the daily loss limit.
+    /// Days on which the calendar has no session are skipped.
+    fn roll_trading_day(&mut self, ticker: &Ticker) {
+        let day = ticker.datetime.date_naive();
+        if self.calendar.as_ref().is_some_and(|calendar| calendar.session(day).is_none()) {
+            return;
+        }
+        if self.trading_day.is_none_or(|current| day > current) {
+            self.trading_day = Some(day);
+            self.day_start_equity = self.get_equity();
+            self.halted = false;
+        }
+    }
+
+    /// Schedules dividends and splits. Each one is applied to the positions held at the
+    /// first ticker on or after its date, before any order fills at that ticker.
+    /// Active orders are not adjusted for splits.
+    pub fn add_corporate_actions(&mut self, actions: Vec<CorporateAction>) {
+        self.corporate_actions.extend(actions);
+        self.corporate_actions.sort_by_key(|action| std::cmp::Reverse(action.get_date()));
+    }
+
+    /// Applies every pending corporate action dated on or before `ticker`.
+    fn apply_corporate_actions(&mut self, ticker: &Ticker) {
+        let day = ticker.datetime.date_naive();
+        while self.corporate_actions.last().is_some_and(|action| action.get_date() <= day) {
+            let action = self.corporate_actions.pop().unwrap();
+            self.log(format_args!("Corporate action: {:?}", action));
+            match action {
+                CorporateAction::Dividend { symbol, per_share, .. } => {
+                    if let Some(position) = self.positions.get(&symbol) {
+                        self.current_cash += position.amount * per_share * self.get_fx_rate(&symbol);
+                    }
                 }
-                info!("Bought {} shares @ {}", order.quantity, ticker.close);
-                self.current_cash -= order.quantity * ticker.close;
-            }
-            OrderSide::Sell => {
-                if let Some(position) = self.positions.remove(&order.symbol) {
-                    // We already have a position in this symbol. We need to update the position.
-                    let new_amount = position.amount - order.quantity;
-                    if new_amount.abs() > std::f32::EPSILON {
-                        self.positions.insert(
-                            order.symbol.clone(),
-                            Position {
-                                symbol: order.symbol,
-                                amount: new_amount,
-                                price: (position.amount * position.price
-                                    - order.quantity * ticker.close)
-                                    / (position.amount - order.quantity),
-                            },
-                        );
+                CorporateAction::Split { symbol, ratio, .. } => {
+                    if let Some(position) = self.positions.get_mut(&symbol) {
+                        position.amount *= ratio;
+                        position.price /= ratio;
+                    }
+                    for lot in self.lots.get_mut(&symbol).into_iter().flatten() {
+                        lot.amount *= ratio;
+                        lot.price /= ratio;
+                    }
+                    for allocation in self.allocations.values_mut() {
+                        if let Some(amount) = allocation.amounts.get_mut(&symbol) {
+                            *amount *= ratio;
+                        }
                     }
-                } else {
-                    self.positions.insert(
-                        order.symbol.clone(),
-                        Position {
-                            symbol: order.symbol,
-                            amount: -order.quantity,
-                            price: ticker.close,
-                        },
-                    );
                 }
-                info!("Sold {} shares @ {}", order.quantity, ticker.close);
-                self.current_cash += order.quantity * ticker.close;
             }
-        };
+        }
+    }
 
-        // Handle the `on_execute` callback
-        if let Some(callback) = order.on_execute {
-            callback(self)?;
+    /// Closes every open position at market, filling immediately against `ticker`, e.g. at the
+    /// end of a run or on a risk event. When fed per symbol (see `next_symbol`), each position
+    /// fills against the latest ticker of its own symbol instead. Does nothing when flat.
+    ///
+    /// Positions are closed in the order of their symbols, with reduce-only orders that
+    /// bypass the broker's risk limits. Fractional positions, e.g. after a split, are closed
+    /// in full even if the broker only trades whole shares.
+    pub fn flatten_all(&mut self, ticker: &Ticker) -> BrokerResult<()> {
+        let mut positions = self.positions.values().cloned().collect::<Vec<_>>();
+        positions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        for position in positions {
+            let ticker = self.prices.get(&position.symbol).cloned().unwrap_or_else(|| ticker.clone());
+            let side = if position.amount > 0.0 {
+                OrderSide::Sell
+            } else {
+                OrderSide::Buy
+            };
+            let id = self.next_order_id;
+            self.next_order_id += 1;
+            let order = Order {
+                symbol: position.symbol,
+                quantity: position.amount.abs(),
+                side,
+                order_type: OrderType::Market,
+                datetime: self.get_datetime(),
+                execution: OrderExecutionStrategy::GTC,
+                reduce_only: true,
+                on_execute: None,
+                on_cancel: None,
+            };
+            let fill = self.fill_at(&order, order.quantity, &ticker);
+            self.settle(id, order, fill, None, &ticker)?;
+        }
+        for allocation in self.allocations.values_mut() {
+            allocation.amounts.clear();
         }
+        Ok(())
+    }
+
+    /// Until `exit_allocation` is called, limits the gross exposure, long and short, that orders
+    /// placed against `slot` may build up to `weight` of the equity.
+    /// `buying_power`, `max_shares` and `can_execute` reflect the limit, and orders submitted in
+    /// the meantime are held to it when they execute.
+    pub(crate) fn enter_allocation(&mut self, slot: usize, weight: f32) {
+        self.allocations
+            .entry(slot)
+            .or_insert(Allocation { weight, amounts: HashMap::new() })
+            .weight = weight;
+        self.active_allocation = Some(slot);
+    }
+
+    pub(crate) fn exit_allocation(&mut self) {
+        self.active_allocation = None;
+    }
+
+    /// Places `order` in the order book and returns the id allocated to it.
+    /// Ids increase monotonically, so an order can never overwrite another one.
+    ///
+    /// Fails with `DailyLossLimit` while trading is halted for the day.
+    pub fn submit_order(&mut self, order: Order) -> BrokerResult<OrderId> {
+        self.check_submission(&order)?;
+        let id = self.next_order_id;
+        self.insert_order(id, order);
+        Ok(id)
+    }
 
-        info!("Positions: {:?}", self.positions);
+    /// Places `order` in the order book under `id`.
+    /// Fails with `DuplicateOrderId` if an order with `id` is already active.
+    #[deprecated(note = "use `submit_order`, which allocates the order id")]
+    pub fn submit_order_with_id(&mut self, id: OrderId, order: Order) -> BrokerResult<()> {
+        if self.active_orders.contains_key(&id) {
+            return Err(BrokerError::DuplicateOrderId);
+        }
+        self.check_submission(&order)?;
+        self.insert_order(id, order);
+        Ok(())
+    }
 
+    /// Refuses an order that cannot be placed in the order book right now.
+    /// Shared by `submit_order` and `replace_order`.
+    fn check_submission(&self, order: &Order) -> Result<(), BrokerError> {
+        if self.halted {
+            return Err(BrokerError::DailyLossLimit);
+        }
+        self.check_symbol(order)?;
+        self.check_cooldown(order)?;
         Ok(())
     }
 
-    /// Processes all the withstanding active_orders in the order book.
-    /// This function mainly handles the order processing logic, but the
-    /// actual order execution is performed in 'execute_order'.
+    fn insert_order(&mut self, id: OrderId, order: Order) {
+        self.emit(BrokerEvent::OrderSubmitted {
+            id,
+            symbol: order.symbol.clone(),
+            side: order.side.clone(),
+            quantity: order.quantity,
+            datetime: self.datetime,
+        });
+
+        self.next_order_id = self.next_order_id.max(id + 1);
+        if let Some(slot) = self.active_allocation {
+            self.order_allocations.insert(id, slot);
+        }
+        self.active_orders.insert(id, order);
+    }
+
+    /// Places a market order for `quantity` shares of `symbol` and, once it fills, a stop-loss
+    /// `pct_from_fill` (e.g. `0.05` for 5%) away from the fill price: below it for a buy,
+    /// above it for a sell. Returns the id of the entry order.
+    pub fn submit_stop_loss(&mut self, symbol: &str, side: OrderSide, quantity: f32, pct_from_fill: f32) -> BrokerResult<OrderId> {
+        let on_execute = FillCallback::new(move |broker, trade| {
+            let (side, stop) = match trade.side {
+                OrderSide::Buy => (OrderSide::Sell, trade.price * (1.0 - pct_from_fill)),
+                OrderSide::Sell => (OrderSide::Buy, trade.price * (1.0 + pct_from_fill)),
+            };
+            broker.submit_order(Order {
+                symbol: trade.symbol.clone(),
+                quantity: trade.quantity,
+                side,
+                order_type: OrderType::Stop(stop),
+                datetime: trade.datetime,
+                execution: OrderExecutionStrategy::GTC,
+                reduce_only: true,
+                on_execute: None,
+                on_cancel: None,
+            })?;
+            Ok(())
+        });
+        self.submit_order(Order {
+            symbol: symbol.to_string(),
+            quantity,
+            side,
+            order_type: OrderType::Market,
+            datetime: self.datetime,
+            execution: OrderExecutionStrategy::GTC,
+            reduce_only: false,
+            on_execute: Some(on_execute),
+            on_cancel: None,
+        })
+    }
+
+    /// Places `entry` together with a protective stop-loss at `stop_price` and a take-profit
+    /// at `take_profit_price`. The protective orders are submitted once the entry fills, sized
+    /// to the fill, and are one-cancels-other: when either executes, the other is cancelled.
     ///
-    /// # TODO: There needs to be some sense of time delay
-    fn process_active_orders(&mut self, ticker: &Ticker) -> Result<(), BrokerError> {
-        let mut non_executed_active_orders = HashMap::new();
-        for (id, order) in self.active_orders.clone() {
-            match order.order_type {
-                OrderType::Market => {
-                    self.execute_order(order, ticker)?;
-                    continue;
-                }
-                OrderType::Limit(limit) => match order.side {
-                    OrderSide::Buy => {
-                        if ticker.close <= limit {
-                            self.execute_order(order, ticker)?;
-                            continue;
+    /// Any `on_execute` callback of `entry` is still called after the protective orders are placed.
+    pub fn submit_bracket(&mut self, entry: Order, stop_price: f32, take_profit_price: f32) -> BrokerResult<OrderId> {
+        let on_entry = entry.on_execute.clone();
+        let on_execute = FillCallback::new(move |broker, trade| {
+            let exit = Order {
+                symbol: trade.symbol.clone(),
+                quantity: trade.quantity,
+                side: match trade.side {
+                    OrderSide::Buy => OrderSide::Sell,
+                    OrderSide::Sell => OrderSide::Buy,
+                },
+                order_type: OrderType::Market,
+                datetime: trade.datetime,
+                execution: OrderExecutionStrategy::GTC,
+                reduce_only: true,
+                on_execute: None,
+                on_cancel: None,
+            };
+
+            let take_profit_id = Rc::new(Cell::new(None));
+            let sibling = take_profit_id.clone();
+            let stop_id = broker.submit_order(Order {
+                order_type: OrderType::Stop(stop_price),
+                on_execute: Some(FillCallback::new(move |broker, _| cancel_sibling(broker, sibling.get()))),
+                ..exit.clone()
+            })?;
+            take_profit_id.set(Some(broker.submit_order(Order {
+                order_type: OrderType::Limit(take_profit_price),
+                on_execute: Some(FillCallback::new(move |broker, _| cancel_sibling(broker, Some(stop_id)))),
+                ..exit
+            })?));
+
+            match &on_entry {
+                Some(callback) => callback.call(broker, trade),
+                None => Ok(()),
+            }
+        });
+        self.submit_order(Order {
+            on_execute: Some(on_execute),
+            ..entry
+        })
+    }
+
+    /// Registers `callback` to be called with every `Trade` as soon as an order executes,
+    /// e.g. to stream fills into a database or a UI. Replaces any previous callback.
+    ///
+    /// Clones of the broker share the callback.
+    pub fn set_on_trade(&mut self, callback: Box<dyn FnMut(&Trade)>) {
+        self.on_trade = Some(Rc::new(RefCell::new(callback)));
+    }
+
+    /// Registers `callback` to be called with every `BrokerEvent`, whether or not logging is
+    /// enabled. Replaces any previous callback.
+    ///
+    /// Clones of the broker share the callback.
+    pub fn set_on_event(&mut self, callback: Box<dyn FnMut(&BrokerEvent)>) {
+        self.on_event = Some(Rc::new(RefCell::new(callback)));
+    }
+
+    /// Returns the order with `id` if it has neither been executed nor cancelled.
+    pub fn get_active_order(&self, id: OrderId) -> Option<&Order> {
+        self.active_orders.get(&id)
+    }
+
+    /// Returns `true` if the order with `id` has neither been executed nor cancelled.
+    pub fn has_active_order(&self, id: OrderId) -> bool {
+        self.active_orders.contains_key(&id)
+    }
+
+    /// Iterates the orders that have neither been executed nor cancelled, in the order they were submitted.
+    pub fn get_active_orders(&self) -> impl Iterator<Item = (&OrderId, &Order)> {
+        self.active_orders.iter()
+    }
+
+    /// Checks whether `order` would be accepted if it were filled at the latest ticker of its
+    /// symbol, without submitting it. The order is sized and priced like at execution and goes
+    /// through the same checks, so strategies can size an order down rather than have it rejected.
+    ///
+    /// Before the first ticker there is no price to check against, so every order is accepted.
+    pub fn can_execute(&self, order: &Order) -> Result<(), BrokerError> {
+        if self.halted {
+            return Err(BrokerError::DailyLossLimit);
+        }
+        self.check_symbol(order)?;
+        let ticker = match self.prices.get(&order.symbol).or(self.previous_ticker.as_ref()) {
+            Some(ticker) => ticker,
+            None => return Ok(()),
+        };
+        match self.fill_for(order, ticker) {
+            Some(fill) => {
+                let order = Order { quantity: fill.quantity, ..order.clone() };
+                self.check_fill(&order, &fill, self.active_allocation)
+            }
+            // A reduce-only order without a position to reduce is dropped, not rejected.
+            None => Ok(()),
+        }
+    }
+
+    /// Swaps the active order with `id` for `order` in a single step, e.g. to move a limit price.
+    /// The new order keeps the id and the place in the order book of the old one, which is
+    /// dropped without calling its `on_cancel` callback.
+    /// Fails with `OrderIdNotFound` if no order with `id` is active, and otherwise validates
+    /// `order` like `submit_order`, keeping the old order if it is refused.
+    pub fn replace_order(&mut self, id: OrderId, order: Order) -> Result<(), BrokerError> {
+        if !self.active_orders.contains_key(&id) {
+            return Err(BrokerError::OrderIdNotFound);
+        }
+        self.check_submission(&order)?;
+        let event = BrokerEvent::OrderReplaced {
+            id,
+            symbol: order.symbol.clone(),
+            side: order.side.clone(),
+            quantity: order.quantity,
+            datetime: self.datetime,
+        };
+        self.active_orders.insert(id, order);
+        self.trailing_marks.remove(&id);
+        self.emit(event);
+        Ok(())
+    }
+
+    pub fn cancel_order(&mut self, id: OrderId) -> Result<(), BrokerError> {
+        if let Some(order) = self.active_orders.remove(&id) {
+            self.order_allocations.remove(&id);
+            self.trailing_marks.remove(&id);
+            self.canceled_orders.insert(id, order.clone());
+            self.emit(BrokerEvent::OrderCancelled {
+                id,
+                symbol: order.symbol.clone(),
+                side: order.side.clone(),
+                quantity: order.quantity,
+                datetime: self.datetime,
+            });
+            if let Some(callback) = order.on_cancel {
+                callback.call(self)?;
+            }
+        } else {
+            return Err(BrokerError::OrderIdNotFound);
+        }
+
+        Ok(())
+    }
+
+    /// Processes a single order.
+    fn execute_order(&mut self, id: OrderId, mut order: Order, ticker: &Ticker) -> Result<(), BrokerError> {
+        self.active_orders.remove(&id);
+        let allocation = self.order_allocations.remove(&id);
+        let fill = match self.fill_for(&order, ticker) {
+            Some(fill) => fill,
+            None => return Ok(()),
+        };
+        order.quantity = fill.quantity;
+        if let Err(reason) = self.check_fill(&order, &fill, allocation) {
+            return self.reject(id, order, reason);
+        }
+        self.settle(id, order, fill, allocation, ticker)
+    }
+
+    /// Books `fill` of `order`, which passed its checks, against the account and the allocation
+    /// in `slot`, records the trade and runs the order's `on_execute` callback.
+    fn settle(&mut self, id: OrderId, order: Order, fill: Fill, allocation: Option<usize>, ticker: &Ticker) -> Result<(), BrokerError> {
+        let Fill { market_price, price, commission, maker, fx_rate, limit, .. } = fill;
+        let symbol = order.symbol.clone();
+        let quantity = match order.side {
+            OrderSide::Buy => order.quantity,
+            OrderSide::Sell => -order.quantity,
+        };
+        if let Some(allocation) = allocation.and_then(|slot| self.allocations.get_mut(&slot)) {
+            *allocation.amounts.entry(symbol.clone()).or_insert(0.0) += quantity;
+        }
+        self.realized_pnl += self.apply_fill(&symbol, quantity, price) * fx_rate;
+        self.current_cash -= quantity * price * fx_rate;
+
+        self.current_cash -= commission * fx_rate;
+        self.emit(BrokerEvent::OrderExecuted {
+            id,
+            symbol: symbol.clone(),
+            side: order.side.clone(),
+            quantity: order.quantity,
+            price,
+            commission,
+            datetime: ticker.datetime,
+        });
+        self.emit(BrokerEvent::PositionChanged {
+            symbol: symbol.clone(),
+            amount: self.positions.get(&symbol).map_or(0.0, |position| position.amount),
+            price: self.positions.get(&symbol).map_or(price, |position| position.price),
+            datetime: ticker.datetime,
+        });
+        self.trades.push(Trade {
+            symbol,
+            side: order.side.clone(),
+            quantity: order.quantity,
+            price,
+            commission,
+            datetime: ticker.datetime,
+            market_price,
+            maker,
+            fx_rate,
+            limit,
+        });
+        self.last_trades.insert(order.symbol.clone(), ticker.datetime);
+        if let (Some(on_trade), Some(trade)) = (&self.on_trade, self.trades.last()) {
+            (on_trade.borrow_mut())(trade);
+        }
+
+        // Handle the `on_execute` callback
+        if let Some(callback) = order.on_execute {
+            let trade = self.trades[self.trades.len() - 1].clone();
+            callback.call(self, &trade)?;
+        }
+
+        Ok(())
+    }
+
+    /// How `order` would fill at `ticker`: its quantity once clamped to the position if it is
+    /// reduce-only and floored unless fractional shares are allowed, and its price from the
+    /// fill model with slippage and tick rounding. `None` if the order is reduce-only and
+    /// there is no position to reduce.
+    fn fill_for(&self, order: &Order, ticker: &Ticker) -> Option<Fill> {
+        let mut quantity = order.quantity;
+        if order.reduce_only {
+            let amount = self.positions.get(&order.symbol).map_or(0.0, |position| position.amount);
+            let reducible = match order.side {
+                OrderSide::Buy => (-amount).max(0.0),
+                OrderSide::Sell => amount.max(0.0),
+            };
+            if reducible == 0.0 {
+                return None;
+            }
+            quantity = quantity.min(reducible);
+        }
+        if !self.allow_fractional {
+            quantity = quantity.floor();
+        }
+        Some(self.fill_at(order, quantity, ticker))
+    }
+
+    /// The fill of `quantity` of `order` at `ticker`, at the price given by the fill model
+    /// with slippage and tick rounding, but no worse than the order's limit.
+    fn fill_at(&self, order: &Order, quantity: f32, ticker: &Ticker) -> Fill {
+        let market_price = self.fill_model.fill_price(order, ticker);
+        let limit = match order.order_type {
+            OrderType::Limit(limit) | OrderType::LOC(limit) | OrderType::LOO(limit) => {
+                Some(self.round_limit(&order.side, limit))
+            }
+            _ => None,
+        };
+        let price = self.execution_price(&order.side, market_price, self.slippage, limit);
+        let maker = self.makes_liquidity(order);
+        Fill {
+            quantity,
+            market_price,
+            price,
+            commission: self.commission.commission_for(quantity, price, maker),
+            maker,
+            fx_rate: self.get_fx_rate(&order.symbol),
+            limit,
+        }
+    }
+
+    /// The price at which an order on `side` fills against `market_price`: moved against the
+    /// order by `slippage`, rounded to the tick size and capped at the order's `limit`, if any.
+    pub(crate) fn execution_price(&self, side: &OrderSide, market_price: f32, slippage: f32, limit: Option<f32>) -> f32 {
+        let price = self.round_to_tick(slipped_price(side, market_price, slippage));
+        match (limit, side) {
+            (Some(limit), OrderSide::Buy) => price.min(limit),
+            (Some(limit), OrderSide::Sell) => price.max(limit),
+            (None, _) => price,
+        }
+    }
+
+    /// Runs the checks that `order`, already sized to `fill.quantity`, must pass to fill as `fill`
+    /// against the allocation in `slot`. Prices are converted to the account currency first.
+    fn check_fill(&self, order: &Order, fill: &Fill, slot: Option<usize>) -> Result<(), BrokerError> {
+        self.check_order(order, fill.price * fill.fx_rate)?;
+        self.check_allocation(slot, order, fill.price * fill.fx_rate, fill.commission * fill.fx_rate)
+    }
+
+    /// Records that `order` was rejected for `reason` and fails with it if the broker aborts on
+    /// rejections. The order is already out of the order book.
+    fn reject(&mut self, id: OrderId, order: Order, reason: BrokerError) -> Result<(), BrokerError> {
+        self.emit(BrokerEvent::OrderRejected {
+            id,
+            symbol: order.symbol.clone(),
+            side: order.side.clone(),
+            quantity: order.quantity,
+            reason: reason.clone(),
+            datetime: self.datetime,
+        });
+        self.last_rejection = Some(Rejection {
+            id,
+            order,
+            reason: reason.clone(),
+            datetime: self.datetime,
+        });
+        match self.abort_on_rejection {
+            true => Err(reason),
+            false => Ok(()),
+        }
+    }
+
+    /// Whether `order` fills as a maker, i.e. it is a limit order that the previous price of its
+    /// symbol did not reach, so it rested in the order book. Before any price is known, every
+    /// order is taken to be marketable.
+    fn makes_liquidity(&self, order: &Order) -> bool {
+        let previous = match self.prices.get(&order.symbol).or(self.previous_ticker.as_ref()) {
+            Some(previous) => previous.close,
+            None => return false,
+        };
+        match (&order.order_type, &order.side) {
+            (OrderType::Limit(limit) | OrderType::LOC(limit) | OrderType::LOO(limit), OrderSide::Buy) => previous > *limit,
+            (OrderType::Limit(limit) | OrderType::LOC(limit) | OrderType::LOO(limit), OrderSide::Sell) => previous < *limit,
+            _ => false,
+        }
+    }
+
+    /// Updates the position in `symbol` with a fill of `quantity` shares (negative for sells)
+    /// at `price`, and returns the profit realized by the part of the fill that reduced the position.
+    ///
+    /// Adding to a position averages its entry price, reducing it keeps the entry price,
+    /// and reversing it opens the remainder at `price`.
+    fn apply_fill(&mut self, symbol: &str, quantity: f32, price: f32) -> f32 {
+        let (amount, entry) = self
+            .positions
+            .get(symbol)
+            .map_or((0.0, price), |position| (position.amount, position.price));
+        let new_amount = amount + quantity;
+
+        let mut realized = 0.0;
+        let new_entry = if amount == 0.0 || amount.signum() == quantity.signum() {
+            (amount * entry + quantity * price) / new_amount
+        } else {
+            realized = quantity.abs().min(amount.abs()) * (price - entry) * amount.signum();
+            if quantity.abs() > amount.abs() {
+                price
+            } else {
+                entry
+            }
+        };
+
+        if new_amount.abs() > f32::EPSILON {
+            self.positions.insert(
+                symbol.to_string(),
+                Position {
+                    symbol: symbol.to_string(),
+                    amount: new_amount,
+                    price: new_entry,
+                },
+            );
+        } else {
+            self.positions.remove(symbol);
+        }
+
+        match self.accounting {
+            Accounting::Averaged => realized,
+            Accounting::Lots(matching) => self.apply_lots(symbol, quantity, price, matching),
+        }
+    }
+
+    /// Closes the lots of `symbol` in `matching` order with a fill of `quantity` shares at `price`,
+    /// opening a new lot with whatever remains, and returns the realized profit.
+    /// The entry price of the position is kept at the weighted average of the open lots.
+    fn apply_lots(&mut self, symbol: &str, quantity: f32, price: f32, matching: LotMatching) -> f32 {
+        let lots = self.lots.entry(symbol.to_string()).or_default();
+        let mut remaining = quantity;
+        let mut realized = 0.0;
+        while remaining.abs() > f32::EPSILON {
+            let index = match matching {
+                LotMatching::Fifo => 0,
+                LotMatching::Lifo => lots.len().wrapping_sub(1),
+            };
+            let lot = match lots.get_mut(index) {
+                Some(lot) if lot.amount.signum() != remaining.signum() => lot,
+                _ => break,
+            };
+            let closed = remaining.abs().min(lot.amount.abs()) * lot.amount.signum();
+            realized += closed * (price - lot.price);
+            lot.amount -= closed;
+            remaining += closed;
+            if lot.amount.abs() <= f32::EPSILON {
+                lots.remove(index);
+            }
+        }
+        if remaining.abs() > f32::EPSILON {
+            lots.push(Lot { amount: remaining, price });
+        }
+
+        let amount: f32 = lots.iter().map(|lot| lot.amount).sum();
+        let cost: f32 = lots.iter().map(|lot| lot.amount * lot.price).sum();
+        if lots.is_empty() {
+            self.lots.remove(symbol);
+        }
+        if let Some(position) = self.positions.get_mut(symbol) {
+            position.price = cost / amount;
+        }
+        realized
+    }
+
+    /// Runs every check that `order` must pass to be filled at `price`.
+    fn check_order(&self, order: &Order, price: f32) -> Result<(), BrokerError> {
+        if !self.allow_fractional && order.quantity < 1.0 {
+            return Err(BrokerError::FractionalQuantity);
+        }
+        // A reduce-only order is clamped to the position before it fills, so it can only
+        // shrink exposure.
+        if order.reduce_only {
+            return Ok(());
+        }
+        if !self.allow_short && order.side == OrderSide::Sell {
+            let long = self
+                .positions
+                .get(&order.symbol)
+                .map_or(0.0, |position| position.amount.max(0.0));
+            if order.quantity > long {
+                return Err(BrokerError::ShortNotAllowed);
+            }
+        }
+        self.check_cooldown(order)?;
+        self.check_margin(order, price)?;
+        self.check_risk_limits(order, price)
+    }
+
+    /// Rejects `order` with `UnknownSymbol` if the broker knows which symbols it is fed
+    /// and `order.symbol` is not one of them.
+    fn check_symbol(&self, order: &Order) -> Result<(), BrokerError> {
+        match &self.symbols {
+            Some(symbols) if !symbols.contains(&order.symbol) => Err(BrokerError::UnknownSymbol),
+            _ => Ok(()),
+        }
+    }
+
+    /// Rejects `order` with `CooldownActive` if it opens or adds to a position in its symbol
+    /// within the `cooldown` of the last trade in that symbol.
+    fn check_cooldown(&self, order: &Order) -> Result<(), BrokerError> {
+        let cooldown = match self.cooldown {
+            Some(cooldown) if !order.reduce_only => cooldown,
+            _ => return Ok(()),
+        };
+        let amount = self.positions.get(&order.symbol).map_or(0.0, |position| position.amount);
+        let reduces = match order.side {
+            OrderSide::Buy => amount < 0.0,
+            OrderSide::Sell => amount > 0.0,
+        };
+        if reduces {
+            return Ok(());
+        }
+        match self.last_trades.get(&order.symbol) {
+            Some(datetime) if self.datetime < *datetime + cooldown => Err(BrokerError::CooldownActive),
+            _ => Ok(()),
+        }
+    }
+
+    /// Rejects `order` with `RiskLimitExceeded` if filling it at `price` for `commission`, both in
+    /// the account currency, would grow the gross exposure of the allocation in `slot` beyond its
+    /// weight of the equity. Longs and shorts count alike.
+    fn check_allocation(&self, slot: Option<usize>, order: &Order, price: f32, commission: f32) -> Result<(), BrokerError> {
+        let allocation = match slot.and_then(|slot| self.allocations.get(&slot)) {
+            Some(allocation) => allocation,
+            None => return Ok(()),
+        };
+        let signed_quantity = match order.side {
+            OrderSide::Buy => order.quantity,
+            OrderSide::Sell => -order.quantity,
+        };
+        let current = allocation.amounts.get(&order.symbol).copied().unwrap_or(0.0);
+        let new_amount = current + signed_quantity;
+        // Orders that shrink the allocation's position are always allowed.
+        if new_amount.abs() <= current.abs() {
+            return Ok(());
+        }
+        let exposure = self.allocation_exposure(allocation) + (new_amount.abs() - current.abs()) * price + commission;
+        if exposure > allocation.weight * self.get_equity() {
+            return Err(BrokerError::RiskLimitExceeded);
+        }
+        Ok(())
+    }
+
+    /// The gross value of the shares held through `allocation`, marked like `get_equity`.
+    fn allocation_exposure(&self, allocation: &Allocation) -> f32 {
+        allocation
+            .amounts
+            .iter()
+            .filter_map(|(symbol, amount)| {
+                let position = self.positions.get(symbol)?;
+                Some(amount.abs() * self.mark_price(position) * self.get_fx_rate(symbol))
+            })
+            .sum::<f32>()
+    }
+
+    /// Rejects `order` if filling it at `price` would grow a position beyond the
+    /// `max_position_fraction` of the equity, with the other positions marked at their latest
+    /// price, or open more than `max_open_positions`.
+    fn check_risk_limits(&self, order: &Order, price: f32) -> Result<(), BrokerError> {
+        let signed_quantity = match order.side {
+            OrderSide::Buy => order.quantity,
+            OrderSide::Sell => -order.quantity,
+        };
+        let current = self
+            .positions
+            .get(&order.symbol)
+            .map_or(0.0, |position| position.amount);
+        let new_amount = current + signed_quantity;
+        // Orders that shrink a position are always allowed.
+        if new_amount.abs() <= current.abs() {
+            return Ok(());
+        }
+
+        if let Some(fraction) = self.max_position_fraction {
+            if new_amount.abs() * price > fraction * self.equity_at(&order.symbol, price) {
+                return Err(BrokerError::RiskLimitExceeded);
+            }
+        }
+        if let Some(max_open_positions) = self.max_open_positions {
+            if current == 0.0 && self.positions.len() >= max_open_positions {
+                return Err(BrokerError::RiskLimitExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    /// The equity with `symbol` marked at `price` and other positions marked like `get_equity`.
+    fn equity_at(&self, symbol: &str, price: f32) -> f32 {
+        self.current_cash
+            + self
+                .positions
+                .values()
+                .map(|position| {
+                    if position.symbol == symbol {
+                        position.market_value(price)
+                    } else {
+                        self.position_value(position)
+                    }
+                })
+                .sum::<f32>()
+    }
+
+    /// Rejects `order` if filling it at `price` would increase the gross exposure
+    /// beyond `leverage` times the account's equity (its cash while no positions are open).
+    fn check_margin(&self, order: &Order, price: f32) -> Result<(), BrokerError> {
+        let signed_quantity = match order.side {
+            OrderSide::Buy => order.quantity,
+            OrderSide::Sell => -order.quantity,
+        };
+        let current = self
+            .positions
+            .get(&order.symbol)
+            .map_or(0.0, |position| position.amount);
+        let equity = self.equity_at(&order.symbol, price);
+        let others_exposure = self
+            .positions
+            .values()
+            .filter(|position| position.symbol != order.symbol)
+            .map(|position| self.position_value(position).abs())
+            .sum::<f32>();
+        let exposure = others_exposure + current.abs() * price;
+        let new_exposure = others_exposure + (current + signed_quantity).abs() * price;
+
+        // Orders that reduce exposure are always allowed so that positions can be closed.
+        if new_exposure > exposure && new_exposure > equity * self.leverage {
+            return Err(BrokerError::InsufficientMargin);
+        }
+        Ok(())
+    }
+
+    /// Rounds `price` to the nearest multiple of the tick size, if there is one.
+    fn round_to_tick(&self, price: f32) -> f32 {
+        match self.tick_size {
+            Some(tick) => (price / tick).round() * tick,
+            None => price,
+        }
+    }
+
+    /// Rounds the `limit` of an order on `side` to the tick size without loosening it:
+    /// down for a buy and up for a sell.
+    fn round_limit(&self, side: &OrderSide, limit: f32) -> f32 {
+        let tick = match self.tick_size {
+            Some(tick) => tick,
+            None => return limit,
+        };
+        let ticks = limit / tick;
+        // A limit that is already on a tick is kept, despite the float error of the division.
+        let ticks = match side {
+            _ if (ticks - ticks.round()).abs() < 0.01 => ticks.round(),
+            OrderSide::Buy => ticks.floor(),
+            OrderSide::Sell => ticks.ceil(),
+        };
+        ticks * tick
+    }
+
+    /// The type of `order` with its trigger prices rounded to the tick size: stops to the
+    /// nearest tick and limits as in `round_limit`.
+    fn round_levels(&self, order: &Order) -> OrderType {
+        let round = |price| self.round_to_tick(price);
+        let limit = |limit| self.round_limit(&order.side, limit);
+        match order.order_type {
+            OrderType::Limit(price) => OrderType::Limit(limit(price)),
+            OrderType::Stop(stop) => OrderType::Stop(round(stop)),
+            OrderType::StopLimit(stop, price) => OrderType::StopLimit(round(stop), limit(price)),
+            OrderType::LOC(price) => OrderType::LOC(limit(price)),
+            OrderType::LOO(price) => OrderType::LOO(limit(price)),
+            ref order_type => order_type.clone(),
+        }
+    }
+
+    /// Processes all the withstanding active_orders in the order book.
+    /// This function mainly handles the order processing logic, but the
+    /// actual order execution is performed in 'execute_order'.
+    /// Triggered stops are turned into market or limit orders in place, under the same id.
+    ///
+    /// # TODO: There needs to be some sense of time delay
+    fn process_active_orders(&mut self, ticker: &Ticker, symbol: Option<&str>) -> Result<(), BrokerError> {
+        let participation = self.max_volume_participation;
+        if participation.is_some() && ticker.volume == 0 && !self.active_orders.is_empty() {
+            self.log(format_args!("Halted: no volume at {}, orders stay active", ticker.datetime));
+            return Ok(());
+        }
+
+        // Orders are processed in the order they were submitted. Orders that callbacks submit
+        // or cancel along the way are left in the order book for the next ticker.
+        for (id, order) in self.active_orders.clone() {
+            if !self.active_orders.contains_key(&id) || symbol.is_some_and(|symbol| symbol != order.symbol) {
+                continue;
+            }
+            if participation.is_some_and(|fraction| order.quantity > fraction * ticker.volume as f32) {
+                continue;
+            }
+            match self.round_levels(&order) {
+                OrderType::Market => {
+                    self.execute_order(id, order, ticker)?;
+                    continue;
+                }
+                OrderType::Limit(limit) => match order.side {
+                    OrderSide::Buy => {
+                        if ticker.close <= limit {
+                            self.execute_order(id, order, ticker)?;
+                            continue;
                         }
                     }
                     OrderSide::Sell => {
                         if ticker.close >= limit {
-                            self.execute_order(order, ticker)?;
+                            self.execute_order(id, order, ticker)?;
                             continue;
                         }
                     }
@@ -243,32 +1845,34 @@ impl Broker {
                     OrderSide::Buy => {
                         // Buy Stop Order turns into a Market Buy Order when the price is above the stop price
                         if ticker.close >= stop {
-                            self.submit_order(id, Order {
+                            self.active_orders.insert(id, Order {
                                 symbol: order.symbol,
                                 quantity: order.quantity,
                                 side: OrderSide::Buy,
                                 order_type: OrderType::Market,
                                 execution: order.execution,
                                 datetime: self.get_datetime(),
+                                reduce_only: order.reduce_only,
                                 on_execute: order.on_execute,
                                 on_cancel: order.on_cancel,
-                            })?;
+                            });
                             continue;
                         }
                     }
                     OrderSide::Sell => {
                         // Sell Stop Order turns into a Market Sell Order when the price is below the stop price
                         if ticker.close <= stop {
-                            self.submit_order(id, Order {
+                            self.active_orders.insert(id, Order {
                                 symbol: order.symbol,
                                 quantity: order.quantity,
                                 side: OrderSide::Sell,
                                 order_type: OrderType::Market,
                                 execution: order.execution,
                                 datetime: self.get_datetime(),
+                                reduce_only: order.reduce_only,
                                 on_execute: order.on_execute,
                                 on_cancel: order.on_cancel,
-                            })?;
+                            });
                             continue;
                         }
                     }
@@ -277,83 +1881,103 @@ impl Broker {
                     OrderSide::Buy => {
                         // Buy Stop Order turns into a Limit Buy Order when the price is above the stop price and below the limit price
                         if ticker.close >= stop && ticker.close < limit {
-                            self.submit_order(id, Order {
+                            self.active_orders.insert(id, Order {
                                 symbol: order.symbol,
                                 quantity: order.quantity,
                                 side: OrderSide::Buy,
                                 order_type: OrderType::Limit(limit),
                                 execution: order.execution,
                                 datetime: self.get_datetime(),
+                                reduce_only: order.reduce_only,
                                 on_execute: order.on_execute,
                                 on_cancel: order.on_cancel,
-                            })?;
+                            });
                             continue;
                         }
                     }
                     OrderSide::Sell => {
                         // Sell Stop Order turns into a Limit Sell Order when the price is below the stop price and above the limit price
                         if ticker.close <= stop && ticker.close > limit {
-                            self.submit_order(id, Order {
+                            self.active_orders.insert(id, Order {
                                 symbol: order.symbol,
                                 quantity: order.quantity,
                                 side: OrderSide::Sell,
                                 order_type: OrderType::Limit(limit),
                                 execution: order.execution,
                                 datetime: self.get_datetime(),
+                                reduce_only: order.reduce_only,
                                 on_execute: order.on_execute,
                                 on_cancel: order.on_cancel,
-                            })?;
+                            });
                             continue;
                         }
                     }
                 },
                 OrderType::MOC => {
-                    if self.next_date() {
-                        if let Some(previous) = &self.previous_ticker.clone() {
-                            self.execute_order(order, previous)?;
-                            continue;
-                        }
+                    if let Some(close) = self.closing_ticker(ticker) {
+                        self.execute_order(id, order, &close)?;
+                        continue;
                     }
                 },
                 OrderType::MOO => {
-                    if self.next_date() {
-                        self.execute_order(order, ticker)?;
+                    if self.opens_session(ticker) {
+                        self.execute_order(id, order, ticker)?;
                         continue;
                     }
-                    todo!();
                 },
                 OrderType::LOC(limit) => {
-                    if self.next_date() {
-                        if let Some(previous) = &self.previous_ticker.clone() {
-                            match order.side {
-                                OrderSide::Buy => {
-                                    if ticker.close <= limit {
-                                        self.execute_order(order, previous)?;
-                                        continue;
-                                    }
+                    if let Some(close) = self.closing_ticker(ticker) {
+                        match order.side {
+                            OrderSide::Buy => {
+                                if close.close <= limit {
+                                    self.execute_order(id, order, &close)?;
+                                    continue;
                                 }
-                                OrderSide::Sell => {
-                                    if ticker.close >= limit {
-                                        self.execute_order(order, previous)?;
-                                        continue;
-                                    }
+                            }
+                            OrderSide::Sell => {
+                                if close.close >= limit {
+                                    self.execute_order(id, order, &close)?;
+                                    continue;
                                 }
                             }
                         }
-                    }   
+                    }
+                },
+                OrderType::TrailingStopPct(pct) => {
+                    let mark = match (self.trailing_marks.get(&id), &order.side) {
+                        (Some(mark), OrderSide::Sell) => mark.max(ticker.close),
+                        (Some(mark), OrderSide::Buy) => mark.min(ticker.close),
+                        (None, _) => ticker.close,
+                    };
+                    self.trailing_marks.insert(id, mark);
+                    let triggered = match order.side {
+                        // Sell Trailing Stop turns into a Market Sell Order when the price falls `pct` below its high
+                        OrderSide::Sell => ticker.close <= self.round_to_tick(mark * (1.0 - pct)),
+                        // Buy Trailing Stop turns into a Market Buy Order when the price rises `pct` above its low
+                        OrderSide::Buy => ticker.close >= self.round_to_tick(mark * (1.0 + pct)),
+                    };
+                    if triggered {
+                        self.trailing_marks.remove(&id);
+                        self.active_orders.insert(id, Order {
+                            order_type: OrderType::Market,
+                            datetime: self.get_datetime(),
+                            ..order
+                        });
+                        continue;
+                    }
                 },
                 OrderType::LOO(limit) => {
-                    if self.next_date() {
+                    if self.opens_session(ticker) {
                         match order.side {
                             OrderSide::Buy => {
                                 if ticker.close <= limit {
-                                    self.execute_order(order, ticker)?;
+                                    self.execute_order(id, order, ticker)?;
                                     continue;
                                 }
                             }
                             OrderSide::Sell => {
                                 if ticker.close >= limit {
-                                    self.execute_order(order, ticker)?;
+                                    self.execute_order(id, order, ticker)?;
                                     continue;
                                 }
                             }
@@ -361,17 +1985,19 @@ impl Broker {
                     }
                 },
             }
-
-            // This code will be executed if no order was executed.
-            // Otherwise, we skip over this block with the use of `continue`.
-            non_executed_active_orders.insert(id, order);
         }
 
-        self.active_orders = non_executed_active_orders;
-
         Ok(())
     }
 
+    /// Starts the clock at `datetime`, the datetime of the first ticker to come, unless the
+    /// broker was given a `start_datetime` or has already processed a ticker.
+    pub(crate) fn start_clock(&mut self, datetime: DateTime<Utc>) {
+        if self.start_datetime.is_none() && self.previous_ticker.is_none() && self.prices.is_empty() {
+            self.datetime = datetime;
+        }
+    }
+
     pub fn get_datetime(&self) -> DateTime<Utc> {
         self.datetime.clone()
     }
@@ -380,10 +2006,287 @@ impl Broker {
         self.current_cash
     }
 
+    pub fn get_initial_cash(&self) -> f32 {
+        self.initial_cash
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_leverage(&self) -> f32 {
+        self.leverage
+    }
+
+    pub fn get_commission(&self) -> &CommissionModel {
+        &self.commission
+    }
+
+    pub fn get_slippage(&self) -> f32 {
+        self.slippage
+    }
+
+    /// The latest order that was rejected when it came to execute, with the reason, or `None`
+    /// if no order has been rejected. `submit_order` returns its own rejections instead.
+    pub fn get_last_rejection(&self) -> Option<&Rejection> {
+        self.last_rejection.as_ref()
+    }
+
+    /// Returns every trade executed so far, in the order they were filled.
+    pub fn get_trades(&self) -> &[Trade] {
+        &self.trades
+    }
+
+    /// Writes every trade executed so far to a CSV file at `path`, as a fill log that can be
+    /// read back with `Series::<Trade>::from_csv` and re-applied with `replay`.
+    pub fn save_trades<P: AsRef<Path>>(&self, path: P) -> Result<(), csv::Error> {
+        let mut writer = csv::Writer::from_path(path)?;
+        for trade in &self.trades {
+            writer.serialize(trade)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Re-applies the `trades` of a fill log to the account, in order, at their recorded price,
+    /// commission and FX rate, without running any strategy or order logic. Replaying a run's trades into
+    /// a broker with the same initial cash reproduces its cash, positions and realized profit,
+    /// as long as the run had no interest, dividends or deposits, which are not trades.
+    pub fn replay(&mut self, trades: impl IntoIterator<Item = Trade>) {
+        for trade in trades {
+            let quantity = match trade.side {
+                OrderSide::Buy => trade.quantity,
+                OrderSide::Sell => -trade.quantity,
+            };
+            self.datetime = trade.datetime;
+            self.realized_pnl += self.apply_fill(&trade.symbol, quantity, trade.price) * trade.fx_rate;
+            self.current_cash -= quantity * trade.price * trade.fx_rate;
+            self.current_cash -= trade.commission * trade.fx_rate;
+            self.last_trades.insert(trade.symbol.clone(), trade.datetime);
+            self.trades.push(trade);
+        }
+    }
+
+    /// Captures the state of the account at the current ticker, e.g. for a dashboard.
+    pub fn snapshot(&self) -> AccountSnapshot {
+        let mut positions = self.positions.values().cloned().collect::<Vec<_>>();
+        positions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        let unrealized_pnl = positions
+            .iter()
+            .map(|position| position.unrealized_pnl(self.mark_price(position)) * self.get_fx_rate(&position.symbol))
+            .sum();
+        AccountSnapshot {
+            datetime: self.datetime,
+            cash: self.current_cash,
+            equity: self.get_equity(),
+            realized_pnl: self.realized_pnl,
+            unrealized_pnl,
+            positions,
+            active_orders: self.active_orders.len(),
+        }
+    }
+
+    /// The sum of the absolute values of every position, marked like `get_equity`.
+    fn gross_exposure(&self) -> f32 {
+        self.positions
+            .values()
+            .map(|position| self.position_value(position).abs())
+            .sum::<f32>()
+    }
+
+    /// The value of the shares that can still be bought at the latest close without breaching
+    /// the leverage limit, net of the commission and slippage that buying them would cost.
+    /// While no positions are open, this is the cash times the leverage, less costs.
+    pub fn buying_power(&self) -> f32 {
+        match &self.previous_ticker {
+            Some(ticker) => self.affordable_quantity(ticker.close) * ticker.close,
+            None => self.headroom().max(0.0),
+        }
+    }
+
+    /// The value that can still be committed without breaching the leverage limit,
+    /// nor the active allocation if there is one.
+    fn headroom(&self) -> f32 {
+        let headroom = self.get_equity() * self.leverage - self.gross_exposure();
+        match self.active_allocation.and_then(|slot| self.allocations.get(&slot)) {
+            Some(allocation) => headroom.min(allocation.weight * self.get_equity() - self.allocation_exposure(allocation)),
+            None => headroom,
+        }
+    }
+
+    /// The largest whole number of shares that can be bought at `price` once commission and
+    /// slippage are paid, without breaching the leverage limit.
+    pub fn max_shares(&self, price: f32) -> f32 {
+        self.affordable_quantity(price).floor()
+    }
+
+    /// Solves `quantity * fill + commission(quantity) <= headroom` for `quantity`,
+    /// where commission is affine in the quantity for every `CommissionModel`.
+    fn affordable_quantity(&self, price: f32) -> f32 {
+        let headroom = self.headroom();
+        let fill = slipped_price(&OrderSide::Buy, price, self.slippage);
+        let fixed = self.commission.commission(0.0, fill);
+        let per_share = self.commission.commission(1.0, fill) - fixed;
+        ((headroom - fixed) / (fill + per_share)).max(0.0)
+    }
+
+    /// The latest close of the position's symbol, or of the feed if it is not fed per symbol.
+    /// Before the first ticker, positions are marked at their entry price.
+    fn mark_price(&self, position: &Position) -> f32 {
+        self.prices
+            .get(&position.symbol)
+            .or(self.previous_ticker.as_ref())
+            .map_or(position.price, |ticker| ticker.close)
+    }
+
+    /// The market value of `position` in the account currency.
+    fn position_value(&self, position: &Position) -> f32 {
+        position.market_value(self.mark_price(position)) * self.get_fx_rate(&position.symbol)
+    }
+
+    /// Returns the value of the account: cash plus every position marked at the latest close.
+    /// Before the first ticker, positions are valued at their entry price.
+    pub fn get_equity(&self) -> f32 {
+        let positions_value = self
+            .positions
+            .values()
+            .map(|position| self.position_value(position))
+            .sum::<f32>();
+        self.current_cash + positions_value
+    }
+
+    /// Captures the account, e.g. to serialize it and resume the run later with `with_state`.
+    pub fn get_state(&self) -> BrokerState {
+        let mut positions = self.positions.values().cloned().collect::<Vec<_>>();
+        positions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        let mut lots = self.lots.clone().into_iter().collect::<Vec<_>>();
+        lots.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut canceled_orders = self.canceled_orders.clone().into_iter().collect::<Vec<_>>();
+        canceled_orders.sort_by_key(|(id, _)| *id);
+        let mut prices = self.prices.clone().into_iter().collect::<Vec<_>>();
+        prices.sort_by(|a, b| a.0.cmp(&b.0));
+        BrokerState {
+            name: self.name.clone(),
+            initial_cash: self.initial_cash,
+            datetime: self.datetime,
+            cash: self.current_cash,
+            realized_pnl: self.realized_pnl,
+            day_start_equity: self.day_start_equity,
+            halted: self.halted,
+            next_order_id: self.next_order_id,
+            positions,
+            lots,
+            active_orders: self.active_orders.clone().into_iter().collect(),
+            canceled_orders,
+            trailing_marks: self.trailing_marks.clone().into_iter().collect(),
+            trades: self.trades.clone(),
+            cash_flows: self.cash_flows.clone(),
+            previous_ticker: self.previous_ticker.clone(),
+            prices,
+        }
+    }
+
+    /// Replaces the account of this broker with `state`, keeping its configuration.
+    /// The broker continues as if it had processed the tickers that led to `state`.
+    pub fn with_state(mut self, state: BrokerState) -> Self {
+        self.name = state.name;
+        self.initial_cash = state.initial_cash;
+        self.datetime = state.datetime;
+        self.current_cash = state.cash;
+        self.realized_pnl = state.realized_pnl;
+        self.day_start_equity = state.day_start_equity;
+        self.halted = state.halted;
+        self.next_order_id = state.next_order_id;
+        self.trading_day = state.previous_ticker.as_ref().map(|ticker| ticker.datetime.date_naive());
+        self.positions = state.positions.into_iter().map(|position| (position.symbol.clone(), position)).collect();
+        self.lots = state.lots.into_iter().collect();
+        self.active_orders = state.active_orders.into_iter().collect();
+        self.canceled_orders = state.canceled_orders.into_iter().collect();
+        self.trailing_marks = state.trailing_marks.into_iter().collect();
+        self.order_allocations.clear();
+        self.last_trades = state.trades.iter().map(|trade| (trade.symbol.clone(), trade.datetime)).collect();
+        self.trades = state.trades;
+        self.cash_flows = state.cash_flows;
+        self.previous_ticker = state.previous_ticker;
+        self.prices = state.prices.into_iter().collect();
+        self
+    }
+
+    /// Restores a broker with the default configuration of `BrokerBuilder` from `state`.
+    /// Use `with_state` to restore onto a broker with a different configuration.
+    pub fn from_state(state: BrokerState) -> Self {
+        BrokerBuilder::new(&state.name).initial_cash(state.initial_cash).build().with_state(state)
+    }
+
+    /// Adds `amount` of cash to the account from outside of it, or withdraws it if negative,
+    /// e.g. to model regular contributions. Unlike returns, these flows are excluded from
+    /// `BacktestResult::twr`.
+    pub fn deposit(&mut self, amount: f32) {
+        self.current_cash += amount;
+        self.cash_flows.push(CashFlow {
+            amount,
+            datetime: self.datetime,
+        });
+    }
+
+    /// Every deposit and withdrawal, in the order they were made.
+    pub fn get_cash_flows(&self) -> &[CashFlow] {
+        &self.cash_flows
+    }
+
+    /// Returns `true` if the account holds a nonzero position in any symbol.
+    pub fn has_open_positions(&self) -> bool {
+        !self.positions.is_empty()
+    }
+
     pub fn get_position(&self, symbol: &str) -> Option<Position> {
         self.positions.get(symbol).cloned()
     }
 
+    /// The open lots of the position in `symbol`, oldest first.
+    /// Always empty unless the broker uses `Accounting::Lots`.
+    pub fn get_lots(&self, symbol: &str) -> &[Lot] {
+        self.lots.get(symbol).map_or(&[], |lots| lots.as_slice())
+    }
+
+    /// Returns `true` if `ticker` is the first ticker of a session, at which on-open orders fill.
+    fn opens_session(&self, ticker: &Ticker) -> bool {
+        let calendar = match &self.calendar {
+            Some(calendar) => calendar,
+            None => return self.next_date(),
+        };
+        let (date, time) = (ticker.datetime.date_naive(), ticker.datetime.time());
+        match calendar.session(date) {
+            Some((open, close)) => {
+                open <= time
+                    && time <= close
+                    && self.previous_ticker.as_ref().is_none_or(|previous| {
+                        previous.datetime.date_naive() != date || previous.datetime.time() < open
+                    })
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the ticker that closed a session, at which on-close orders fill, once it is known.
+    ///
+    /// With a calendar, this is `ticker` itself if it is the first ticker at or after the close.
+    /// Without one, it is the previous ticker once `ticker` begins a new trading day.
+    fn closing_ticker(&self, ticker: &Ticker) -> Option<Ticker> {
+        let calendar = match &self.calendar {
+            Some(calendar) => calendar,
+            None if self.next_date() => return self.previous_ticker.clone(),
+            None => return None,
+        };
+        let (date, time) = (ticker.datetime.date_naive(), ticker.datetime.time());
+        let (_, close) = calendar.session(date)?;
+        let first_after_close = time >= close
+            && self.previous_ticker.as_ref().is_none_or(|previous| {
+                previous.datetime.date_naive() != date || previous.datetime.time() < close
+            });
+        first_after_close.then(|| ticker.clone())
+    }
+
     /// Returns `true` if the current `Ticker` being processed is the beginning of a new trading day.
     fn next_date(&self) -> bool {
         if let Some(previous) = &self.previous_ticker {
@@ -391,4 +2294,1312 @@ impl Broker {
         }
         true
     }
-}
\ No newline at end of file
+}
+
+/// Fails with `InvalidPrice` if any price of `ticker` is NaN. Every comparison with NaN
+/// is false, so such a ticker would silently never trigger a limit or a stop.
+fn check_prices(ticker: &Ticker) -> BrokerResult<()> {
+    let prices = [ticker.open, ticker.high, ticker.low, ticker.close];
+    if prices.iter().any(|price| price.is_nan()) {
+        return Err(BrokerError::InvalidPrice);
+    }
+    Ok(())
+}
+
+/// Cancels the other leg of a bracket, which may already be gone.
+fn cancel_sibling(broker: &mut Broker, id: Option<OrderId>) -> Result<(), BrokerError> {
+    match id.map(|id| broker.cancel_order(id)) {
+        Some(Err(BrokerError::OrderIdNotFound)) | None => Ok(()),
+        Some(result) => result,
+    }
+}
+
+/// Applies `slippage` against the side of the order.
+pub(crate) fn slipped_price(side: &OrderSide, price: f32, slippage: f32) -> f32 {
+    match side {
+        OrderSide::Buy => price * (1.0 + slippage),
+        OrderSide::Sell => price * (1.0 - slippage),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::Nyse;
+    use chrono::TimeZone;
+
+    fn ticker(close: f32, day: i64) -> Ticker {
+        Ticker {
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 1000,
+            datetime: Utc.timestamp_opt(day * 86_400, 0).unwrap(),
+        }
+    }
+
+    fn market_order(side: OrderSide, quantity: f32) -> Order {
+        Order {
+            symbol: "AAPL".to_string(),
+            quantity,
+            side,
+            order_type: OrderType::Market,
+            datetime: Utc.timestamp_opt(0, 0).unwrap(),
+            execution: OrderExecutionStrategy::GTC,
+            reduce_only: false,
+            on_execute: None,
+            on_cancel: None,
+        }
+    }
+
+    #[test]
+    fn builder_defaults() {
+        let broker = BrokerBuilder::new("Defaults").build();
+        assert_eq!(broker.get_name(), "Defaults");
+        assert_eq!(broker.get_initial_cash(), 100_000.0);
+        assert_eq!(broker.get_cash(), 100_000.0);
+        assert_eq!(broker.get_commission(), &CommissionModel::Percentage(0.0));
+        assert_eq!(broker.get_slippage(), 0.0);
+        assert_eq!(broker.get_leverage(), 1.0);
+        assert!(!broker.exclusive_orders);
+        assert!(!broker.hedging);
+        assert!(broker.logging);
+    }
+
+    #[test]
+    fn new_delegates_to_builder() {
+        let broker = Broker::new("Delegate", 50_000.0, 0.01, 0.5, true, true);
+        let built = BrokerBuilder::new("Delegate")
+            .initial_cash(50_000.0)
+            .commission(0.01)
+            .margin(0.5)
+            .exclusive_orders(true)
+            .hedging(true)
+            .build();
+        assert_eq!(broker.to_string(), built.to_string());
+        assert_eq!(broker.get_leverage(), 2.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn builder_rejects_invalid_margin() {
+        BrokerBuilder::new("Invalid").margin(1.5).build();
+    }
+
+    #[test]
+    fn leverage_limits_gross_exposure() {
+        let broker = || BrokerBuilder::new("Leveraged").initial_cash(10_000.0).margin(0.5).build();
+
+        // 2x leverage allows exposure of up to twice the cash.
+        let mut within = broker();
+        within.submit_order(market_order(OrderSide::Buy, 200.0)).unwrap();
+        within.next(&ticker(100.0, 0)).unwrap();
+        assert_eq!(within.get_position("AAPL").unwrap().amount, 200.0);
+
+        // Adding to the position beyond the limit is rejected, but reducing it is not.
+        within.submit_order(market_order(OrderSide::Buy, 1.0)).unwrap();
+        assert!(matches!(within.next(&ticker(100.0, 1)), Err(BrokerError::InsufficientMargin)));
+        let mut reducing = broker();
+        reducing.submit_order(market_order(OrderSide::Buy, 200.0)).unwrap();
+        reducing.next(&ticker(100.0, 0)).unwrap();
+        reducing.submit_order(market_order(OrderSide::Sell, 100.0)).unwrap();
+        reducing.next(&ticker(100.0, 1)).unwrap();
+        assert_eq!(reducing.get_position("AAPL").unwrap().amount, 100.0);
+
+        let mut beyond = broker();
+        beyond.submit_order(market_order(OrderSide::Buy, 201.0)).unwrap();
+        assert!(matches!(beyond.next(&ticker(100.0, 0)), Err(BrokerError::InsufficientMargin)));
+        assert!(beyond.get_position("AAPL").is_none());
+        assert_eq!(beyond.get_cash(), 10_000.0);
+    }
+
+    #[test]
+    fn leverage_marks_other_positions() {
+        let mut broker = BrokerBuilder::new("Leveraged").initial_cash(10_000.0).margin(0.5).build();
+        broker.submit_order(order_for("AAPL", OrderSide::Buy, 50.0)).unwrap();
+        broker.next_symbol("AAPL", &ticker(100.0, 0)).unwrap();
+        broker.next_symbol("MSFT", &ticker(100.0, 0)).unwrap();
+
+        // Once AAPL halves, the equity is 7,500, so 2x leverage leaves room for 12,500 of MSFT
+        // next to the 2,500 of AAPL, not the 15,000 that AAPL's entry price would suggest.
+        broker.next_symbol("AAPL", &ticker(50.0, 1)).unwrap();
+        assert_eq!(broker.can_execute(&order_for("MSFT", OrderSide::Buy, 125.0)), Ok(()));
+        assert_eq!(
+            broker.can_execute(&order_for("MSFT", OrderSide::Buy, 126.0)),
+            Err(BrokerError::InsufficientMargin)
+        );
+    }
+
+    #[test]
+    fn submit_order_allocates_ids() {
+        let mut broker = BrokerBuilder::new("Ids").allow_short(true).build();
+        let first = broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        let second = broker.submit_order(market_order(OrderSide::Sell, 5.0)).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(broker.get_active_order(first).unwrap().quantity, 10.0);
+        assert_eq!(broker.get_active_order(second).unwrap().quantity, 5.0);
+
+        broker.next(&ticker(100.0, 0)).unwrap();
+        assert!(broker.get_active_order(first).is_none());
+        assert!(broker.get_active_order(second).is_none());
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, 5.0);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn submit_order_skips_supplied_ids() {
+        let mut broker = BrokerBuilder::new("Ids").build();
+        broker.submit_order_with_id(3, market_order(OrderSide::Buy, 10.0)).unwrap();
+        let id = broker.submit_order(market_order(OrderSide::Buy, 5.0)).unwrap();
+        assert_eq!(id, 4);
+        assert!(broker.get_active_order(3).is_some());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn duplicate_order_id_is_rejected() {
+        let mut broker = BrokerBuilder::new("Duplicate").build();
+        broker.submit_order_with_id(0, market_order(OrderSide::Buy, 10.0)).unwrap();
+        assert_eq!(
+            broker.submit_order_with_id(0, market_order(OrderSide::Buy, 20.0)),
+            Err(BrokerError::DuplicateOrderId)
+        );
+        assert_eq!(broker.get_active_order(0).unwrap().quantity, 10.0);
+
+        // Once cancelled, the id can be reused.
+        broker.cancel_order(0).unwrap();
+        broker.submit_order_with_id(0, market_order(OrderSide::Buy, 20.0)).unwrap();
+        assert_eq!(broker.get_active_order(0).unwrap().quantity, 20.0);
+    }
+
+    #[test]
+    fn can_execute_matches_execution() {
+        let mut broker = BrokerBuilder::new("Dry Run").initial_cash(10_000.0).build();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        let affordable = market_order(OrderSide::Buy, 100.0);
+        let over_budget = market_order(OrderSide::Buy, 101.0);
+        assert_eq!(broker.can_execute(&affordable), Ok(()));
+        assert_eq!(broker.can_execute(&over_budget), Err(BrokerError::InsufficientMargin));
+
+        // Checking an order has no side effects.
+        assert_eq!(broker.get_cash(), 10_000.0);
+        assert!(broker.get_position("AAPL").is_none());
+
+        broker.submit_order(over_budget).unwrap();
+        assert_eq!(broker.next(&ticker(100.0, 1)), Err(BrokerError::InsufficientMargin));
+    }
+
+    fn order_for(symbol: &str, side: OrderSide, quantity: f32) -> Order {
+        Order {
+            symbol: symbol.to_string(),
+            ..market_order(side, quantity)
+        }
+    }
+
+    #[test]
+    fn max_position_fraction_caps_symbol() {
+        let mut broker = BrokerBuilder::new("Capped")
+            .initial_cash(10_000.0)
+            .allow_short(true)
+            .max_position_fraction(0.25)
+            .build();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        assert_eq!(broker.can_execute(&market_order(OrderSide::Buy, 25.0)), Ok(()));
+        assert_eq!(
+            broker.can_execute(&market_order(OrderSide::Buy, 26.0)),
+            Err(BrokerError::RiskLimitExceeded)
+        );
+        assert_eq!(
+            broker.can_execute(&market_order(OrderSide::Sell, 26.0)),
+            Err(BrokerError::RiskLimitExceeded)
+        );
+
+        broker.submit_order(market_order(OrderSide::Buy, 25.0)).unwrap();
+        broker.next(&ticker(100.0, 1)).unwrap();
+        broker.submit_order(market_order(OrderSide::Buy, 1.0)).unwrap();
+        assert_eq!(broker.next(&ticker(100.0, 2)), Err(BrokerError::RiskLimitExceeded));
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, 25.0);
+    }
+
+    #[test]
+    fn allocation_limits_shorts() {
+        let mut broker = BrokerBuilder::new("Sleeve").initial_cash(10_000.0).allow_short(true).build();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        // A 40% slice may short 40 shares, but the 41st is rejected when it executes.
+        broker.enter_allocation(0, 0.4);
+        assert_eq!(broker.max_shares(100.0), 40.0);
+        broker.submit_order(market_order(OrderSide::Sell, 40.0)).unwrap();
+        broker.submit_order(market_order(OrderSide::Sell, 1.0)).unwrap();
+        broker.exit_allocation();
+        assert_eq!(broker.next(&ticker(100.0, 1)), Err(BrokerError::RiskLimitExceeded));
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, -40.0);
+
+        // Nor do the proceeds of the short make room to buy something else, but covering is fine.
+        broker.enter_allocation(0, 0.4);
+        assert_eq!(
+            broker.can_execute(&order_for("MSFT", OrderSide::Buy, 1.0)),
+            Err(BrokerError::RiskLimitExceeded)
+        );
+        assert_eq!(broker.can_execute(&market_order(OrderSide::Buy, 40.0)), Ok(()));
+    }
+
+    #[test]
+    fn max_position_fraction_marks_other_positions() {
+        let mut broker = BrokerBuilder::new("Capped")
+            .initial_cash(10_000.0)
+            .margin(0.5)
+            .max_position_fraction(0.5)
+            .build();
+        broker.submit_order(order_for("AAPL", OrderSide::Buy, 50.0)).unwrap();
+        broker.next_symbol("AAPL", &ticker(100.0, 0)).unwrap();
+        broker.next_symbol("MSFT", &ticker(100.0, 0)).unwrap();
+
+        // Once AAPL doubles, the equity is 15,000, so half of it buys 75 shares of MSFT, not 50.
+        broker.next_symbol("AAPL", &ticker(200.0, 1)).unwrap();
+        assert_eq!(broker.can_execute(&order_for("MSFT", OrderSide::Buy, 75.0)), Ok(()));
+        assert_eq!(
+            broker.can_execute(&order_for("MSFT", OrderSide::Buy, 76.0)),
+            Err(BrokerError::RiskLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn max_open_positions_caps_symbols() {
+        let mut broker = BrokerBuilder::new("Diversified")
+            .allow_short(true)
+            .max_open_positions(2)
+            .build();
+        broker.submit_order(order_for("AAPL", OrderSide::Buy, 10.0)).unwrap();
+        broker.submit_order(order_for("MSFT", OrderSide::Sell, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        // Adding to an open position is fine, opening a third one is not.
+        assert_eq!(broker.can_execute(&order_for("MSFT", OrderSide::Sell, 10.0)), Ok(()));
+        assert_eq!(
+            broker.can_execute(&order_for("GOOG", OrderSide::Buy, 10.0)),
+            Err(BrokerError::RiskLimitExceeded)
+        );
+
+        // Once a position is closed, another one can be opened.
+        broker.submit_order(order_for("AAPL", OrderSide::Sell, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 1)).unwrap();
+        broker.submit_order(order_for("GOOG", OrderSide::Buy, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 2)).unwrap();
+        assert!(broker.get_position("GOOG").is_some());
+    }
+
+    #[test]
+    fn daily_loss_limit_halts_until_next_day() {
+        let mut broker = BrokerBuilder::new("Circuit Breaker")
+            .initial_cash(10_000.0)
+            .daily_loss_limit(0.05)
+            .build();
+        broker.submit_order(market_order(OrderSide::Buy, 100.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        // A 4% loss is within the limit.
+        let mut intraday = ticker(96.0, 0);
+        intraday.datetime += Duration::hours(1);
+        broker.next(&intraday).unwrap();
+        assert!(broker.submit_order(market_order(OrderSide::Sell, 1.0)).is_ok());
+
+        // A 6% loss trips it for the rest of the day.
+        let mut intraday = ticker(94.0, 0);
+        intraday.datetime += Duration::hours(2);
+        broker.next(&intraday).unwrap();
+        let order = market_order(OrderSide::Sell, 99.0);
+        assert_eq!(broker.can_execute(&order), Err(BrokerError::DailyLossLimit));
+        assert_eq!(broker.submit_order(order.clone()), Err(BrokerError::DailyLossLimit));
+
+        broker.next(&ticker(94.0, 1)).unwrap();
+        assert!(broker.submit_order(order).is_ok());
+        broker.next(&ticker(94.0, 2)).unwrap();
+        assert!(broker.get_position("AAPL").is_none());
+    }
+
+    #[test]
+    fn margin_call_liquidates_positions() {
+        let mut broker = BrokerBuilder::new("Margin Call")
+            .initial_cash(10_000.0)
+            .margin(0.5)
+            .maintenance_margin(0.25)
+            .build();
+        broker.submit_order(market_order(OrderSide::Buy, 200.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        // Equity of $6,000 still covers 25% of the $16,000 exposure.
+        broker.next(&ticker(80.0, 1)).unwrap();
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, 200.0);
+
+        // Equity of $2,000 does not cover 25% of the $12,000 exposure.
+        assert!(matches!(broker.next(&ticker(60.0, 2)), Err(BrokerError::OutOfMoneyError)));
+        assert!(broker.get_position("AAPL").is_none());
+        assert_eq!(broker.get_cash(), 2_000.0);
+        assert_eq!(broker.get_equity(), 2_000.0);
+
+        let liquidation = broker.get_trades().last().unwrap();
+        assert!(matches!(liquidation.side, OrderSide::Sell));
+        assert_eq!(liquidation.quantity, 200.0);
+        assert_eq!(liquidation.price, 60.0);
+    }
+
+    #[test]
+    fn short_accrues_borrow_cost() {
+        // 3.65% a year on a $10,000 short is $1 a day.
+        let mut broker = BrokerBuilder::new("Short")
+            .initial_cash(10_000.0)
+            .allow_short(true)
+            .borrow_rate(0.0365)
+            .build();
+        broker.submit_order(market_order(OrderSide::Sell, 100.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        assert_eq!(broker.get_cash(), 20_000.0);
+
+        for day in 1..=3 {
+            broker.next(&ticker(100.0, day)).unwrap();
+        }
+        assert!((broker.get_cash() - 19_997.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn leveraged_long_accrues_margin_interest() {
+        // Half of the $20,000 long is borrowed: 3.65% a year on $10,000 is $1 a day.
+        let mut broker = BrokerBuilder::new("Leveraged Long")
+            .initial_cash(10_000.0)
+            .margin(0.5)
+            .margin_rate(0.0365)
+            .build();
+        broker.submit_order(market_order(OrderSide::Buy, 200.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        broker.next(&ticker(100.0, 1)).unwrap();
+        assert!((broker.get_cash() + 10_001.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn margin_interest_tracks_effr() {
+        let path = std::env::temp_dir().join(format!("backtester_broker_effr_{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "DATE,DFF\n1970-01-01,3.65\n1970-01-02,3.65\n1970-01-03,7.30\n1970-01-04,7.30\n",
+        )
+        .unwrap();
+
+        // Half of the $20,000 long is borrowed, so 3.65% costs $1 a day and 7.30% costs $2.
+        let mut broker = BrokerBuilder::new("EFFR")
+            .initial_cash(10_000.0)
+            .margin(0.5)
+            .margin_rate_indicator(Box::new(crate::indicators::EFFR::from_csv(&path)))
+            .build();
+        broker.submit_order(market_order(OrderSide::Buy, 200.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        let mut charges = Vec::new();
+        for day in 1..=3 {
+            let cash = broker.get_cash();
+            broker.next(&ticker(100.0, day)).unwrap();
+            charges.push(cash - broker.get_cash());
+        }
+        assert!((charges[0] - 1.0).abs() < 1e-2);
+        assert!((charges[1] - 1.0).abs() < 1e-2);
+        assert!((charges[2] - 2.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn execution_emits_structured_events() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut broker = BrokerBuilder::new("Events").commission_model(CommissionModel::Fixed(1.0)).build();
+        let sink = Rc::clone(&events);
+        broker.set_on_event(Box::new(move |event| sink.borrow_mut().push(event.to_string())));
+        let id = broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 1)).unwrap();
+
+        let executed = BrokerEvent::OrderExecuted {
+            id,
+            symbol: "AAPL".to_string(),
+            side: OrderSide::Buy,
+            quantity: 10.0,
+            price: 100.0,
+            commission: 1.0,
+            datetime: ticker(100.0, 1).datetime,
+        };
+        assert_eq!(
+            executed.to_string(),
+            "OrderExecuted id=0 symbol=AAPL side=Buy quantity=10 price=100 commission=1 datetime=1970-01-02T00:00:00+00:00"
+        );
+        assert_eq!(events.borrow().len(), 3);
+        assert!(events.borrow()[0].starts_with("OrderSubmitted id=0 symbol=AAPL side=Buy quantity=10 "));
+        assert_eq!(events.borrow()[1], executed.to_string());
+        assert_eq!(
+            events.borrow()[2],
+            "PositionChanged symbol=AAPL amount=10 price=100 datetime=1970-01-02T00:00:00+00:00"
+        );
+
+        // A triggered stop becomes a market order under the same id, without being submitted again.
+        let stop = broker
+            .submit_order(Order {
+                order_type: OrderType::Stop(95.0),
+                ..market_order(OrderSide::Sell, 10.0)
+            })
+            .unwrap();
+        broker.next(&ticker(94.0, 2)).unwrap();
+        broker.next(&ticker(94.0, 3)).unwrap();
+        let submitted = events.borrow().iter().filter(|event| event.starts_with("OrderSubmitted")).count();
+        assert_eq!(submitted, 2);
+        assert!(events.borrow()[4].starts_with(&format!("OrderExecuted id={} ", stop)));
+    }
+
+    #[test]
+    fn on_trade_receives_every_fill() {
+        let fills = Rc::new(RefCell::new(Vec::new()));
+        let mut broker = BrokerBuilder::new("Streaming").build();
+        let sink = Rc::clone(&fills);
+        broker.set_on_trade(Box::new(move |trade| sink.borrow_mut().push(trade.clone())));
+
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        broker.submit_order(market_order(OrderSide::Sell, 4.0)).unwrap();
+        broker.next(&ticker(105.0, 1)).unwrap();
+
+        assert_eq!(fills.borrow().len(), 2);
+        assert_eq!(fills.borrow().as_slice(), broker.get_trades());
+    }
+
+    #[test]
+    fn on_execute_captures_filled_quantity() {
+        let mut broker = BrokerBuilder::new("Stop Loss").build();
+        let mut entry = market_order(OrderSide::Buy, 10.0);
+        let quantity = entry.quantity;
+        entry.on_execute = Some(FillCallback::new(move |broker, _| {
+            let entry_price = broker.get_position("AAPL").unwrap().price;
+            broker.submit_order(Order {
+                order_type: OrderType::Stop(entry_price * 0.9),
+                ..market_order(OrderSide::Sell, quantity)
+            })?;
+            Ok(())
+        }));
+        let id = broker.submit_order(entry).unwrap();
+
+        broker.next(&ticker(100.0, 0)).unwrap();
+        let stop = broker.get_active_order(id + 1).unwrap();
+        assert_eq!(stop.quantity, 10.0);
+        assert!(matches!(stop.order_type, OrderType::Stop(price) if price == 90.0));
+
+        // The stop turns into a market order once triggered, which fills on the next ticker.
+        broker.next(&ticker(95.0, 1)).unwrap();
+        broker.next(&ticker(85.0, 2)).unwrap();
+        broker.next(&ticker(85.0, 3)).unwrap();
+        assert!(broker.get_position("AAPL").is_none());
+        assert!(broker.get_active_order(id + 1).is_none());
+    }
+
+    #[test]
+    fn on_execute_prices_stop_from_fill() {
+        let mut broker = BrokerBuilder::new("Percentage Stop").slippage(0.01).build();
+        let mut entry = market_order(OrderSide::Buy, 10.0);
+        entry.on_execute = Some(FillCallback::new(|broker, trade| {
+            broker.submit_order(Order {
+                order_type: OrderType::Stop(trade.price * 0.95),
+                ..market_order(OrderSide::Sell, trade.quantity)
+            })?;
+            Ok(())
+        }));
+        let id = broker.submit_order(entry).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        // Slippage fills the entry above the close, and the stop follows the fill.
+        let fill = broker.get_trades()[0].price;
+        assert!((fill - 101.0).abs() < 1e-4);
+        let stop = broker.get_active_order(id + 1).unwrap();
+        assert!(matches!(stop.order_type, OrderType::Stop(price) if price == fill * 0.95));
+    }
+
+    #[test]
+    fn naked_short_rejected_by_default() {
+        let mut broker = BrokerBuilder::new("Long Only").build();
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        // Selling the long position is fine, selling beyond it is not.
+        assert_eq!(broker.can_execute(&market_order(OrderSide::Sell, 10.0)), Ok(()));
+        broker.submit_order(market_order(OrderSide::Sell, 11.0)).unwrap();
+        assert_eq!(broker.next(&ticker(100.0, 1)), Err(BrokerError::ShortNotAllowed));
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, 10.0);
+    }
+
+    #[test]
+    fn naked_short_allowed_when_enabled() {
+        let mut broker = BrokerBuilder::new("Long Short").allow_short(true).build();
+        broker.submit_order(market_order(OrderSide::Sell, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, -10.0);
+    }
+
+    #[test]
+    fn calendar_sessions() {
+        let bar = |close: f32, (month, day, hour, minute)| Ticker {
+            datetime: Utc.with_ymd_and_hms(2023, month, day, hour, minute, 0).unwrap(),
+            ..ticker(close, 0)
+        };
+        let mut broker = BrokerBuilder::new("Sessions").calendar(Box::new(Nyse)).build();
+        broker.next(&bar(100.0, (7, 3, 10, 0))).unwrap();
+        broker
+            .submit_order(Order {
+                order_type: OrderType::MOC,
+                ..market_order(OrderSide::Buy, 10.0)
+            })
+            .unwrap();
+        broker.next(&bar(101.0, (7, 3, 15, 30))).unwrap();
+        assert!(broker.get_trades().is_empty());
+        broker.next(&bar(102.0, (7, 3, 16, 0))).unwrap();
+        assert_eq!(broker.get_trades()[0].price, 102.0);
+
+        // Independence Day, on which the exchange is closed.
+        broker
+            .submit_order(Order {
+                order_type: OrderType::MOO,
+                ..market_order(OrderSide::Sell, 10.0)
+            })
+            .unwrap();
+        broker.next(&bar(103.0, (7, 4, 9, 30))).unwrap();
+        broker.next(&bar(104.0, (7, 4, 16, 0))).unwrap();
+        assert_eq!(broker.get_trades().len(), 1);
+        broker.next(&bar(105.0, (7, 5, 9, 0))).unwrap(); // Pre-market
+        assert_eq!(broker.get_trades().len(), 1);
+        broker.next(&bar(106.0, (7, 5, 9, 30))).unwrap();
+        assert_eq!(broker.get_trades()[1].price, 106.0);
+    }
+
+    #[test]
+    fn repeated_datetime_is_not_a_new_session() {
+        let bar = |close: f32, (day, hour, minute)| Ticker {
+            datetime: Utc.with_ymd_and_hms(2023, 7, day, hour, minute, 0).unwrap(),
+            ..ticker(close, 0)
+        };
+        let mut broker = BrokerBuilder::new("Duplicates").calendar(Box::new(Nyse)).build();
+        broker.next(&bar(100.0, (5, 15, 0))).unwrap();
+        broker
+            .submit_order(Order {
+                order_type: OrderType::MOC,
+                ..market_order(OrderSide::Buy, 10.0)
+            })
+            .unwrap();
+        broker.next(&bar(101.0, (5, 16, 0))).unwrap();
+        broker
+            .submit_order(Order {
+                order_type: OrderType::MOC,
+                ..market_order(OrderSide::Buy, 10.0)
+            })
+            .unwrap();
+        // A corrected copy of the closing ticker does not close the session a second time.
+        broker.next(&bar(101.5, (5, 16, 0))).unwrap();
+        assert_eq!(broker.get_trades().len(), 1);
+        assert_eq!(broker.get_trades()[0].price, 101.0);
+        broker.next(&bar(102.0, (6, 16, 0))).unwrap();
+        assert_eq!(broker.get_trades()[1].price, 102.0);
+    }
+
+    #[test]
+    fn unknown_symbol_is_rejected() {
+        let mut broker = BrokerBuilder::new("Symbols").build();
+        broker.set_symbols(&["AAPL", "MSFT"]);
+        let order = order_for("TSLA", OrderSide::Buy, 10.0);
+        assert_eq!(broker.can_execute(&order), Err(BrokerError::UnknownSymbol));
+        assert_eq!(broker.submit_order(order), Err(BrokerError::UnknownSymbol));
+        assert_eq!(broker.get_active_orders().count(), 0);
+
+        // Nor can a valid order be replaced with one for an unknown symbol.
+        let id = broker.submit_order(order_for("AAPL", OrderSide::Buy, 10.0)).unwrap();
+        let replacement = order_for("TSLA", OrderSide::Buy, 10.0);
+        assert_eq!(broker.replace_order(id, replacement), Err(BrokerError::UnknownSymbol));
+        assert_eq!(broker.get_active_order(id).unwrap().symbol, "AAPL");
+        broker.cancel_order(id).unwrap();
+
+        // Symbols that have not been priced yet are still accepted.
+        broker.submit_order(order_for("MSFT", OrderSide::Buy, 10.0)).unwrap();
+        broker.next_symbol("AAPL", &ticker(100.0, 0)).unwrap();
+        broker.next_symbol("MSFT", &ticker(200.0, 0)).unwrap();
+        assert_eq!(broker.get_position("MSFT").unwrap().price, 200.0);
+    }
+
+    #[test]
+    fn next_symbol_marks_each_symbol() {
+        let mut broker = BrokerBuilder::new("Symbols").initial_cash(10_000.0).build();
+        broker.submit_order(order_for("AAPL", OrderSide::Buy, 10.0)).unwrap();
+        broker.submit_order(order_for("MSFT", OrderSide::Buy, 10.0)).unwrap();
+
+        // Only the orders for the ticker's own symbol fill.
+        broker.next_symbol("AAPL", &ticker(100.0, 0)).unwrap();
+        assert!(broker.get_position("MSFT").is_none());
+        broker.next_symbol("MSFT", &ticker(200.0, 0)).unwrap();
+        assert_eq!(broker.get_position("MSFT").unwrap().price, 200.0);
+        assert_eq!(broker.get_cash(), 10_000.0 - 1_000.0 - 2_000.0);
+
+        broker.next_symbol("AAPL", &ticker(110.0, 1)).unwrap();
+        assert_eq!(broker.get_equity(), 7_000.0 + 10.0 * 110.0 + 10.0 * 200.0);
+    }
+
+    #[test]
+    fn fractional_shares_disabled() {
+        let mut broker = BrokerBuilder::new("Whole Shares").allow_fractional(false).build();
+        broker.submit_order(market_order(OrderSide::Buy, 10.7)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        assert_eq!(broker.can_execute(&market_order(OrderSide::Buy, 0.5)), Err(BrokerError::FractionalQuantity));
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, 10.0);
+        assert_eq!(broker.get_trades()[0].quantity, 10.0);
+        assert_eq!(broker.get_cash(), 100_000.0 - 1_000.0);
+
+        broker.submit_order(market_order(OrderSide::Buy, 0.7)).unwrap();
+        assert_eq!(broker.next(&ticker(100.0, 1)), Err(BrokerError::FractionalQuantity));
+    }
+
+    #[test]
+    fn nan_price_is_rejected() {
+        let mut broker = BrokerBuilder::new("Dirty Data").build();
+        broker
+            .submit_order(Order {
+                order_type: OrderType::Limit(95.0),
+                ..market_order(OrderSide::Buy, 10.0)
+            })
+            .unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        assert_eq!(broker.next(&ticker(f32::NAN, 1)), Err(BrokerError::InvalidPrice));
+        assert_eq!(broker.next(&Ticker { low: f32::NAN, ..ticker(90.0, 1) }), Err(BrokerError::InvalidPrice));
+        assert!(broker.get_trades().is_empty());
+
+        // The bar is skipped, so the limit order still fills on clean data.
+        broker.next(&ticker(90.0, 2)).unwrap();
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, 10.0);
+    }
+
+    #[test]
+    fn zero_volume_blocks_fills() {
+        let bar = |close, day, volume| Ticker { volume, ..ticker(close, day) };
+        let mut broker = BrokerBuilder::new("Volume Limits").max_volume_participation(0.1).build();
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.next(&bar(100.0, 0, 0)).unwrap();
+        assert!(broker.get_trades().is_empty());
+        assert!(broker.get_active_order(0).is_some());
+
+        // 10 shares are more than 10% of 50, but not of 100.
+        broker.next(&bar(101.0, 1, 50)).unwrap();
+        assert!(broker.get_trades().is_empty());
+        broker.next(&bar(102.0, 2, 100)).unwrap();
+        assert_eq!(broker.get_trades()[0].price, 102.0);
+
+        // Without limits, zero-volume bars fill as before.
+        let mut broker = BrokerBuilder::new("No Limits").build();
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.next(&bar(100.0, 0, 0)).unwrap();
+        assert_eq!(broker.get_trades()[0].price, 100.0);
+    }
+
+    #[test]
+    fn tick_size_rounds_fills_and_triggers() {
+        let mut broker = BrokerBuilder::new("Ticks").tick_size(0.25).build();
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.next(&ticker(100.30, 0)).unwrap();
+        assert_eq!(broker.get_trades()[0].price, 100.25);
+
+        // A buy limit of 100.2 is rounded down to 100.0, which a close of 100.24 does not reach.
+        broker
+            .submit_order(Order {
+                order_type: OrderType::Limit(100.2),
+                ..market_order(OrderSide::Buy, 10.0)
+            })
+            .unwrap();
+        broker.next(&ticker(100.24, 1)).unwrap();
+        assert_eq!(broker.get_trades().len(), 1);
+        broker.next(&ticker(99.9, 2)).unwrap();
+        assert_eq!(broker.get_trades().len(), 2);
+        assert_eq!(broker.get_trades()[1].price, 100.0);
+
+        // A sell limit of 100.3 is rounded up to 100.5, and slipping a close of 100.6 by 1%
+        // does not fill it below that.
+        let mut broker = BrokerBuilder::new("Ticks").tick_size(0.25).slippage(0.01).allow_short(true).build();
+        broker
+            .submit_order(Order {
+                order_type: OrderType::Limit(100.3),
+                ..market_order(OrderSide::Sell, 10.0)
+            })
+            .unwrap();
+        broker.next(&ticker(100.4, 0)).unwrap();
+        assert!(broker.get_trades().is_empty());
+        broker.next(&ticker(100.6, 1)).unwrap();
+        assert_eq!(broker.get_trades()[0].price, 100.5);
+    }
+
+    #[test]
+    fn trailing_stop_pct_follows_the_high() {
+        let mut broker = BrokerBuilder::new("Trailing Stop").build();
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        let stop = broker
+            .submit_order(Order {
+                order_type: OrderType::TrailingStopPct(0.1),
+                ..market_order(OrderSide::Sell, 10.0)
+            })
+            .unwrap();
+        // Run up to 200, then retrace. A 10% trail from 200 fires at 180, not at 135 from 150.
+        for (day, close) in [100.0, 150.0, 200.0, 185.0, 181.0].into_iter().enumerate() {
+            broker.next(&ticker(close, day as i64)).unwrap();
+        }
+        assert!(matches!(broker.get_active_order(stop).unwrap().order_type, OrderType::TrailingStopPct(_)));
+        assert_eq!(broker.get_state().trailing_marks, vec![(stop, 200.0)]);
+
+        broker.next(&ticker(179.0, 5)).unwrap();
+        assert!(matches!(broker.get_active_order(stop).unwrap().order_type, OrderType::Market));
+        broker.next(&ticker(178.0, 6)).unwrap();
+        assert_eq!(broker.get_trades().len(), 2);
+        assert_eq!(broker.get_trades()[1].price, 178.0);
+        assert!(broker.get_state().trailing_marks.is_empty());
+    }
+
+    #[test]
+    fn replay_fill_log() {
+        let broker = || BrokerBuilder::new("Replay").commission_model(CommissionModel::Fixed(1.0)).slippage(0.001).build();
+        let mut recorded = broker();
+        for (day, (side, quantity)) in [(OrderSide::Buy, 10.0), (OrderSide::Buy, 5.0), (OrderSide::Sell, 12.0)].into_iter().enumerate() {
+            recorded.submit_order(market_order(side, quantity)).unwrap();
+            recorded.next(&ticker(100.0 + 3.0 * day as f32, day as i64)).unwrap();
+        }
+        recorded.next(&ticker(97.0, 3)).unwrap();
+        assert_eq!(recorded.get_trades().len(), 3);
+
+        let path = std::env::temp_dir().join(format!("backtester_fill_log_{}.csv", std::process::id()));
+        recorded.save_trades(&path).unwrap();
+        let fill_log = crate::series::Series::<Trade>::from_csv(&path)
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut replayed = broker();
+        replayed.replay(fill_log);
+        assert_eq!(replayed.get_cash(), recorded.get_cash());
+        assert_eq!(replayed.get_position("AAPL"), recorded.get_position("AAPL"));
+        assert_eq!(replayed.get_state().realized_pnl, recorded.get_state().realized_pnl);
+        assert_eq!(replayed.get_trades(), recorded.get_trades());
+    }
+
+    #[test]
+    fn maker_rebate_and_taker_fee() {
+        let mut broker = BrokerBuilder::new("Maker Taker")
+            .commission_model(CommissionModel::MakerTaker { maker: -0.001, taker: 0.002 })
+            .build();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        broker
+            .submit_order(Order {
+                order_type: OrderType::Limit(95.0),
+                ..market_order(OrderSide::Buy, 10.0)
+            })
+            .unwrap();
+        broker.next(&ticker(98.0, 1)).unwrap();
+        assert!(broker.get_trades().is_empty());
+        // The limit rested above the market for a ticker, so it made liquidity and earns a rebate.
+        broker.next(&ticker(95.0, 2)).unwrap();
+        assert!((broker.get_trades()[0].commission + 0.95).abs() < 1e-4);
+
+        broker.submit_order(market_order(OrderSide::Sell, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 3)).unwrap();
+        assert!((broker.get_trades()[1].commission - 2.0).abs() < 1e-4);
+
+        // A limit that the market already reached fills immediately, taking liquidity.
+        broker
+            .submit_order(Order {
+                order_type: OrderType::Limit(105.0),
+                ..market_order(OrderSide::Buy, 10.0)
+            })
+            .unwrap();
+        broker.next(&ticker(101.0, 4)).unwrap();
+        assert!((broker.get_trades()[2].commission - 2.02).abs() < 1e-4);
+    }
+
+    #[test]
+    fn can_execute_agrees_with_execution_under_fx() {
+        let mut broker = BrokerBuilder::new("EUR Listing")
+            .initial_cash(10_000.0)
+            .fx_rate("SAP", 1.1)
+            .abort_on_rejection(false)
+            .build();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        // 95 shares at 100 EUR cost 10450 USD, more than the account holds, while 90 cost 9900 USD.
+        for (day, quantity) in [(1, 95.0), (2, 90.0)] {
+            let order = Order {
+                symbol: "SAP".to_string(),
+                ..market_order(OrderSide::Buy, quantity)
+            };
+            let check = broker.can_execute(&order);
+            let trades = broker.get_trades().len();
+            broker.submit_order(order).unwrap();
+            broker.next(&ticker(100.0, day)).unwrap();
+            match check {
+                Ok(()) => assert_eq!(broker.get_trades().len(), trades + 1),
+                Err(reason) => {
+                    assert_eq!(broker.get_trades().len(), trades);
+                    assert_eq!(broker.get_last_rejection().unwrap().reason, reason);
+                }
+            }
+        }
+        assert!(broker.get_last_rejection().is_some());
+        assert_eq!(broker.get_position("SAP").unwrap().amount, 90.0);
+    }
+
+    #[test]
+    fn fx_rate_converts_fills_to_account_currency() {
+        let mut broker = BrokerBuilder::new("EUR Listing")
+            .initial_cash(10_000.0)
+            .commission_model(CommissionModel::Percentage(0.001))
+            .fx_rate("SAP", 1.1)
+            .build();
+        broker
+            .submit_order(Order {
+                symbol: "SAP".to_string(),
+                ..market_order(OrderSide::Buy, 10.0)
+            })
+            .unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        // 1000 EUR of shares and 1 EUR of commission cost 1101.1 USD.
+        let trade = &broker.get_trades()[0];
+        assert_eq!((trade.price, trade.commission), (100.0, 1.0));
+        assert!((broker.get_cash() - (10_000.0 - 1101.1)).abs() < 1e-2);
+        assert!((broker.get_equity() - (10_000.0 - 1.1)).abs() < 1e-2);
+
+        // The position is revalued when the euro strengthens.
+        broker.set_fx_rate("SAP", 1.2);
+        assert!((broker.get_equity() - (10_000.0 + 98.9)).abs() < 1e-2);
+
+        // Dividends are paid in the symbol's currency too.
+        let cash = broker.get_cash();
+        broker.add_corporate_actions(vec![CorporateAction::Dividend {
+            symbol: "SAP".to_string(),
+            date: NaiveDate::from_ymd_opt(1970, 1, 2).unwrap(),
+            per_share: 1.0,
+        }]);
+        broker.next(&ticker(100.0, 1)).unwrap();
+        assert!((broker.get_cash() - (cash + 12.0)).abs() < 1e-2);
+
+        // Selling at the entry price realizes no profit, although the stronger euro returns more cash.
+        broker
+            .submit_order(Order {
+                symbol: "SAP".to_string(),
+                ..market_order(OrderSide::Sell, 10.0)
+            })
+            .unwrap();
+        broker.next(&ticker(100.0, 2)).unwrap();
+        assert_eq!(broker.snapshot().realized_pnl, 0.0);
+        assert!(broker.get_cash() > 10_000.0 + 98.9);
+    }
+
+    #[test]
+    fn cooldown_blocks_rapid_entries() {
+        let mut broker = BrokerBuilder::new("Cooldown").cooldown(Duration::days(3)).build();
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        assert_eq!(broker.get_trades().len(), 1);
+
+        // Adding to the position is an entry, but closing part of it is not.
+        assert_eq!(broker.submit_order(market_order(OrderSide::Buy, 10.0)).err(), Some(BrokerError::CooldownActive));
+        let exit = broker
+            .submit_order(Order {
+                order_type: OrderType::Limit(150.0),
+                ..market_order(OrderSide::Sell, 5.0)
+            })
+            .unwrap();
+        assert_eq!(broker.replace_order(exit, market_order(OrderSide::Buy, 10.0)), Err(BrokerError::CooldownActive));
+        broker.cancel_order(exit).unwrap();
+        assert!(broker.submit_order(market_order(OrderSide::Sell, 5.0)).is_ok());
+        broker.next(&ticker(100.0, 1)).unwrap();
+        broker.next(&ticker(100.0, 3)).unwrap();
+        assert_eq!(broker.submit_order(market_order(OrderSide::Buy, 10.0)).err(), Some(BrokerError::CooldownActive));
+
+        // Three days after the sell on day 1.
+        broker.next(&ticker(100.0, 4)).unwrap();
+        assert!(broker.submit_order(market_order(OrderSide::Buy, 10.0)).is_ok());
+    }
+
+    #[test]
+    fn cooldown_applies_at_execution() {
+        let mut broker = BrokerBuilder::new("Cooldown").cooldown(Duration::days(3)).abort_on_rejection(false).build();
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        let resting = broker
+            .submit_order(Order {
+                order_type: OrderType::Limit(90.0),
+                ..market_order(OrderSide::Buy, 10.0)
+            })
+            .unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        // The second entry on the same ticker comes within the cooldown of the first.
+        assert_eq!(broker.get_trades().len(), 1);
+        assert_eq!(broker.get_last_rejection().unwrap().reason, BrokerError::CooldownActive);
+
+        // So does the limit order resting since before the first entry.
+        broker.next(&ticker(85.0, 1)).unwrap();
+        assert_eq!(broker.get_trades().len(), 1);
+        assert_eq!(broker.get_last_rejection().unwrap().id, resting);
+        assert_eq!(broker.get_last_rejection().unwrap().reason, BrokerError::CooldownActive);
+    }
+
+    #[test]
+    fn replace_order_keeps_id_and_place() {
+        let limit = |price| Order {
+            order_type: OrderType::Limit(price),
+            ..market_order(OrderSide::Buy, 10.0)
+        };
+        let mut broker = BrokerBuilder::new("Replace").build();
+        let first = broker.submit_order(limit(90.0)).unwrap();
+        let second = broker.submit_order(limit(90.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        assert!(broker.get_trades().is_empty());
+
+        broker.replace_order(first, limit(98.0)).unwrap();
+        let ids = broker.get_active_orders().map(|(id, _)| *id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![first, second]);
+        assert_eq!(broker.replace_order(42, limit(98.0)), Err(BrokerError::OrderIdNotFound));
+
+        // Only the replaced order's new price is reached.
+        broker.next(&ticker(97.0, 1)).unwrap();
+        assert_eq!(broker.get_trades().len(), 1);
+        assert_eq!(broker.get_trades()[0].price, 97.0);
+        assert!(!broker.has_active_order(first));
+        assert!(broker.has_active_order(second));
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let mut broker = BrokerBuilder::new("Checkpoint").allow_short(true).build();
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.submit_order(Order { symbol: "MSFT".to_string(), ..market_order(OrderSide::Sell, 5.0) }).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        let limit = broker
+            .submit_order(Order { order_type: OrderType::Limit(80.0), ..market_order(OrderSide::Buy, 1.0) })
+            .unwrap();
+        let canceled = broker.submit_order(market_order(OrderSide::Buy, 1.0)).unwrap();
+        broker.cancel_order(canceled).unwrap();
+
+        let json = serde_json::to_string(&broker.get_state()).unwrap();
+        let state: BrokerState = serde_json::from_str(&json).unwrap();
+        let mut restored = BrokerBuilder::new("Other").allow_short(true).build().with_state(state.clone());
+        assert_eq!(restored.get_cash(), broker.get_cash());
+        assert_eq!(restored.get_position("AAPL"), broker.get_position("AAPL"));
+        assert_eq!(restored.get_position("MSFT"), broker.get_position("MSFT"));
+        assert_eq!(restored.snapshot(), broker.snapshot());
+        assert!(restored.has_active_order(limit));
+        assert_eq!(state.canceled_orders.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![canceled]);
+
+        // Both continue identically, and new orders do not reuse ids.
+        for broker in [&mut broker, &mut restored] {
+            broker.next(&ticker(79.0, 1)).unwrap();
+            assert_eq!(broker.submit_order(market_order(OrderSide::Sell, 1.0)), Ok(canceled + 1));
+        }
+        assert_eq!(restored.get_equity(), broker.get_equity());
+        assert_eq!(restored.get_trades().len(), 3);
+        assert_eq!(Broker::from_state(state).get_cash(), 100_000.0 - 1_000.0 + 500.0);
+    }
+
+    #[test]
+    fn flatten_all_closes_fractional_position_after_split() {
+        let mut broker = BrokerBuilder::new("Whole Shares").allow_fractional(false).build();
+        broker.submit_order(market_order(OrderSide::Buy, 3.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        // A 3:2 split leaves 4.5 shares.
+        broker.add_corporate_actions(vec![CorporateAction::Split {
+            symbol: "AAPL".to_string(),
+            date: NaiveDate::from_ymd_opt(1970, 1, 2).unwrap(),
+            ratio: 1.5,
+        }]);
+        broker.next(&ticker(70.0, 1)).unwrap();
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, 4.5);
+
+        broker.flatten_all(&ticker(70.0, 1)).unwrap();
+        assert!(!broker.has_open_positions());
+        assert_eq!(broker.get_trades()[1].quantity, 4.5);
+        assert_eq!(broker.get_cash(), 100_000.0 - 300.0 + 4.5 * 70.0);
+    }
+
+    #[test]
+    fn flatten_all_closes_every_position() {
+        let mut broker = BrokerBuilder::new("Flatten").allow_short(true).build();
+        broker.flatten_all(&ticker(100.0, 0)).unwrap();
+        assert!(broker.get_trades().is_empty());
+
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.submit_order(Order { symbol: "MSFT".to_string(), ..market_order(OrderSide::Sell, 5.0) }).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        assert_eq!(broker.get_trades().len(), 2);
+
+        broker.flatten_all(&ticker(110.0, 1)).unwrap();
+        assert!(!broker.has_open_positions());
+        let closing = broker.get_trades()[2..]
+            .iter()
+            .map(|trade| (trade.symbol.as_str(), trade.side.clone(), trade.quantity, trade.price))
+            .collect::<Vec<_>>();
+        assert_eq!(closing, vec![("AAPL", OrderSide::Sell, 10.0, 110.0), ("MSFT", OrderSide::Buy, 5.0, 110.0)]);
+        // +100 on the long and -50 on the short.
+        assert_eq!(broker.get_cash(), 100_000.0 + 100.0 - 50.0);
+        assert_eq!(broker.get_equity(), broker.get_cash());
+    }
+
+    #[test]
+    fn dividend_and_split() {
+        let date = |day| NaiveDate::from_ymd_opt(1970, 1, day).unwrap();
+        let mut broker = BrokerBuilder::new("Corporate Actions").initial_cash(10_000.0).build();
+        broker.add_corporate_actions(vec![
+            CorporateAction::Split { symbol: "AAPL".to_string(), date: date(4), ratio: 2.0 },
+            CorporateAction::Dividend { symbol: "AAPL".to_string(), date: date(3), per_share: 1.0 },
+            CorporateAction::Dividend { symbol: "MSFT".to_string(), date: date(3), per_share: 5.0 },
+        ]);
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        broker.next(&ticker(100.0, 1)).unwrap();
+        assert_eq!(broker.get_cash(), 9_000.0);
+
+        broker.next(&ticker(99.0, 2)).unwrap();
+        assert_eq!(broker.get_cash(), 9_010.0);
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, 10.0);
+
+        broker.next(&ticker(50.0, 3)).unwrap();
+        let position = broker.get_position("AAPL").unwrap();
+        assert_eq!((position.amount, position.price), (20.0, 50.0));
+        assert_eq!(broker.get_equity(), 9_010.0 + 20.0 * 50.0);
+    }
+
+    #[test]
+    fn lot_accounting() {
+        let accountings = vec![
+            (Accounting::Averaged, 10.0 * (130.0 - 110.0), 110.0),
+            (Accounting::Lots(LotMatching::Fifo), 10.0 * (130.0 - 100.0), 120.0),
+            (Accounting::Lots(LotMatching::Lifo), 10.0 * (130.0 - 120.0), 100.0),
+        ];
+        for (accounting, realized_pnl, entry) in accountings {
+            let mut broker = BrokerBuilder::new("Lots").accounting(accounting).build();
+            for (side, quantity, close) in [(OrderSide::Buy, 10.0, 100.0), (OrderSide::Buy, 10.0, 120.0), (OrderSide::Sell, 10.0, 130.0)] {
+                broker.submit_order(market_order(side, quantity)).unwrap();
+                broker.next(&ticker(close, 0)).unwrap();
+            }
+
+            let snapshot = broker.snapshot();
+            assert_eq!(snapshot.realized_pnl, realized_pnl);
+            assert_eq!(snapshot.positions[0].amount, 10.0);
+            assert_eq!(snapshot.positions[0].price, entry);
+            if accounting == Accounting::Averaged {
+                assert!(broker.get_lots("AAPL").is_empty());
+            } else {
+                assert_eq!(broker.get_lots("AAPL"), &[Lot { amount: 10.0, price: entry }]);
+            }
+        }
+    }
+
+    #[test]
+    fn snapshot_after_trades() {
+        let mut broker = BrokerBuilder::new("Snapshot").initial_cash(10_000.0).build();
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        broker.submit_order(market_order(OrderSide::Sell, 4.0)).unwrap();
+        broker.next(&ticker(110.0, 1)).unwrap();
+        broker
+            .submit_order(Order {
+                order_type: OrderType::Limit(50.0),
+                ..market_order(OrderSide::Buy, 1.0)
+            })
+            .unwrap();
+        broker.next(&ticker(120.0, 2)).unwrap();
+
+        let snapshot = broker.snapshot();
+        assert_eq!(snapshot.datetime, ticker(120.0, 2).datetime);
+        assert_eq!(snapshot.cash, 10_000.0 - 1_000.0 + 440.0);
+        assert_eq!(snapshot.equity, 9_440.0 + 6.0 * 120.0);
+        assert_eq!(snapshot.realized_pnl, 4.0 * 10.0);
+        assert_eq!(snapshot.unrealized_pnl, 6.0 * 20.0);
+        assert_eq!(snapshot.positions.len(), 1);
+        assert_eq!(snapshot.positions[0].amount, 6.0);
+        assert_eq!(snapshot.positions[0].price, 100.0);
+        assert_eq!(snapshot.active_orders, 1);
+    }
+
+    #[test]
+    fn reversing_a_position_realizes_the_closed_part() {
+        let mut broker = BrokerBuilder::new("Reversal").allow_short(true).build();
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        broker.submit_order(market_order(OrderSide::Sell, 15.0)).unwrap();
+        broker.next(&ticker(90.0, 1)).unwrap();
+
+        let position = broker.get_position("AAPL").unwrap();
+        assert_eq!(position.amount, -5.0);
+        assert_eq!(position.price, 90.0);
+        assert_eq!(broker.snapshot().realized_pnl, -100.0);
+
+        // Covering the short closes the position entirely.
+        broker.submit_order(market_order(OrderSide::Buy, 5.0)).unwrap();
+        broker.next(&ticker(80.0, 2)).unwrap();
+        assert!(broker.get_position("AAPL").is_none());
+        assert_eq!(broker.snapshot().realized_pnl, -50.0);
+    }
+
+    #[test]
+    fn fill_models() {
+        let bar = Ticker {
+            open: 100.0,
+            high: 110.0,
+            low: 96.0,
+            close: 105.0,
+            ..ticker(0.0, 0)
+        };
+        let fills = [
+            (Box::new(CloseFill) as Box<dyn FillModel>, 105.0),
+            (Box::new(OpenFill), 100.0),
+            (Box::new(MidpointFill), 103.0),
+            (Box::new(OhlcAverageFill), 102.75),
+        ];
+        for (fill_model, expected) in fills {
+            let mut broker = BrokerBuilder::new("Fills").fill_model(fill_model).build();
+            broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+            broker.next(&bar).unwrap();
+            assert_eq!(broker.get_trades()[0].price, expected);
+            assert_eq!(broker.get_cash(), 100_000.0 - 10.0 * expected);
+        }
+    }
+
+    #[test]
+    fn reduce_only_never_flips_position() {
+        let mut broker = BrokerBuilder::new("Reduce Only").initial_cash(100_000.0).build();
+        broker.submit_order(market_order(OrderSide::Buy, 150.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        let order = Order {
+            reduce_only: true,
+            ..market_order(OrderSide::Sell, 200.0)
+        };
+        assert_eq!(broker.can_execute(&order), Ok(()));
+        broker.submit_order(order.clone()).unwrap();
+        broker.next(&ticker(100.0, 1)).unwrap();
+        assert!(broker.get_position("AAPL").is_none());
+        assert_eq!(broker.get_trades().last().unwrap().quantity, 150.0);
+        assert_eq!(broker.get_cash(), 100_000.0);
+
+        // With no position left to reduce, the order does nothing.
+        broker.submit_order(order).unwrap();
+        broker.next(&ticker(100.0, 2)).unwrap();
+        assert!(broker.get_position("AAPL").is_none());
+        assert_eq!(broker.get_trades().len(), 2);
+    }
+
+    #[test]
+    fn percentage_stop_loss() {
+        let mut broker = BrokerBuilder::new("Stop Loss").allow_short(true).build();
+        let long = broker.submit_stop_loss("AAPL", OrderSide::Buy, 10.0, 0.05).unwrap();
+        let short = broker.submit_stop_loss("MSFT", OrderSide::Sell, 10.0, 0.05).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+
+        for (entry, side, expected) in [(long, OrderSide::Sell, 95.0), (short, OrderSide::Buy, 105.0)] {
+            let stop = broker.get_active_order(entry + 2).unwrap();
+            assert_eq!((stop.side.clone(), stop.quantity), (side, 10.0));
+            assert!(matches!(stop.order_type, OrderType::Stop(price) if (price - expected).abs() < 1e-4));
+        }
+    }
+
+    #[test]
+    fn bracket_take_profit_cancels_stop() {
+        let mut broker = BrokerBuilder::new("Bracket").initial_cash(100_000.0).build();
+        let entry = broker.submit_bracket(market_order(OrderSide::Buy, 10.0), 90.0, 110.0).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        assert!(broker.get_active_order(entry).is_none());
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, 10.0);
+
+        let stop = broker.get_active_order(entry + 1).unwrap();
+        assert!(matches!(stop.order_type, OrderType::Stop(price) if price == 90.0));
+        assert_eq!((stop.side.clone(), stop.quantity), (OrderSide::Sell, 10.0));
+        let take_profit = broker.get_active_order(entry + 2).unwrap();
+        assert!(matches!(take_profit.order_type, OrderType::Limit(price) if price == 110.0));
+        assert_eq!((take_profit.side.clone(), take_profit.quantity), (OrderSide::Sell, 10.0));
+
+        broker.next(&ticker(111.0, 1)).unwrap();
+        assert!(broker.get_position("AAPL").is_none());
+        assert!(broker.get_active_order(entry + 1).is_none());
+        assert!(broker.get_active_order(entry + 2).is_none());
+        assert_eq!(broker.get_trades().len(), 2);
+    }
+
+    #[test]
+    fn competing_orders_fill_in_submission_order() {
+        for _ in 0..20 {
+            let mut broker = BrokerBuilder::new("Competing").initial_cash(1_000.0).build();
+            let ids = [6.0, 5.0, 4.0]
+                .into_iter()
+                .map(|quantity| broker.submit_order(market_order(OrderSide::Buy, quantity)).unwrap())
+                .collect::<Vec<_>>();
+
+            // The first order leaves too little cash for the second, which is rejected.
+            assert_eq!(broker.next(&ticker(100.0, 0)), Err(BrokerError::InsufficientMargin));
+            assert!(broker.get_active_order(ids[0]).is_none());
+            assert!(broker.get_active_order(ids[1]).is_none());
+            assert!(broker.get_active_order(ids[2]).is_some());
+
+            broker.next(&ticker(100.0, 1)).unwrap();
+            let quantities = broker.get_trades().iter().map(|trade| trade.quantity).collect::<Vec<_>>();
+            assert_eq!(quantities, vec![6.0, 4.0]);
+        }
+    }
+
+    #[test]
+    fn max_shares_leaves_cash_for_costs() {
+        let models = [
+            CommissionModel::Percentage(0.01),
+            CommissionModel::PerShare(0.05),
+            CommissionModel::Fixed(9.99),
+            CommissionModel::Combined { fixed: 1.0, per_share: 0.01, pct: 0.001 },
+        ];
+        for model in models {
+            let mut broker = BrokerBuilder::new("All In")
+                .initial_cash(10_000.0)
+                .commission_model(model.clone())
+                .slippage(0.001)
+                .build();
+            broker.next(&ticker(33.0, 0)).unwrap();
+
+            let shares = broker.max_shares(33.0);
+            assert!(shares > 0.0);
+            assert!(broker.buying_power() < 10_000.0);
+            assert!(broker.buying_power() >= shares * 33.0);
+
+            broker.submit_order(market_order(OrderSide::Buy, shares)).unwrap();
+            broker.next(&ticker(33.0, 1)).unwrap();
+            assert!(broker.get_cash() >= 0.0, "{:?} left {}", model, broker.get_cash());
+
+            // One more share would not have been affordable.
+            let fill = slipped_price(&OrderSide::Buy, 33.0, 0.001);
+            let cost = (shares + 1.0) * fill + model.commission(shares + 1.0, fill);
+            assert!(cost > 10_000.0, "{:?} could afford {}", model, shares + 1.0);
+        }
+    }
+
+    #[test]
+    fn commission_models() {
+        assert_eq!(CommissionModel::Percentage(0.01).commission(10.0, 100.0), 10.0);
+        assert_eq!(CommissionModel::PerShare(0.5).commission(-10.0, 100.0), 5.0);
+        assert_eq!(CommissionModel::Fixed(1.0).commission(10.0, 100.0), 1.0);
+        let combined = CommissionModel::Combined { fixed: 1.0, per_share: 0.5, pct: 0.01 };
+        assert_eq!(combined.commission(10.0, 100.0), 16.0);
+    }
+
+    #[test]
+    fn fixed_commission_per_trade() {
+        let mut broker = BrokerBuilder::new("Fixed")
+            .commission_model(CommissionModel::Fixed(1.0))
+            .build();
+        broker.submit_order(market_order(OrderSide::Buy, 10.0)).unwrap();
+        broker.next(&ticker(100.0, 0)).unwrap();
+        broker.submit_order(market_order(OrderSide::Buy, 500.0)).unwrap();
+        broker.next(&ticker(100.0, 1)).unwrap();
+
+        let commissions = broker.get_trades().iter().map(|trade| trade.commission).collect::<Vec<_>>();
+        assert_eq!(commissions, vec![1.0, 1.0]);
+        assert_eq!(broker.get_cash(), 100_000.0 - 510.0 * 100.0 - 2.0);
+    }
+}
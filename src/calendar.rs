@@ -0,0 +1,120 @@
+//! Trading sessions and holidays of an exchange.
+//!
+//! Without a calendar, the broker treats a gap of more than 8 hours between two tickers as
+//! the boundary between trading days. A `TradingCalendar` instead tells it when each session
+//! opens and closes, so that on-open and on-close orders fill at the actual session boundaries
+//! and bars outside of a session (pre-market, after-hours, holidays) never trigger them.
+//!
+//! Session times are compared against the time of day of each ticker as it is stored,
+//! so the feed should be timestamped in the exchange's local time.
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Weekday};
+use dyn_clone::DynClone;
+
+pub trait TradingCalendar: DynClone {
+    /// The opening and closing time of the session on `date`, or `None` if the exchange is closed.
+    fn session(&self, date: NaiveDate) -> Option<(NaiveTime, NaiveTime)>;
+}
+
+dyn_clone::clone_trait_object!(TradingCalendar);
+
+/// A calendar modelled after the New York Stock Exchange: sessions run from 9:30 to 16:00
+/// on weekdays, except on the exchange's regular holidays.
+///
+/// Early closes and one-off closures are not modelled.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Nyse;
+
+impl TradingCalendar for Nyse {
+    fn session(&self, date: NaiveDate) -> Option<(NaiveTime, NaiveTime)> {
+        if matches!(date.weekday(), Weekday::Sat | Weekday::Sun) || nyse_holidays(date.year()).contains(&date) {
+            return None;
+        }
+        Some((NaiveTime::from_hms_opt(9, 30, 0).unwrap(), NaiveTime::from_hms_opt(16, 0, 0).unwrap()))
+    }
+}
+
+/// The dates on which the NYSE is closed for a holiday in `year`.
+fn nyse_holidays(year: i32) -> Vec<NaiveDate> {
+    let date = |month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+    let nth = |month, weekday, n| NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).unwrap();
+    let last_monday_of_may = (25..=31).map(|day| date(5, day)).find(|day| day.weekday() == Weekday::Mon).unwrap();
+
+    let mut holidays = vec![
+        nth(1, Weekday::Mon, 3),             // Martin Luther King Jr. Day
+        nth(2, Weekday::Mon, 3),             // Washington's Birthday
+        easter(year) - Duration::days(2),    // Good Friday
+        last_monday_of_may,                  // Memorial Day
+        observed(date(7, 4)),                // Independence Day
+        nth(9, Weekday::Mon, 1),             // Labor Day
+        nth(11, Weekday::Thu, 4),            // Thanksgiving Day
+        observed(date(12, 25)),              // Christmas Day
+    ];
+    // New Year's Day is not observed on the preceding Friday, since that would close the year early.
+    if date(1, 1).weekday() != Weekday::Sat {
+        holidays.push(observed(date(1, 1)));
+    }
+    if year >= 2022 {
+        holidays.push(observed(date(6, 19))); // Juneteenth
+    }
+    holidays
+}
+
+/// Holidays that fall on a Saturday are observed on the Friday before, and those that fall
+/// on a Sunday on the Monday after.
+fn observed(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date - Duration::days(1),
+        Weekday::Sun => date + Duration::days(1),
+        _ => date,
+    }
+}
+
+/// Easter Sunday in the Gregorian calendar, using the anonymous Gregorian algorithm.
+fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn nyse_holidays_of_2023() {
+        let closed = [
+            date(2023, 1, 2),   // New Year's Day, observed
+            date(2023, 1, 16),  // Martin Luther King Jr. Day
+            date(2023, 2, 20),  // Washington's Birthday
+            date(2023, 4, 7),   // Good Friday
+            date(2023, 5, 29),  // Memorial Day
+            date(2023, 6, 19),  // Juneteenth
+            date(2023, 7, 4),   // Independence Day
+            date(2023, 9, 4),   // Labor Day
+            date(2023, 11, 23), // Thanksgiving Day
+            date(2023, 12, 25), // Christmas Day
+            date(2023, 12, 30), // Saturday
+        ];
+        for day in closed {
+            assert_eq!(Nyse.session(day), None, "{}", day);
+        }
+        assert!(Nyse.session(date(2023, 7, 3)).is_some());
+        assert!(Nyse.session(date(2023, 11, 24)).is_some());
+    }
+}
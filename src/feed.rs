@@ -0,0 +1,300 @@
+//! Sources of tickers that drive a backtest or a paper-trading session.
+//!
+//! A `Feed` decouples where tickers come from from the loop that hands them to the
+//! broker and the strategy. Finite sources such as a `TimeSeries` end when their data
+//! runs out, while live sources (e.g. a channel fed by a websocket) block until the
+//! next ticker arrives.
+//!
+//! ```no_run
+//! use backtester::prelude::*;
+//! use backtester::strategy::SMACrossover;
+//! use std::sync::mpsc;
+//!
+//! fn main() -> Result<(), BacktestError> {
+//!     let (sender, receiver) = mpsc::channel::<Ticker>();
+//!     // Hand `sender` to the thread that receives live market data...
+//!     # drop(sender);
+//!     let backtest = Backtest::new(
+//!         TimeSeries::from_vec(Vec::new()),
+//!         BrokerBuilder::new("Paper Trading").build(),
+//!         Box::new(SMACrossover::new(20)),
+//!     );
+//!     let result = backtest.run_feed(receiver)?;
+//!     println!("{}", result);
+//!     Ok(())
+//! }
+//! ```
+use crate::{
+    indicators::Indicator,
+    series::SeriesIntoIterator,
+    timeseries::{DedupTimestamps, HeikinAshi},
+    types::Ticker,
+};
+use log::warn;
+use std::collections::VecDeque;
+use std::sync::mpsc::Receiver;
+
+/// Produces tickers one at a time until the source is exhausted.
+pub trait Feed {
+    /// Returns the next ticker, or `None` once no more tickers will arrive.
+    fn next_ticker(&mut self) -> Option<Ticker>;
+}
+
+/// Returns the next row of `records` that parses, skipping the others with a warning.
+fn next_parsed(records: &mut impl Iterator<Item = Result<Ticker, csv::Error>>) -> Option<Ticker> {
+    for ticker in records {
+        match ticker {
+            Ok(ticker) => return Some(ticker),
+            Err(err) => warn!("Skipping unparseable ticker: {}", err),
+        }
+    }
+    None
+}
+
+/// The records of a `TimeSeries`, i.e. `TimeSeries::into_iter()`.
+/// Rows that cannot be parsed are skipped with a warning.
+impl Feed for SeriesIntoIterator<Ticker> {
+    fn next_ticker(&mut self) -> Option<Ticker> {
+        next_parsed(self)
+    }
+}
+
+/// The Heikin-Ashi candles of a `TimeSeries`, i.e. `TimeSeries::heikin_ashi()`.
+/// Rows that cannot be parsed are skipped with a warning.
+impl<I> Feed for HeikinAshi<I>
+where
+    I: Iterator<Item = Result<Ticker, csv::Error>>,
+{
+    fn next_ticker(&mut self) -> Option<Ticker> {
+        next_parsed(self)
+    }
+}
+
+/// The bars of a `TimeSeries` with one bar per datetime, i.e. `TimeSeries::dedup_timestamps()`.
+/// Rows that cannot be parsed are skipped with a warning.
+impl<I> Feed for DedupTimestamps<I>
+where
+    I: Iterator<Item = Result<Ticker, csv::Error>>,
+{
+    fn next_ticker(&mut self) -> Option<Ticker> {
+        next_parsed(self)
+    }
+}
+
+/// An in-memory queue of tickers, consumed from the front.
+impl Feed for VecDeque<Ticker> {
+    fn next_ticker(&mut self) -> Option<Ticker> {
+        self.pop_front()
+    }
+}
+
+/// A live push feed. Blocks until the next ticker is sent, and ends once every sender is dropped.
+impl Feed for Receiver<Ticker> {
+    fn next_ticker(&mut self) -> Option<Ticker> {
+        self.recv().ok()
+    }
+}
+
+/// Merges the feeds of several symbols into a single stream of `(symbol, Ticker)` events
+/// in chronological order, so that a strategy can trade the symbols on a shared timeline.
+/// See `Backtest::run_multi_feed`.
+///
+/// Tickers with the same timestamp are emitted in the order their feeds were added.
+/// Every feed must have produced its next ticker before an event is emitted, so a live
+/// feed holds back the others until its next ticker arrives.
+#[derive(Default)]
+pub struct MultiFeed {
+    feeds: Vec<(String, Box<dyn Feed>, Option<Ticker>)>, // (symbol, feed, next ticker)
+}
+
+impl MultiFeed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the tickers of `symbol`.
+    pub fn add(mut self, symbol: &str, feed: impl Feed + 'static) -> Self {
+        self.feeds.push((symbol.to_string(), Box::new(feed), None));
+        self
+    }
+
+    pub fn get_symbols(&self) -> Vec<&str> {
+        self.feeds.iter().map(|(symbol, _, _)| symbol.as_str()).collect()
+    }
+}
+
+impl Iterator for MultiFeed {
+    type Item = (String, Ticker);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (_, feed, next) in &mut self.feeds {
+            if next.is_none() {
+                *next = feed.next_ticker();
+            }
+        }
+        let (symbol, _, next) = self
+            .feeds
+            .iter_mut()
+            .filter(|(_, _, next)| next.is_some())
+            .min_by_key(|(_, _, next)| next.as_ref().map(|ticker| ticker.datetime))?;
+        Some((symbol.clone(), next.take()?))
+    }
+}
+
+/// Pairs every ticker of `prices` with the latest value of `values` as of the ticker's datetime,
+/// e.g. to annotate intraday bars with a daily macro series such as the `EFFR`. `values` is
+/// any step-wise indicator, such as a `StepSeriesIndicator`, which carries each value forward
+/// until the next one.
+///
+/// Tickers for which `values` has no value, i.e. that are dated before its first value or
+/// after its end, are skipped.
+pub fn as_of_join<V>(mut prices: impl Feed, mut values: V) -> impl Iterator<Item = (Ticker, f32)>
+where
+    V: Indicator<Result = f32>,
+{
+    std::iter::from_fn(move || loop {
+        let ticker = prices.next_ticker()?;
+        if values.update(&ticker).is_ok() {
+            if let Ok(value) = values.get_value() {
+                return Some((ticker, value));
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backtest::Backtest, broker::Broker, strategy::SMACrossover, timeseries::TimeSeries};
+    use chrono::{TimeZone, Utc};
+    use std::sync::mpsc;
+
+    fn tickers() -> Vec<Ticker> {
+        (0..60)
+            .map(|day| {
+                let close = 100.0 + 5.0 * (day as f32 / 4.0).sin();
+                Ticker {
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: 1000,
+                    datetime: Utc.timestamp_opt(day * 86_400, 0).unwrap(),
+                }
+            })
+            .collect()
+    }
+
+    fn backtest() -> Backtest {
+        Backtest::new(
+            TimeSeries::from_vec(tickers()),
+            Broker::new("Feed", 100_000.0, 0.0, 1.0, false, false),
+            Box::new(SMACrossover::new(5)),
+        )
+    }
+
+    #[test]
+    fn queue_feed_matches_run() {
+        let expected = backtest().run().unwrap();
+        let result = backtest().run_feed(VecDeque::from(tickers())).unwrap();
+
+        assert_eq!(result.get_equity_curve(), expected.get_equity_curve());
+        assert_eq!(result.get_broker().get_trades().len(), expected.get_broker().get_trades().len());
+        assert!(!result.get_broker().get_trades().is_empty());
+    }
+
+    #[test]
+    fn channel_feed_ends_when_sender_is_dropped() {
+        let (sender, receiver) = mpsc::channel();
+        let producer = std::thread::spawn(move || {
+            for ticker in tickers() {
+                sender.send(ticker).unwrap();
+            }
+        });
+        let result = backtest().run_feed(receiver).unwrap();
+        producer.join().unwrap();
+        assert_eq!(result.get_equity_curve(), backtest().run().unwrap().get_equity_curve());
+    }
+
+    #[test]
+    fn multi_feed_merges_in_time_order() {
+        let at = |day: i64| Ticker {
+            datetime: Utc.timestamp_opt(day * 86_400, 0).unwrap(),
+            ..tickers()[0].clone()
+        };
+        let feed = MultiFeed::new()
+            .add("AAPL", TimeSeries::from_vec(vec![at(0), at(2), at(3), at(7)]).into_iter())
+            .add("MSFT", VecDeque::from(vec![at(1), at(2), at(5)]));
+        assert_eq!(feed.get_symbols(), vec!["AAPL", "MSFT"]);
+
+        let events = feed.map(|(symbol, ticker)| (symbol, ticker.datetime.timestamp() / 86_400)).collect::<Vec<_>>();
+        let expected = [("AAPL", 0), ("MSFT", 1), ("AAPL", 2), ("MSFT", 2), ("AAPL", 3), ("MSFT", 5), ("AAPL", 7)];
+        assert_eq!(events, expected.map(|(symbol, day)| (symbol.to_string(), day)).to_vec());
+    }
+
+    #[test]
+    fn as_of_join_carries_daily_values_forward() {
+        use crate::indicators::EFFR;
+
+        // The rate moves from 0.07 to 0.08 on 2014-03-05.
+        let bar = |day: u32, hour: u32| Ticker {
+            datetime: Utc.with_ymd_and_hms(2014, 3, day, hour, 30, 0).unwrap(),
+            ..tickers()[0].clone()
+        };
+        let bars = VecDeque::from(vec![bar(4, 9), bar(4, 15), bar(5, 9), bar(5, 15)]);
+        let joined = as_of_join(bars, EFFR::from_csv("./benches/datasets/indicators/DFF.csv"))
+            .map(|(ticker, rate)| (ticker.datetime, rate))
+            .collect::<Vec<_>>();
+        let expected = [(bar(4, 9), 0.07), (bar(4, 15), 0.07), (bar(5, 9), 0.08), (bar(5, 15), 0.08)];
+        assert_eq!(joined, expected.map(|(ticker, rate)| (ticker.datetime, rate)).to_vec());
+
+        // Bars after the end of the series have no value.
+        let late = VecDeque::from(vec![Ticker {
+            datetime: Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap(),
+            ..tickers()[0].clone()
+        }]);
+        assert_eq!(as_of_join(late, EFFR::from_csv("./benches/datasets/indicators/DFF.csv")).count(), 0);
+    }
+
+    #[test]
+    fn heikin_ashi_feed_drives_backtest() {
+        let candles = TimeSeries::from_vec(tickers())
+            .heikin_ashi()
+            .collect::<Result<VecDeque<Ticker>, _>>()
+            .unwrap();
+        let expected = backtest().run_feed(candles).unwrap();
+        let result = backtest().run_feed(TimeSeries::from_vec(tickers()).heikin_ashi()).unwrap();
+
+        assert_eq!(result.get_equity_curve().len(), 60);
+        assert_eq!(result.get_equity_curve(), expected.get_equity_curve());
+        assert_eq!(result.get_broker().get_trades(), expected.get_broker().get_trades());
+    }
+
+    #[test]
+    fn dedup_timestamps_feed_drives_backtest() {
+        // Every bar arrives twice, the second time with a corrected close.
+        let repeated = tickers()
+            .into_iter()
+            .flat_map(|ticker| {
+                let corrected = Ticker { close: ticker.close + 1.0, ..ticker.clone() };
+                [ticker, corrected]
+            })
+            .collect::<Vec<_>>();
+        let corrected = repeated.iter().skip(1).step_by(2).cloned().collect::<VecDeque<_>>();
+        let expected = backtest().run_feed(corrected).unwrap();
+        let result = backtest().run_feed(TimeSeries::from_vec(repeated).dedup_timestamps()).unwrap();
+
+        assert_eq!(result.get_equity_curve().len(), 60);
+        assert_eq!(result.get_equity_curve(), expected.get_equity_curve());
+    }
+
+    #[test]
+    fn series_feed() {
+        let mut feed = TimeSeries::from_vec(tickers()).into_iter();
+        let mut count = 0;
+        while feed.next_ticker().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 60);
+    }
+}
@@ -0,0 +1,115 @@
+use super::*;
+
+/// [Average True Range](https://www.investopedia.com/terms/a/atr.asp)
+///
+/// Measures volatility as the average range of the tickers, including any gap from the
+/// previous close. The true range of a ticker is the largest of `high - low`,
+/// `|high - previous close|` and `|low - previous close|`, or `high - low` for the first ticker.
+///
+/// The first value is the simple average of the first `period` true ranges, and later
+/// values are Wilder's moving average of them.
+#[derive(Clone)]
+pub struct ATR {
+    period: u32,
+    previous_close: Option<f32>,
+    /// The true ranges until the first value is available.
+    true_ranges: Vec<f32>,
+    values: History<f32>,
+}
+
+impl Default for ATR {
+    fn default() -> Self {
+        Self::new(14)
+    }
+}
+
+impl fmt::Display for ATR {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ATR(Period: {})", self.period)
+    }
+}
+
+impl ATR {
+    /// Default period of `14` tickers.
+    pub fn new(period: u32) -> Self {
+        Self {
+            period,
+            previous_close: None,
+            true_ranges: Vec::new(),
+            values: History::new(),
+        }
+    }
+}
+
+impl Indicator for ATR {
+    type Result = f32;
+
+    fn update(&mut self, ticker: &Ticker) -> IndicatorResult<()> {
+        let true_range = match self.previous_close.replace(ticker.close) {
+            Some(previous_close) => (ticker.high - ticker.low)
+                .max((ticker.high - previous_close).abs())
+                .max((ticker.low - previous_close).abs()),
+            None => ticker.high - ticker.low,
+        };
+
+        let period = self.period as f32;
+        let value = match self.values.last() {
+            Some(previous) => (previous * (period - 1.0) + true_range) / period,
+            None => {
+                self.true_ranges.push(true_range);
+                if self.true_ranges.len() < self.period as usize {
+                    return Ok(());
+                }
+                self.true_ranges.drain(..).sum::<f32>() / period
+            }
+        };
+        self.values.push(value);
+        Ok(())
+    }
+
+    fn get_value(&self) -> IndicatorResult<Self::Result> {
+        self.values.last().ok_or(IndicatorError::InsufficientData)
+    }
+
+    fn at(&self, index: usize) -> IndicatorResult<Self::Result> {
+        self.values.get(index).ok_or(IndicatorError::IndexOutOfRange)
+    }
+}
+
+impl WithHistory for ATR {
+    fn history_mut(&mut self) -> &mut History<f32> {
+        &mut self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(high: f32, low: f32, close: f32) -> Ticker {
+        Ticker {
+            open: close,
+            high,
+            low,
+            close,
+            volume: 100,
+            datetime: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn period_2() {
+        let mut atr = ATR::new(2);
+        // True range 2.
+        atr.update(&tick(11.0, 9.0, 10.0)).unwrap();
+        assert!(atr.get_value().is_err());
+        // Gaps up from 10: true range 14 - 10 = 4.
+        atr.update(&tick(14.0, 12.0, 13.0)).unwrap();
+        assert_eq!(atr.get_value().unwrap(), 3.0);
+        // True range 13 - 7 = 6, smoothed to (3 + 6) / 2.
+        atr.update(&tick(13.0, 7.0, 8.0)).unwrap();
+        assert_eq!(atr.get_value().unwrap(), 4.5);
+        assert_eq!(atr.at(0).unwrap(), 3.0);
+        assert!(atr.at(2).is_err());
+    }
+}
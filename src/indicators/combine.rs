@@ -0,0 +1,185 @@
+use super::*;
+
+/// # Map
+///
+/// Transforms the value of another indicator with `f`, e.g. to scale or clamp it.
+#[derive(Clone)]
+pub struct Map<A, F> {
+    inner: A,
+    f: F,
+    values: History<f32>,
+}
+
+impl<A, F> Map<A, F>
+where
+    A: Indicator<Result = f32>,
+    F: Fn(f32) -> f32,
+{
+    pub fn new(inner: A, f: F) -> Self {
+        Self {
+            inner,
+            f,
+            values: History::new(),
+        }
+    }
+}
+
+impl<A: fmt::Display, F> fmt::Display for Map<A, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Map({})", self.inner)
+    }
+}
+
+impl<A, F> Indicator for Map<A, F>
+where
+    A: Indicator<Result = f32> + Clone,
+    F: Fn(f32) -> f32 + Clone,
+{
+    type Result = f32;
+
+    fn update(&mut self, ticker: &Ticker) -> IndicatorResult<()> {
+        self.inner.update(ticker)?;
+        if let Ok(value) = self.inner.get_value() {
+            self.values.push((self.f)(value));
+        }
+        Ok(())
+    }
+
+    fn get_value(&self) -> IndicatorResult<Self::Result> {
+        self.values.last().ok_or(IndicatorError::InsufficientData)
+    }
+
+    fn at(&self, index: usize) -> IndicatorResult<Self::Result> {
+        self.values.get(index).ok_or(IndicatorError::IndexOutOfRange)
+    }
+}
+
+impl<A, F> WithHistory for Map<A, F>
+where
+    A: Indicator<Result = f32> + Clone,
+    F: Fn(f32) -> f32 + Clone,
+{
+    fn history_mut(&mut self) -> &mut History<f32> {
+        &mut self.values
+    }
+}
+
+/// # Combine
+///
+/// Combines the values of two indicators with `f`, e.g. the difference of a fast and a slow SMA.
+/// Both indicators are updated with every ticker, and a value is only available once both have one.
+///
+/// ```
+/// use backtester::prelude::*;
+///
+/// let spread = Combine::new(SMA::new(5), SMA::new(20), |fast, slow| fast - slow);
+/// ```
+#[derive(Clone)]
+pub struct Combine<A, B, F> {
+    a: A,
+    b: B,
+    f: F,
+    values: History<f32>,
+}
+
+impl<A, B, F> Combine<A, B, F>
+where
+    A: Indicator<Result = f32>,
+    B: Indicator<Result = f32>,
+    F: Fn(f32, f32) -> f32,
+{
+    pub fn new(a: A, b: B, f: F) -> Self {
+        Self {
+            a,
+            b,
+            f,
+            values: History::new(),
+        }
+    }
+}
+
+impl<A: fmt::Display, B: fmt::Display, F> fmt::Display for Combine<A, B, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Combine({}, {})", self.a, self.b)
+    }
+}
+
+impl<A, B, F> Indicator for Combine<A, B, F>
+where
+    A: Indicator<Result = f32> + Clone,
+    B: Indicator<Result = f32> + Clone,
+    F: Fn(f32, f32) -> f32 + Clone,
+{
+    type Result = f32;
+
+    fn update(&mut self, ticker: &Ticker) -> IndicatorResult<()> {
+        // Both indicators see every ticker, even if one of them fails.
+        let (a, b) = (self.a.update(ticker), self.b.update(ticker));
+        a?;
+        b?;
+        if let (Ok(a), Ok(b)) = (self.a.get_value(), self.b.get_value()) {
+            self.values.push((self.f)(a, b));
+        }
+        Ok(())
+    }
+
+    fn get_value(&self) -> IndicatorResult<Self::Result> {
+        self.values.last().ok_or(IndicatorError::InsufficientData)
+    }
+
+    fn at(&self, index: usize) -> IndicatorResult<Self::Result> {
+        self.values.get(index).ok_or(IndicatorError::IndexOutOfRange)
+    }
+}
+
+impl<A, B, F> WithHistory for Combine<A, B, F>
+where
+    A: Indicator<Result = f32> + Clone,
+    B: Indicator<Result = f32> + Clone,
+    F: Fn(f32, f32) -> f32 + Clone,
+{
+    fn history_mut(&mut self) -> &mut History<f32> {
+        &mut self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicators::SMA;
+    use chrono::Utc;
+
+    fn tick(close: f32) -> Ticker {
+        Ticker {
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 100,
+            datetime: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn difference_of_smas() {
+        let closes = [1.0, 4.0, 2.0, 8.0, 5.0, 7.0, 3.0];
+        let mut difference = Combine::new(SMA::new(2), SMA::new(4), |fast, slow| fast - slow);
+        let mut doubled = Map::new(SMA::new(2), |value| 2.0 * value);
+        for (i, close) in closes.iter().enumerate() {
+            difference.update(&tick(*close)).unwrap();
+            doubled.update(&tick(*close)).unwrap();
+            let fast = (closes[i.saturating_sub(1)] + close) / 2.0;
+            if i < 3 {
+                assert!(difference.get_value().is_err());
+            } else {
+                let slow = closes[i - 3..=i].iter().sum::<f32>() / 4.0;
+                assert_eq!(difference.get_value().unwrap(), fast - slow);
+            }
+            if i >= 1 {
+                assert_eq!(doubled.get_value().unwrap(), 2.0 * fast);
+            }
+        }
+        assert_eq!(difference.at(0).unwrap(), (2.0 + 8.0) / 2.0 - 15.0 / 4.0);
+        assert_eq!(difference.to_string(), "Combine(SMA(Period: 2), SMA(Period: 4))");
+    }
+}
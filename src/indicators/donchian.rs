@@ -0,0 +1,130 @@
+use super::*;
+
+/// The bounds of a `Donchian` channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Channel {
+    /// The highest high of the window.
+    pub upper: f32,
+    /// The lowest low of the window.
+    pub lower: f32,
+    /// The average of `upper` and `lower`.
+    pub middle: f32,
+}
+
+/// # [Donchian Channel](https://www.investopedia.com/terms/d/donchianchannels.asp)
+///
+/// The highest high and the lowest low of the last `period` tickers, and their average.
+/// Trend-following systems buy breakouts above the upper band and sell below the lower one.
+#[derive(Clone)]
+pub struct Donchian {
+    period: u32,
+    /// The last `period` highs and lows.
+    highs: Vec<f32>,
+    lows: Vec<f32>,
+    values: History<Channel>,
+}
+
+impl Default for Donchian {
+    fn default() -> Self {
+        Self::new(20)
+    }
+}
+
+impl Donchian {
+    /// Default uses a `20` ticker period.
+    pub fn new(period: u32) -> Self {
+        Self {
+            period,
+            highs: Vec::new(),
+            lows: Vec::new(),
+            values: History::new(),
+        }
+    }
+}
+
+impl fmt::Display for Donchian {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Donchian(Period: {})", self.period)
+    }
+}
+
+impl Indicator for Donchian {
+    type Result = Channel;
+
+    fn update(&mut self, ticker: &Ticker) -> IndicatorResult<()> {
+        self.highs.push(ticker.high);
+        self.lows.push(ticker.low);
+        if self.highs.len() > self.period as usize {
+            self.highs.remove(0);
+            self.lows.remove(0);
+        }
+
+        // Return early if we don't have enough data
+        if self.highs.len() < self.period as usize {
+            return Ok(());
+        }
+
+        let upper = self.highs.iter().copied().fold(f32::MIN, f32::max);
+        let lower = self.lows.iter().copied().fold(f32::MAX, f32::min);
+        self.values.push(Channel {
+            upper,
+            lower,
+            middle: (upper + lower) / 2.0,
+        });
+
+        Ok(())
+    }
+
+    fn get_value(&self) -> IndicatorResult<Self::Result> {
+        self.values.last().ok_or(IndicatorError::InsufficientData)
+    }
+
+    fn at(&self, index: usize) -> IndicatorResult<Self::Result> {
+        self.values.get(index).ok_or(IndicatorError::IndexOutOfRange)
+    }
+}
+
+impl WithHistory for Donchian {
+    fn history_mut(&mut self) -> &mut History<Channel> {
+        &mut self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn tick(high: f32, low: f32) -> Ticker {
+        Ticker {
+            open: low,
+            high,
+            low,
+            close: high,
+            volume: 100,
+            datetime: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn ramp_period_3() {
+        let mut donchian = Donchian::new(3);
+        for day in 0..10 {
+            let high = 10.0 + day as f32;
+            donchian.update(&tick(high, high - 2.0)).unwrap();
+            if day < 2 {
+                assert!(donchian.get_value().is_err());
+                continue;
+            }
+            // On a ramp, the latest high is the highest and the low of 2 tickers ago the lowest.
+            let lower = high - 4.0;
+            let expected = Channel {
+                upper: high,
+                lower,
+                middle: (high + lower) / 2.0,
+            };
+            assert_eq!(donchian.get_value().unwrap(), expected);
+        }
+        assert_eq!(donchian.at(0).unwrap().upper, 12.0);
+    }
+}
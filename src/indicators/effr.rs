@@ -1,58 +1,27 @@
 use super::*;
-use crate::{
-	util::serde_ext::*,
-	series::SeriesIntoIterator
-};
-use chrono::{DateTime, Utc};
-use std::path::Path;	
-
-#[derive(Clone, Deserialize, Debug)]
-struct DFF {
-	#[serde(rename = "DFF")]
-	dff: f32,
-	#[serde(rename = "DATE")]
-	#[serde(with = "yyyy_mm_dd")]
-	date: DateTime<Utc>
-}
+use std::path::Path;
 
 /// [Federal Funds Effective Rate](https://www.newyorkfed.org/markets/reference-rates/effr)
 /// 
 /// The actual rate at which commercial banks borrow and lend their excess reserves overnight.
 /// Notice, that this is the actualized rate rather than the target federal funds rate.
+///
+/// Reads the `DATE` and `DFF` columns of the [FRED](https://fred.stlouisfed.org/series/DFF) export.
+#[derive(Clone)]
 pub struct EFFR {
-	previous: Option<DFF>,
-	current: Option<f32>,
-	date: DateTime<Utc>,
-	series: Series<DFF>,
-	stream: SeriesIntoIterator<DFF>
+	series: StepSeriesIndicator
 }
 
 impl fmt::Display for EFFR {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "EFFR({:?})", self.current)
+		write!(f, "EFFR({:?})", self.series.get_value().ok())
 	}
 }
 
 impl EFFR {
 	pub fn from_csv<P: AsRef<Path>>(path: P) -> Self {
 		Self {
-			previous: None,
-			current: None,
-			date: DateTime::from_timestamp(0, 0).unwrap(),
-			series: Series::<DFF>::from_csv(&path),
-			stream: Series::<DFF>::from_csv(&path).into_iter()
-		}
-	}
-}
-
-impl Clone for EFFR {
-	fn clone(&self) -> Self {
-		Self {
-			previous: self.previous.clone(),
-			current: self.current.clone(),
-			date: self.date.clone(),
-			series: self.series.clone(),
-			stream: self.series.clone().into_iter()
+			series: StepSeriesIndicator::from_csv(path, "DATE", "DFF")
 		}
 	}
 }
@@ -61,32 +30,15 @@ impl Indicator for EFFR {
 	type Result = f32;
 
 	fn update(&mut self, ticker: &Ticker) -> IndicatorResult<()> {
-		// Iterate until we find the next update that is after the current ticker
-		// Remember the previous ticker.
-		while let Some(line) = self.stream.next() { 
-			if let Ok(update) = line {
-			  // If the current update is after the ticker, we use the previous update.
-				if update.date >= ticker.datetime {
-					self.current = Some(update.dff);
-					self.date = update.date;
-					return Ok(())
-				} 
-				self.previous = Some(update);
-			}
-		}
-		Err(IndicatorError::InsufficientData)
+		self.series.update(ticker)
 	}
 
 	fn get_value(&self) -> IndicatorResult<Self::Result> {
-		if let Some(result) = self.current {
-			Ok(result)
-		} else {
-			Err(IndicatorError::InsufficientData)
-		}
+		self.series.get_value()
 	}
 
-	fn at(&self, _: usize) -> IndicatorResult<Self::Result> {
-		Err(IndicatorError::IndexOutOfRange)
+	fn at(&self, index: usize) -> IndicatorResult<Self::Result> {
+		self.series.at(index)
 	}
 }
 
@@ -97,7 +49,7 @@ impl Indicator for EFFR {
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use chrono::{NaiveDate, Utc};
+	use chrono::{DateTime, NaiveDate, Utc};
 
 	fn get_date(year: i32, month: u32, day: u32) -> DateTime<Utc> {
 		let datetime = NaiveDate::from_ymd_opt(year, month, day)
@@ -187,4 +139,23 @@ mod tests {
 		}).is_ok());
 		assert_eq!(effr.get_value().unwrap(), expected_on_first);
 	}
-}
\ No newline at end of file
+
+	// Corner Case: We call the update function repeatedly with the same date, right before the rate changes.
+	// The record that matches the date must not be consumed, so the value should not advance to the next day's rate.
+	#[test]
+	fn same_date_twice() {
+		let mut effr = EFFR::from_csv("./benches/datasets/indicators/DFF.csv");
+		let datetime = get_date(2014, 3, 4); // The rate moves from 0.07 to 0.08 on 2014-03-05
+		for _ in 0..2 {
+			assert!(effr.update(&Ticker {
+				datetime,
+				open: 0.0,
+				high: 0.0,
+				low: 0.0,
+				close: 0.0,
+				volume: 0,
+			}).is_ok());
+			assert_eq!(effr.get_value().unwrap(), 0.07);
+		}
+	}
+}
@@ -0,0 +1,105 @@
+use super::*;
+
+/// # [Exponential Moving Average](https://www.investopedia.com/terms/e/ema.asp)
+///
+/// Weighs recent closes more heavily than the `SMA`, so it reacts faster to trend changes.
+/// The first value is the simple average of the first `period` closes, and every later close
+/// is blended in with a weight of `2 / (period + 1)`.
+#[derive(Clone)]
+pub struct EMA {
+    period: u32,
+    /// The closes until the first value is available.
+    ticks: Vec<f32>,
+    values: History<f32>,
+}
+
+impl Default for EMA {
+    fn default() -> Self {
+        Self::new(10)
+    }
+}
+
+impl EMA {
+    /// Default uses a `10` ticker period.
+    pub fn new(period: u32) -> Self {
+        Self {
+            period,
+            ticks: Vec::new(),
+            values: History::new(),
+        }
+    }
+}
+
+impl fmt::Display for EMA {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EMA(Period: {})", self.period)
+    }
+}
+
+impl Indicator for EMA {
+    type Result = f32;
+
+    fn update(&mut self, ticker: &Ticker) -> IndicatorResult<()> {
+        let value = match self.values.last() {
+            Some(previous) => {
+                let alpha = 2.0 / (self.period as f32 + 1.0);
+                alpha * ticker.close + (1.0 - alpha) * previous
+            }
+            None => {
+                self.ticks.push(ticker.close);
+                if self.ticks.len() < self.period as usize {
+                    return Ok(());
+                }
+                self.ticks.drain(..).sum::<f32>() / self.period as f32
+            }
+        };
+        self.values.push(value);
+        Ok(())
+    }
+
+    fn get_value(&self) -> IndicatorResult<Self::Result> {
+        self.values.last().ok_or(IndicatorError::InsufficientData)
+    }
+
+    fn at(&self, index: usize) -> IndicatorResult<Self::Result> {
+        self.values.get(index).ok_or(IndicatorError::IndexOutOfRange)
+    }
+}
+
+impl WithHistory for EMA {
+    fn history_mut(&mut self) -> &mut History<f32> {
+        &mut self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(close: f32) -> Ticker {
+        Ticker {
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 100,
+            datetime: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn period_3() {
+        let mut ema = EMA::new(3);
+        ema.update(&tick(1.0)).unwrap();
+        ema.update(&tick(2.0)).unwrap();
+        assert!(ema.get_value().is_err());
+        // Seeded with the average of the first three closes.
+        ema.update(&tick(3.0)).unwrap();
+        assert_eq!(ema.get_value().unwrap(), 2.0);
+        // Weighted by 2 / (3 + 1).
+        ema.update(&tick(6.0)).unwrap();
+        assert_eq!(ema.get_value().unwrap(), 4.0);
+        assert_eq!(ema.at(0).unwrap(), 2.0);
+        assert!(ema.at(2).is_err());
+    }
+}
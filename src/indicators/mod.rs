@@ -15,9 +15,11 @@ use crate::{
     types::Ticker,
     series::Series,
 };
+use dyn_clone::DynClone;
 use serde_derive::{Deserialize, Serialize};
 
 pub(crate) use std::fmt;
+use std::collections::VecDeque;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum IndicatorError {
@@ -27,7 +29,7 @@ pub enum IndicatorError {
 
 pub type IndicatorResult<T> = Result<T, IndicatorError>;
 
-pub trait Indicator: fmt::Display {
+pub trait Indicator: fmt::Display + DynClone {
     /// The type of value that the indicator returns.
     type Result;
 
@@ -39,10 +41,118 @@ pub trait Indicator: fmt::Display {
     fn at(&self, index: usize) -> IndicatorResult<Self::Result>;
 }
 
+dyn_clone::clone_trait_object!(Indicator<Result = f32>);
+
+/// An indicator that keeps the values it computes in a `History`, e.g. for `at`.
+pub trait WithHistory: Indicator {
+    /// The values that the indicator has computed.
+    fn history_mut(&mut self) -> &mut History<Self::Result>;
+
+    /// Keeps only the latest `cap` values for `at`, to bound the memory used over long feeds.
+    /// See `History`.
+    fn with_history(mut self, cap: usize) -> Self
+    where
+        Self: Sized,
+    {
+        self.history_mut().set_cap(Some(cap));
+        self
+    }
+}
+
+/// The values that an indicator has computed, indexed from the first one for `Indicator::at`.
+///
+/// Every value is kept by default. With a cap, only the latest `cap` values are kept, which
+/// bounds the memory of runs over long feeds: `at` fails for the dropped indices, while the
+/// latest value, and so `get_value`, is always available.
+#[derive(Debug, Clone)]
+pub struct History<T> {
+    retained: VecDeque<T>,
+    /// The number of values dropped from the front to respect the cap.
+    dropped: usize,
+    cap: Option<usize>,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> History<T> {
+    /// An empty history without a cap.
+    pub fn new() -> Self {
+        Self {
+            retained: VecDeque::new(),
+            dropped: 0,
+            cap: None,
+        }
+    }
+
+    /// Keeps only the latest `cap` values from now on, or every value if `cap` is `None`.
+    /// A cap of `0` is raised to `1` so that the latest value is kept.
+    pub fn set_cap(&mut self, cap: Option<usize>) {
+        self.cap = cap.map(|cap| cap.max(1));
+        self.trim();
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.retained.push_back(value);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        if let Some(cap) = self.cap {
+            while self.retained.len() > cap {
+                self.retained.pop_front();
+                self.dropped += 1;
+            }
+        }
+    }
+
+    /// The number of values pushed, including the dropped ones.
+    pub fn len(&self) -> usize {
+        self.dropped + self.retained.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Copy> History<T> {
+    pub fn last(&self) -> Option<T> {
+        self.retained.back().copied()
+    }
+
+    /// The `index`'th value pushed, or `None` if it was dropped or not computed yet.
+    pub fn get(&self, index: usize) -> Option<T> {
+        index
+            .checked_sub(self.dropped)
+            .and_then(|index| self.retained.get(index))
+            .copied()
+    }
+}
+
 // Re-export all indicators
+mod atr;
+mod combine;
+mod donchian;
+mod ema;
 mod rsi;
 mod sma;
 mod effr;
+mod quantile;
+mod rolling_stats;
+mod step_series;
+mod zscore;
+pub use atr::ATR;
+pub use combine::{Combine, Map};
+pub use donchian::{Channel, Donchian};
+pub use ema::EMA;
 pub use rsi::RSI;
 pub use sma::SMA;
 pub use effr::EFFR;
+pub use quantile::RollingQuantile;
+pub use rolling_stats::RollingStats;
+pub use step_series::StepSeriesIndicator;
+pub use zscore::ZScore;
@@ -0,0 +1,116 @@
+use super::*;
+use crate::metrics;
+
+/// # Rolling Quantile
+///
+/// The `q`-th quantile (in `[0, 1]`) of the last `period` closes, e.g. the median with `q = 0.5`.
+/// Values between two closes are linearly interpolated. Comparing the close against it gives
+/// adaptive thresholds, such as "the close is in the bottom 10% of its last 50 closes".
+#[derive(Clone)]
+pub struct RollingQuantile {
+    period: u32,
+    q: f32,
+    /// The last `period` closing values.
+    ticks: Vec<f32>,
+    values: History<f32>,
+}
+
+impl Default for RollingQuantile {
+    fn default() -> Self {
+        Self::new(20, 0.5)
+    }
+}
+
+impl RollingQuantile {
+    /// Default is the median of the last `20` closes.
+    pub fn new(period: u32, q: f32) -> Self {
+        Self {
+            period,
+            q,
+            ticks: Vec::new(),
+            values: History::new(),
+        }
+    }
+}
+
+impl fmt::Display for RollingQuantile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RollingQuantile(Period: {}, Q: {})", self.period, self.q)
+    }
+}
+
+impl Indicator for RollingQuantile {
+    type Result = f32;
+
+    fn update(&mut self, ticker: &Ticker) -> IndicatorResult<()> {
+        self.ticks.push(ticker.close);
+        if self.ticks.len() > self.period as usize {
+            self.ticks.remove(0);
+        }
+
+        // Return early if we don't have enough data
+        if self.ticks.len() < self.period as usize {
+            return Ok(());
+        }
+
+        let mut sorted = self.ticks.clone();
+        sorted.sort_by(f32::total_cmp);
+        self.values.push(metrics::percentile(&sorted, self.q));
+
+        Ok(())
+    }
+
+    fn get_value(&self) -> IndicatorResult<Self::Result> {
+        self.values.last().ok_or(IndicatorError::InsufficientData)
+    }
+
+    fn at(&self, index: usize) -> IndicatorResult<Self::Result> {
+        self.values.get(index).ok_or(IndicatorError::IndexOutOfRange)
+    }
+}
+
+impl WithHistory for RollingQuantile {
+    fn history_mut(&mut self) -> &mut History<f32> {
+        &mut self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn tick(close: f32) -> Ticker {
+        Ticker {
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 100,
+            datetime: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn median_of_period_4() {
+        let mut median = RollingQuantile::new(4, 0.5);
+        let mut bottom = RollingQuantile::new(4, 0.0);
+        for close in [7.0, 1.0, 4.0] {
+            median.update(&tick(close)).unwrap();
+            assert!(median.get_value().is_err());
+        }
+        // The window is [7, 1, 4, 10], sorted [1, 4, 7, 10], so the median is (4 + 7) / 2.
+        median.update(&tick(10.0)).unwrap();
+        assert_eq!(median.get_value().unwrap(), 5.5);
+
+        // The window moves on to [1, 4, 10, 2], sorted [1, 2, 4, 10].
+        median.update(&tick(2.0)).unwrap();
+        assert_eq!(median.get_value().unwrap(), 3.0);
+        assert_eq!(median.at(0).unwrap(), 5.5);
+
+        for close in [7.0, 1.0, 4.0, 10.0] {
+            bottom.update(&tick(close)).unwrap();
+        }
+        assert_eq!(bottom.get_value().unwrap(), 1.0);
+    }
+}
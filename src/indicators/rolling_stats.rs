@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+
+/// The mean and variance of the last `period` values, updated in constant time per value
+/// with [Welford's algorithm](https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance#Welford's_online_algorithm),
+/// extended to drop the oldest value once the window is full.
+///
+/// Indicators over a rolling window, such as `ZScore`, use it to avoid summing the whole
+/// window on every ticker. The sums are kept in `f64` so that errors do not build up over
+/// long feeds.
+#[derive(Debug, Clone)]
+pub struct RollingStats {
+    period: usize,
+    window: VecDeque<f32>,
+    mean: f64,
+    /// The sum of the squared deviations from the mean.
+    m2: f64,
+}
+
+impl RollingStats {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period,
+            window: VecDeque::with_capacity(period + 1),
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    /// Adds `value` to the window, dropping the oldest value if the window was full.
+    pub fn push(&mut self, value: f32) {
+        let value = value as f64;
+        self.window.push_back(value as f32);
+        if self.window.len() > self.period {
+            let oldest = self.window.pop_front().unwrap() as f64;
+            let mean = self.mean + (value - oldest) / self.period as f64;
+            self.m2 += (value - oldest) * (value - mean + oldest - self.mean);
+            self.mean = mean;
+        } else {
+            let delta = value - self.mean;
+            self.mean += delta / self.window.len() as f64;
+            self.m2 += delta * (value - self.mean);
+        }
+        // Rounding can leave a window without dispersion slightly negative.
+        self.m2 = self.m2.max(0.0);
+    }
+
+    /// The number of values in the window, at most `period`.
+    pub fn len(&self) -> usize {
+        self.window.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.window.is_empty()
+    }
+
+    /// Whether the window holds `period` values.
+    pub fn is_full(&self) -> bool {
+        self.window.len() == self.period
+    }
+
+    /// The mean of the window, or `0.0` if empty.
+    pub fn mean(&self) -> f32 {
+        self.mean as f32
+    }
+
+    /// The population variance of the window, or `0.0` if empty.
+    pub fn variance(&self) -> f32 {
+        match self.window.len() {
+            0 => 0.0,
+            len => (self.m2 / len as f64) as f32,
+        }
+    }
+
+    /// The sample variance of the window, or `0.0` with fewer than two values.
+    pub fn sample_variance(&self) -> f32 {
+        match self.window.len() {
+            0 | 1 => 0.0,
+            len => (self.m2 / (len - 1) as f64) as f32,
+        }
+    }
+
+    /// The population standard deviation of the window.
+    pub fn std_dev(&self) -> f32 {
+        self.variance().sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_brute_force() {
+        let period = 50;
+        let mut stats = RollingStats::new(period);
+        let values = (0..5_000)
+            .map(|i| 1_000.0 + 100.0 * (i as f32 * 0.37).sin() + (i % 7) as f32)
+            .collect::<Vec<_>>();
+        for (i, value) in values.iter().enumerate() {
+            stats.push(*value);
+            let window = &values[(i + 1).saturating_sub(period)..=i];
+            assert_eq!(stats.len(), window.len());
+
+            let mean = window.iter().map(|value| *value as f64).sum::<f64>() / window.len() as f64;
+            let variance = window.iter().map(|value| (*value as f64 - mean).powi(2)).sum::<f64>() / window.len() as f64;
+            assert!((stats.mean() as f64 - mean).abs() < 1e-3, "mean at {}", i);
+            assert!((stats.variance() as f64 - variance).abs() < 1e-3 * variance.max(1.0), "variance at {}", i);
+        }
+        assert!(stats.is_full());
+    }
+}
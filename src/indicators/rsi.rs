@@ -3,17 +3,22 @@ use super::*;
 /// [Relative Strength Index](https://www.investopedia.com/terms/r/rsi.asp)
 ///
 /// Measures the speed of a security's recent price changes.
-/// An RSI reading of 30 or below indicates overbought market conditions,
+/// An RSI reading of 70 or above indicates overbought market conditions,
 /// while a reading of 30 or below indicates an oversold condition.
+///
+/// The gains and losses are the changes between consecutive closes. With smoothing,
+/// their averages are Wilder's moving averages; without it, simple averages over the last `period` changes.
 #[derive(Clone)]
 pub struct RSI {
     period: u32,
     smooth: bool,
+    previous_close: Option<f32>,
+    /// The last `period` gains and losses.
     gains: Vec<f32>,
     losses: Vec<f32>,
     previous_average_gain: f32,
     previous_average_loss: f32,
-    values: Vec<f32>,
+    values: History<f32>,
 }
 
 impl Default for RSI {
@@ -34,11 +39,12 @@ impl RSI {
         Self {
             period,
             smooth,
+            previous_close: None,
             gains: Vec::new(),
             losses: Vec::new(),
             previous_average_gain: 0.0,
             previous_average_loss: 0.0,
-            values: Vec::new(),
+            values: History::new(),
         }
     }
 }
@@ -47,40 +53,41 @@ impl Indicator for RSI {
     type Result = f32;
 
     fn update(&mut self, ticker: &Ticker) -> IndicatorResult<()> {
-        let current_gain = if ticker.close > ticker.open {
-            ticker.close - ticker.open
-        } else {
-            0.0
-        };
-        let current_loss = if ticker.close < ticker.open {
-            ticker.open - ticker.close
-        } else {
-            0.0
+        let change = match self.previous_close.replace(ticker.close) {
+            Some(previous_close) => ticker.close - previous_close,
+            None => return Ok(()),
         };
+        let current_gain = change.max(0.0);
+        let current_loss = (-change).max(0.0);
+
+        self.gains.push(current_gain);
+        self.losses.push(current_loss);
+        if self.gains.len() > self.period as usize {
+            self.gains.remove(0);
+            self.losses.remove(0);
+        }
 
-        if self.values.len() < self.period as usize {
-            self.gains.push(current_gain);
-            self.losses.push(current_loss);
+        // Return early if we don't have enough data
+        if self.gains.len() < self.period as usize {
             return Ok(());
         }
 
-        let average_gain = self.gains.iter().sum::<f32>() / self.period as f32;
-        let average_loss = self.losses.iter().sum::<f32>() / self.period as f32;
-
-        let step_one = 100.0 - (100.0 / (1.0 + (average_gain / average_loss)));
+        let period = self.period as f32;
+        let (average_gain, average_loss) = if self.smooth && !self.values.is_empty() {
+            (
+                (self.previous_average_gain * (period - 1.0) + current_gain) / period,
+                (self.previous_average_loss * (period - 1.0) + current_loss) / period,
+            )
+        } else {
+            (self.gains.iter().sum::<f32>() / period, self.losses.iter().sum::<f32>() / period)
+        };
 
-        if self.smooth {
-            let step_two = 100.0
-                - (100.0
-                    / (1.0
-                        + ((self.previous_average_gain * (self.period - 1) as f32
-                            + current_gain)
-                            / (self.previous_average_loss * (self.period - 1) as f32
-                                + current_loss))));
-            self.values.push(step_two);
+        let value = if average_loss == 0.0 {
+            100.0
         } else {
-            self.values.push(step_one);
-        }
+            100.0 - (100.0 / (1.0 + (average_gain / average_loss)))
+        };
+        self.values.push(value);
 
         self.previous_average_gain = average_gain;
         self.previous_average_loss = average_loss;
@@ -89,16 +96,19 @@ impl Indicator for RSI {
 
     fn get_value(&self) -> IndicatorResult<Self::Result> {
         match !self.values.is_empty() {
-            true => Ok(*self.values.last().unwrap()),
+            true => Ok(self.values.last().unwrap()),
             false => Err(IndicatorError::InsufficientData),
         }
     }
 
     fn at(&self, index: usize) -> IndicatorResult<Self::Result> {
-        match index < self.values.len() {
-            true => Ok(*self.values.get(index).unwrap()),
-            false => Err(IndicatorError::IndexOutOfRange),
-        }
+        self.values.get(index).ok_or(IndicatorError::IndexOutOfRange)
+    }
+}
+
+impl WithHistory for RSI {
+    fn history_mut(&mut self) -> &mut History<f32> {
+        &mut self.values
     }
 }
 
@@ -119,4 +129,31 @@ mod tests {
 
         assert!(sma.at(2).is_err());
     }
+
+    fn tick(close: f32) -> Ticker {
+        Ticker {
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 100,
+            datetime: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn period_2() {
+        // The changes are +1, +1, -1, -1.
+        for (smooth, expected) in [(false, [100.0, 50.0, 0.0]), (true, [100.0, 50.0, 25.0])] {
+            let mut rsi = RSI::new(2, smooth);
+            for close in [1.0, 2.0] {
+                rsi.update(&tick(close)).unwrap();
+                assert!(rsi.get_value().is_err());
+            }
+            for (close, expected) in [3.0, 2.0, 1.0].into_iter().zip(expected) {
+                rsi.update(&tick(close)).unwrap();
+                assert_eq!(rsi.get_value().unwrap(), expected);
+            }
+        }
+    }
 }
@@ -7,7 +7,7 @@ pub struct SMA {
     period: u32,
     /// The last `period` closing values.
     ticks: Vec<f32>,
-    values: Vec<f32>,
+    values: History<f32>,
 }
 
 impl Default for SMA {
@@ -22,7 +22,7 @@ impl SMA {
         Self {
             period,
             ticks: Vec::new(),
-            values: Vec::new(),
+            values: History::new(),
         }
     }
 }
@@ -64,14 +64,17 @@ impl Indicator for SMA {
         if self.values.is_empty() {
             return Err(IndicatorError::InsufficientData);
         }
-        Ok(*self.values.last().unwrap())
+        Ok(self.values.last().unwrap())
     }
 
     fn at(&self, index: usize) -> IndicatorResult<Self::Result> {
-        if index < self.values.len() {
-            return Ok(*self.values.get(index).unwrap());
-        }
-        Err(IndicatorError::IndexOutOfRange)
+        self.values.get(index).ok_or(IndicatorError::IndexOutOfRange)
+    }
+}
+
+impl WithHistory for SMA {
+    fn history_mut(&mut self) -> &mut History<f32> {
+        &mut self.values
     }
 }
 
@@ -126,4 +129,23 @@ mod tests {
 
         assert_eq!(sma.get_value().unwrap(), sum / 5.0);
     }
+
+    #[test]
+    fn capped_history() {
+        let mut capped = SMA::new(5).with_history(100);
+        let mut full = SMA::new(5);
+        for i in 0..100_000 {
+            let tick = Ticker {
+                close: (i % 37) as f32,
+                ..get_ticks(1)[0].clone()
+            };
+            capped.update(&tick).unwrap();
+            full.update(&tick).unwrap();
+            assert!(capped.values.retained.len() <= 100);
+        }
+        assert_eq!(capped.get_value().unwrap(), full.get_value().unwrap());
+        assert_eq!(capped.values.len(), full.values.len());
+        assert_eq!(capped.at(full.values.len() - 1).unwrap(), full.get_value().unwrap());
+        assert!(capped.at(0).is_err());
+    }
 }
@@ -0,0 +1,152 @@
+use super::*;
+use crate::{
+	util::serde_ext::yyyy_mm_dd_hh_mm_ss,
+	series::SeriesIntoIterator
+};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::Path;
+
+type Row = HashMap<String, String>;
+
+/// A single-valued series that only changes at discrete dates, such as an interest rate,
+/// [CPI](https://www.investopedia.com/terms/c/consumerpriceindex.asp), the unemployment rate or the VIX.
+///
+/// Reads a CSV with a date column and a value column, and returns the most recent value
+/// as of each ticker's datetime. Rows that cannot be parsed are skipped.
+///
+/// # Example
+///
+/// ```no_run
+/// use backtester::prelude::*;
+///
+/// let cpi = StepSeriesIndicator::from_csv("data/CPIAUCSL.csv", "DATE", "CPIAUCSL");
+/// ```
+#[derive(Clone)]
+pub struct StepSeriesIndicator {
+	date_column: String,
+	value_column: String,
+	current: Option<f32>,
+	/// The next row of the stream, which is dated after the latest ticker.
+	pending: Option<(DateTime<Utc>, f32)>,
+	stream: SeriesIntoIterator<Row>
+}
+
+impl fmt::Display for StepSeriesIndicator {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}({:?})", self.value_column, self.current)
+	}
+}
+
+impl StepSeriesIndicator {
+	/// `date_column` and `value_column` are the CSV headers of the dates and of the values.
+	pub fn from_csv<P: AsRef<Path>>(path: P, date_column: &str, value_column: &str) -> Self {
+		Self {
+			date_column: date_column.to_string(),
+			value_column: value_column.to_string(),
+			current: None,
+			pending: None,
+			stream: Series::<Row>::from_csv(&path).into_iter()
+		}
+	}
+
+	/// Reads the next parseable row of the stream.
+	fn next_row(&mut self) -> Option<(DateTime<Utc>, f32)> {
+		for row in self.stream.by_ref().flatten() {
+			let date = row.get(&self.date_column).and_then(|date| yyyy_mm_dd_hh_mm_ss::parse(date));
+			let value = row.get(&self.value_column).and_then(|value| value.trim().parse().ok());
+			if let (Some(date), Some(value)) = (date, value) {
+				return Some((date, value))
+			}
+		}
+		None
+	}
+}
+
+impl Indicator for StepSeriesIndicator {
+	type Result = f32;
+
+	/// Fails with `InsufficientData` if the ticker is dated after the end of the series,
+	/// since we do not want to extrapolate the data.
+	fn update(&mut self, ticker: &Ticker) -> IndicatorResult<()> {
+		let mut latest = None;
+		loop {
+			let (date, value) = match self.pending.take().or_else(|| self.next_row()) {
+				Some(row) => row,
+				None => return Err(IndicatorError::InsufficientData)
+			};
+			if date > ticker.datetime {
+				self.pending = Some((date, value));
+				break;
+			}
+			latest = Some(value);
+			if date == ticker.datetime {
+				break;
+			}
+		}
+		if latest.is_some() {
+			self.current = latest;
+		}
+		self.get_value().map(|_| ())
+	}
+
+	fn get_value(&self) -> IndicatorResult<Self::Result> {
+		if let Some(result) = self.current {
+			Ok(result)
+		} else {
+			Err(IndicatorError::InsufficientData)
+		}
+	}
+
+	fn at(&self, _: usize) -> IndicatorResult<Self::Result> {
+		Err(IndicatorError::IndexOutOfRange)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chrono::TimeZone;
+
+	fn ticker(datetime: DateTime<Utc>) -> Ticker {
+		Ticker {
+			datetime,
+			open: 0.0,
+			high: 0.0,
+			low: 0.0,
+			close: 0.0,
+			volume: 0,
+		}
+	}
+
+	fn date(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+		Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).unwrap()
+	}
+
+	/// A monthly series queried with daily tickers holds each value until the next release.
+	#[test]
+	fn most_recent_value() {
+		let path = std::env::temp_dir().join(format!("backtester_step_series_{}.csv", std::process::id()));
+		std::fs::write(&path, "observation_date,UNRATE\n2020-01-01,3.6\n2020-02-01,3.5\nnot a date,0.0\n2020-03-01,4.4\n").unwrap();
+		let mut unrate = StepSeriesIndicator::from_csv(&path, "observation_date", "UNRATE");
+
+		// Before the first observation there is no value yet.
+		assert!(unrate.update(&ticker(date(2019, 12, 31))).is_err());
+
+		let expected = [
+			(date(2020, 1, 1), 3.6),
+			(date(2020, 1, 15), 3.6),
+			(date(2020, 2, 1), 3.5),
+			(date(2020, 2, 29), 3.5),
+			(date(2020, 3, 1), 4.4),
+		];
+		for (datetime, value) in expected {
+			assert!(unrate.update(&ticker(datetime)).is_ok());
+			assert_eq!(unrate.get_value().unwrap(), value);
+		}
+		assert_eq!(unrate.to_string(), "UNRATE(Some(4.4))");
+
+		// The series ends with the last observation.
+		assert!(unrate.update(&ticker(date(2020, 3, 2))).is_err());
+	}
+}
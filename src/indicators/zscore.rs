@@ -0,0 +1,106 @@
+use super::*;
+
+/// # [Z-Score](https://www.investopedia.com/terms/z/zscore.asp)
+///
+/// How many standard deviations the latest close lies above (or below, if negative) the mean
+/// of the last `period` closes. The population standard deviation is used, and a window
+/// without any dispersion has a z-score of `0`.
+#[derive(Clone)]
+pub struct ZScore {
+    period: u32,
+    /// The mean and variance of the last `period` closing values.
+    stats: RollingStats,
+    values: History<f32>,
+}
+
+impl Default for ZScore {
+    fn default() -> Self {
+        Self::new(20)
+    }
+}
+
+impl ZScore {
+    /// Default uses a `20` ticker period.
+    pub fn new(period: u32) -> Self {
+        Self {
+            period,
+            stats: RollingStats::new(period as usize),
+            values: History::new(),
+        }
+    }
+}
+
+impl fmt::Display for ZScore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ZScore(Period: {})", self.period)
+    }
+}
+
+impl Indicator for ZScore {
+    type Result = f32;
+
+    fn update(&mut self, ticker: &Ticker) -> IndicatorResult<()> {
+        self.stats.push(ticker.close);
+
+        // Return early if we don't have enough data
+        if !self.stats.is_full() {
+            return Ok(());
+        }
+
+        let value = if self.stats.variance() > 0.0 {
+            (ticker.close - self.stats.mean()) / self.stats.std_dev()
+        } else {
+            0.0
+        };
+        self.values.push(value);
+
+        Ok(())
+    }
+
+    fn get_value(&self) -> IndicatorResult<Self::Result> {
+        self.values.last().ok_or(IndicatorError::InsufficientData)
+    }
+
+    fn at(&self, index: usize) -> IndicatorResult<Self::Result> {
+        self.values.get(index).ok_or(IndicatorError::IndexOutOfRange)
+    }
+}
+
+impl WithHistory for ZScore {
+    fn history_mut(&mut self) -> &mut History<f32> {
+        &mut self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn tick(close: f32) -> Ticker {
+        Ticker {
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 100,
+            datetime: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn period_4() {
+        let mut zscore = ZScore::new(4);
+        for close in [1.0, 1.0, 1.0] {
+            zscore.update(&tick(close)).unwrap();
+            assert!(zscore.get_value().is_err());
+        }
+        zscore.update(&tick(1.0)).unwrap();
+        assert_eq!(zscore.get_value().unwrap(), 0.0);
+
+        // The window is now [1, 1, 1, 5], with a mean of 2 and a standard deviation of sqrt(3).
+        zscore.update(&tick(5.0)).unwrap();
+        assert_eq!(zscore.get_value().unwrap(), 3.0 / 3.0_f32.sqrt());
+        assert_eq!(zscore.at(0).unwrap(), 0.0);
+    }
+}
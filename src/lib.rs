@@ -37,13 +37,13 @@
 //!
 //! fn main() -> Result<(), BacktestError> {
 //! 	let aapl_timeseries = TimeSeries::from_csv("./benches/datasets/timeseries/AAC.csv");
-//! 	let broker = Broker::new("Simple Backtest", 100_000.0, 0.0, 0.0, false, false);
+//! 	let broker = BrokerBuilder::new("Simple Backtest").initial_cash(100_000.0).build();
 //! 	let strategy = Box::new(SMACrossover::default());
 //! 	let backtest = BacktestBuilder::new()
 //! 	               .add_feed(aapl_timeseries)
 //! 	               .add_broker(broker)
 //! 	               .add_strategy(strategy)
-//! 	               .build();
+//! 	               .build()?;
 //!
 //! 	for test in backtest {
 //! 		let results = test.run()?;
@@ -124,13 +124,14 @@
 //! 
 //!    fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
 //!       if ticker.close > 100.0 {
-//!         broker.submit_order(1, Order {
+//!         broker.submit_order(Order {
 //!                symbol: "AAPL".to_string(),
 //!                quantity: 100.0,
 //!                side: OrderSide::Buy,
 //!                order_type: OrderType::Market,
 //!                datetime: ticker.datetime.clone(),
 //!                execution: OrderExecutionStrategy::GTC,
+//!                reduce_only: false,
 //!                on_execute: None,
 //!                on_cancel: None,
 //!         })?;
@@ -142,9 +143,16 @@
 
 mod backtest;
 pub mod broker;
+pub mod calendar;
+pub mod feed;
 pub mod indicators;
+pub mod metrics;
+pub mod monte_carlo;
+pub mod optimizer;
+pub mod report;
 pub mod strategy;
 pub mod series;
+pub mod sizing;
 pub mod timeseries;
 mod types;
 mod util;
@@ -152,7 +160,11 @@ mod util;
 pub mod prelude {
     pub use crate::backtest::*;
     pub use crate::broker::*;
+    pub use crate::calendar::*;
+    pub use crate::feed::*;
     pub use crate::indicators::*;
+    pub use crate::optimizer::*;
+    pub use crate::report::*;
     pub use crate::strategy::*;
     pub use crate::series::*;
     pub use crate::timeseries::*;
@@ -0,0 +1,21 @@
+//! Runs the SMA crossover strategy over a CSV of tickers and prints the results.
+//!
+//! Usage: `backtester [path]`, where `path` defaults to the bundled `AAC.csv` dataset.
+use backtester::prelude::*;
+use backtester::strategy::SMACrossover;
+use std::env;
+
+fn main() -> Result<(), BacktestError> {
+    env_logger::init();
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "./benches/datasets/timeseries/AAC.csv".to_string());
+    let backtest = Backtest::new(
+        TimeSeries::from_csv(path),
+        BrokerBuilder::new("Simple Backtest").build(),
+        Box::new(SMACrossover::default()),
+    );
+    let result = backtest.run()?;
+    println!("{}", result);
+    Ok(())
+}
@@ -0,0 +1,260 @@
+//! Performance statistics computed from an equity curve.
+//!
+//! These are plain functions over slices so they can be reused by `BacktestResult`,
+//! the `Optimizer`, and any custom analysis.
+use chrono::{DateTime, Duration, Utc};
+
+/// The bar frequencies recognized by `periods_per_year`, as the spacing between bars
+/// and the number of bars in a trading year of 252 days of 6.5 hours.
+const FREQUENCIES: [(i64, f32); 6] = [
+    (60, 252.0 * 390.0),
+    (60 * 60, 252.0 * 6.5),
+    (24 * 60 * 60, 252.0),
+    (7 * 24 * 60 * 60, 52.0),
+    (30 * 24 * 60 * 60, 12.0),
+    (365 * 24 * 60 * 60, 1.0),
+];
+
+/// The per-period fractional returns of an equity curve.
+/// The result has one element fewer than `equity`.
+pub fn returns(equity: &[f32]) -> Vec<f32> {
+    equity
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]) / pair[0])
+        .collect()
+}
+
+/// The arithmetic mean of `values`, or `0.0` if empty.
+pub fn mean(values: &[f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f32>() / values.len() as f32
+}
+
+/// The sample standard deviation of `values`, or `0.0` with fewer than two values.
+pub fn std_dev(values: &[f32]) -> f32 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mean = mean(values);
+    let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f32>()
+        / (values.len() - 1) as f32;
+    variance.sqrt()
+}
+
+/// The sample covariance of `a` and `b`, or `0.0` with fewer than two pairs.
+/// Only the first `min(a.len(), b.len())` values of each are used.
+pub fn covariance(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    if len < 2 {
+        return 0.0;
+    }
+    let (a, b) = (&a[..len], &b[..len]);
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    a.iter().zip(b).map(|(a, b)| (a - mean_a) * (b - mean_b)).sum::<f32>() / (len - 1) as f32
+}
+
+/// The annualized [volatility](https://www.investopedia.com/terms/v/volatility.asp)
+/// of per-period `returns`, i.e. their standard deviation scaled by `sqrt(periods_per_year)`.
+pub fn annualized_volatility(returns: &[f32], periods_per_year: f32) -> f32 {
+    std_dev(returns) * periods_per_year.sqrt()
+}
+
+/// The [beta](https://www.investopedia.com/terms/b/beta.asp) of per-period `returns` against
+/// the `benchmark` returns of the same periods, i.e. their covariance over the benchmark's variance.
+///
+/// Returns `0.0` if the benchmark returns have no variance.
+pub fn beta(returns: &[f32], benchmark: &[f32]) -> f32 {
+    let len = returns.len().min(benchmark.len());
+    let variance = std_dev(&benchmark[..len]).powi(2);
+    if variance == 0.0 {
+        return 0.0;
+    }
+    covariance(returns, benchmark) / variance
+}
+
+/// Infers the number of bars in a year from their `datetimes`, to annualize metrics such as
+/// `sharpe_ratio`. The median spacing between bars is matched (within 10%) against minute,
+/// hourly, daily, weekly, monthly and yearly bars, which have `252 * 390`, `252 * 6.5`, `252`,
+/// `52`, `12` and `1` bars a year. Weekend and overnight gaps thus do not skew the result.
+///
+/// Irregular spacing falls back to the number of bars per elapsed calendar year, and fewer
+/// than two distinct datetimes to `252.0`.
+pub fn periods_per_year(datetimes: &[DateTime<Utc>]) -> f32 {
+    let mut spacings = datetimes
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).num_seconds())
+        .collect::<Vec<_>>();
+    spacings.sort_unstable();
+    let median = match spacings.get(spacings.len() / 2) {
+        Some(median) if *median > 0 => *median,
+        _ => return 252.0,
+    };
+
+    for (spacing, periods) in FREQUENCIES {
+        if (median - spacing).abs() * 10 <= spacing {
+            return periods;
+        }
+    }
+    let elapsed = datetimes[datetimes.len() - 1] - datetimes[0];
+    spacings.len() as f32 / (elapsed.num_seconds() as f32 / Duration::days(365).num_seconds() as f32)
+}
+
+/// The [internal rate of return](https://www.investopedia.com/terms/i/irr.asp) of `flows`,
+/// i.e. the rate `r` at which `sum(amount * (1 + r)^(-time))` is zero, where `time` is the
+/// number of periods (possibly fractional) from the start. Money paid in is negative and
+/// money paid out (e.g. the final equity) is positive.
+///
+/// The rate is found by bisection in `(-1, 1e6)`. Returns NaN if the flows do not change
+/// sign within that range, e.g. if they are all of the same sign.
+pub fn irr(flows: &[(f32, f32)]) -> f32 {
+    let npv = |rate: f64| -> f64 {
+        flows
+            .iter()
+            .map(|(time, amount)| *amount as f64 * (1.0 + rate).powf(-*time as f64))
+            .sum()
+    };
+    let (mut low, mut high) = (-1.0 + 1e-9, 1e6);
+    if npv(low).signum() == npv(high).signum() {
+        return f32::NAN;
+    }
+    for _ in 0..200 {
+        let mid = (low + high) / 2.0;
+        if npv(mid).signum() == npv(low).signum() {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    ((low + high) / 2.0) as f32
+}
+
+/// The annualized [Sharpe ratio](https://www.investopedia.com/terms/s/sharperatio.asp)
+/// of per-period `returns`, assuming a risk-free rate of zero.
+///
+/// Returns `0.0` if the returns have no variance.
+pub fn sharpe_ratio(returns: &[f32], periods_per_year: f32) -> f32 {
+    let std_dev = std_dev(returns);
+    if std_dev == 0.0 {
+        return 0.0;
+    }
+    mean(returns) / std_dev * periods_per_year.sqrt()
+}
+
+/// The decline of every point of an equity curve from the running peak, as a fraction
+/// of the peak. Each value is negative, or `0.0` at a new high.
+pub fn drawdowns(equity: &[f32]) -> Vec<f32> {
+    let mut peak = f32::MIN;
+    equity
+        .iter()
+        .map(|value| {
+            peak = peak.max(*value);
+            (value - peak) / peak
+        })
+        .collect()
+}
+
+/// The largest peak-to-trough decline of an equity curve as a fraction of the peak.
+/// The value is negative (or `0.0` if the curve never declines).
+pub fn max_drawdown(equity: &[f32]) -> f32 {
+    drawdowns(equity).into_iter().fold(0.0, f32::min)
+}
+
+/// The `q`-th quantile (in `[0, 1]`) of `sorted`, linearly interpolating between
+/// neighbouring values. `sorted` must be in ascending order. Returns NaN if empty.
+pub fn percentile(sorted: &[f32], q: f32) -> f32 {
+    if sorted.is_empty() {
+        return f32::NAN;
+    }
+    let rank = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f32;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn returns_of_curve() {
+        assert_eq!(returns(&[100.0, 110.0, 99.0]), vec![0.1, -0.1]);
+    }
+
+    #[test]
+    fn sharpe_of_constant_returns() {
+        assert_eq!(sharpe_ratio(&[0.01, 0.01, 0.01], 252.0), 0.0);
+    }
+
+    #[test]
+    fn sharpe_of_known_returns() {
+        // mean = 0.02, sample std = 0.02
+        let sharpe = sharpe_ratio(&[0.0, 0.02, 0.04], 4.0);
+        assert!((sharpe - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn volatility_of_known_returns() {
+        // mean = 0.02, sample std = 0.02
+        let volatility = annualized_volatility(&[0.0, 0.02, 0.04], 4.0);
+        assert!((volatility - 0.04).abs() < 1e-6);
+        assert_eq!(annualized_volatility(&[0.01, 0.01, 0.01], 252.0), 0.0);
+    }
+
+    #[test]
+    fn beta_of_known_returns() {
+        let benchmark = [0.01, -0.02, 0.03, 0.0];
+        // Twice the benchmark's moves, plus a constant that does not affect the covariance.
+        let levered = benchmark.map(|r| 2.0 * r + 0.001);
+        assert!((beta(&levered, &benchmark) - 2.0).abs() < 1e-5);
+        // cov([0.01, 0.03, 0.02], [0.01, -0.01, 0.03]) = -0.0001, var(benchmark) = 0.0004
+        assert!((beta(&[0.01, 0.03, 0.02], &[0.01, -0.01, 0.03]) + 0.25).abs() < 1e-5);
+        assert_eq!(beta(&[0.01, 0.02], &[0.01, 0.01]), 0.0);
+    }
+
+    #[test]
+    fn periods_per_year_of_bars() {
+        let bars = |start: i64, spacings: &[i64]| {
+            let mut datetime = start;
+            let mut datetimes = vec![DateTime::from_timestamp(datetime, 0).unwrap()];
+            for spacing in spacings {
+                datetime += spacing;
+                datetimes.push(DateTime::from_timestamp(datetime, 0).unwrap());
+            }
+            datetimes
+        };
+        let day = 86_400;
+        // Two weeks of daily bars, with a weekend gap in the middle.
+        let daily = bars(0, &[day, day, day, day, 3 * day, day, day, day, day]);
+        assert_eq!(periods_per_year(&daily), 252.0);
+        assert_eq!(periods_per_year(&bars(0, &[3600; 20])), 1638.0);
+        assert_eq!(periods_per_year(&bars(0, &[60, 60, 60, 17 * 3600, 60])), 98_280.0);
+
+        // Bars every two days match no frequency, so there are 365 / 2 of them a year.
+        assert!((periods_per_year(&bars(0, &[2 * day; 10])) - 182.5).abs() < 1e-3);
+        assert_eq!(periods_per_year(&bars(0, &[])), 252.0);
+    }
+
+    #[test]
+    fn irr_of_known_flows() {
+        // Paying 100 for 121 two periods later earns 10% a period.
+        assert!((irr(&[(0.0, -100.0), (2.0, 121.0)]) - 0.1).abs() < 1e-5);
+        assert!((irr(&[(0.0, -100.0), (1.0, -100.0), (2.0, 200.0)])).abs() < 1e-5);
+        assert!(irr(&[(0.0, 100.0), (1.0, 100.0)]).is_nan());
+    }
+
+    #[test]
+    fn percentile_interpolates() {
+        let sorted = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 0.5), 2.5);
+        assert_eq!(percentile(&sorted, 1.0), 4.0);
+    }
+
+    #[test]
+    fn max_drawdown_of_curve() {
+        let drawdown = max_drawdown(&[100.0, 120.0, 90.0, 130.0, 117.0]);
+        assert!((drawdown + 0.25).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,153 @@
+//! [Monte Carlo](https://www.investopedia.com/terms/m/montecarlosimulation.asp) analysis of trade sequences.
+//!
+//! A single backtest produces a single ordering of trades. Resampling the per-trade
+//! returns shows how much of the outcome depends on that particular ordering (or luck),
+//! by reporting the spread of final equity and maximum drawdown over many simulated paths.
+use crate::{
+    metrics,
+    types::{OrderSide, Trade},
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashMap;
+
+/// The realized return of every trade that reduces or closes a position, relative to the
+/// position's average entry price. Returns are gross of commission.
+pub fn trade_returns(trades: &[Trade]) -> Vec<f32> {
+    // (amount, average price) per symbol
+    let mut positions: HashMap<&str, (f32, f32)> = HashMap::new();
+    let mut returns = Vec::new();
+    for trade in trades {
+        let quantity = match trade.side {
+            OrderSide::Buy => trade.quantity,
+            OrderSide::Sell => -trade.quantity,
+        };
+        let (amount, price) = positions.entry(&trade.symbol).or_insert((0.0, 0.0));
+        if *amount == 0.0 || amount.signum() == quantity.signum() {
+            *price = (*amount * *price + quantity * trade.price) / (*amount + quantity);
+            *amount += quantity;
+            continue;
+        }
+
+        returns.push((trade.price - *price) / *price * amount.signum());
+        let remaining = *amount + quantity;
+        if remaining.abs() <= f32::EPSILON {
+            *amount = 0.0;
+        } else if remaining.signum() != amount.signum() {
+            // The trade flipped the position, so the remainder opens at the trade price.
+            *price = trade.price;
+            *amount = remaining;
+        } else {
+            *amount = remaining;
+        }
+    }
+    returns
+}
+
+/// The distribution of outcomes over every simulated path. See `monte_carlo`.
+pub struct MonteCarlo {
+    /// Final equity of each path as a multiple of the starting equity, in ascending order.
+    pub final_equity: Vec<f32>,
+    /// Maximum drawdown of each path as a negative fraction, in ascending order.
+    pub max_drawdown: Vec<f32>,
+}
+
+impl MonteCarlo {
+    /// The `q`-th quantile (in `[0, 1]`) of the final equity.
+    pub fn final_equity_percentile(&self, q: f32) -> f32 {
+        metrics::percentile(&self.final_equity, q)
+    }
+
+    /// The `q`-th quantile (in `[0, 1]`) of the maximum drawdown.
+    /// Low quantiles are the deepest drawdowns.
+    pub fn max_drawdown_percentile(&self, q: f32) -> f32 {
+        metrics::percentile(&self.max_drawdown, q)
+    }
+}
+
+/// Simulates `iterations` equity paths by resampling the per-trade returns of `trades`
+/// with replacement, compounding each path from a starting equity of `1.0`.
+///
+/// The simulation is deterministic for a given `seed`.
+pub fn monte_carlo(trades: &[Trade], iterations: usize, seed: u64) -> MonteCarlo {
+    let returns = trade_returns(trades);
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut final_equity = Vec::with_capacity(iterations);
+    let mut max_drawdown = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let mut path = Vec::with_capacity(returns.len() + 1);
+        path.push(1.0);
+        for _ in 0..returns.len() {
+            let sample = returns[rng.gen_range(0..returns.len())];
+            path.push(path[path.len() - 1] * (1.0 + sample));
+        }
+        final_equity.push(path[path.len() - 1]);
+        max_drawdown.push(metrics::max_drawdown(&path));
+    }
+    final_equity.sort_by(|a, b| a.total_cmp(b));
+    max_drawdown.sort_by(|a, b| a.total_cmp(b));
+    MonteCarlo {
+        final_equity,
+        max_drawdown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn trade(side: OrderSide, quantity: f32, price: f32) -> Trade {
+        Trade {
+            symbol: "AAPL".to_string(),
+            side,
+            quantity,
+            price,
+            commission: 0.0,
+            datetime: Utc.timestamp_opt(0, 0).unwrap(),
+            market_price: price,
+            maker: false,
+            fx_rate: 1.0,
+            limit: None,
+        }
+    }
+
+    fn round_trips() -> Vec<Trade> {
+        let mut trades = Vec::new();
+        for (entry, exit) in [(100.0, 110.0), (100.0, 95.0), (100.0, 120.0), (100.0, 90.0), (100.0, 105.0)] {
+            trades.push(trade(OrderSide::Buy, 10.0, entry));
+            trades.push(trade(OrderSide::Sell, 10.0, exit));
+        }
+        trades
+    }
+
+    #[test]
+    fn returns_of_round_trips() {
+        let returns = trade_returns(&round_trips());
+        let expected = [0.1, -0.05, 0.2, -0.1, 0.05];
+        assert_eq!(returns.len(), expected.len());
+        for (actual, expected) in returns.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn returns_of_short() {
+        let trades = vec![trade(OrderSide::Sell, 10.0, 100.0), trade(OrderSide::Buy, 10.0, 90.0)];
+        let returns = trade_returns(&trades);
+        assert_eq!(returns.len(), 1);
+        assert!((returns[0] - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn seeded_runs_are_stable() {
+        let first = monte_carlo(&round_trips(), 500, 42);
+        let second = monte_carlo(&round_trips(), 500, 42);
+        for q in [0.05, 0.5, 0.95] {
+            assert_eq!(first.final_equity_percentile(q), second.final_equity_percentile(q));
+            assert_eq!(first.max_drawdown_percentile(q), second.max_drawdown_percentile(q));
+        }
+        assert!(first.final_equity_percentile(0.05) < first.final_equity_percentile(0.95));
+        assert!(first.max_drawdown_percentile(0.95) <= 0.0);
+    }
+}
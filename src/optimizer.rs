@@ -0,0 +1,333 @@
+//! Grid-search over strategy parameters.
+//!
+//! The `Optimizer` builds one `Backtest` per parameter set with the `BacktestBuilder`,
+//! runs each of them in turn against the same feed and broker, and scores the results with a `Metric`.
+//!
+//! ```no_run
+//! use backtester::prelude::*;
+//! use backtester::strategy::SMACrossover;
+//!
+//! fn main() -> Result<(), BacktestError> {
+//!     let optimization = Optimizer::new(
+//!         TimeSeries::from_csv("./benches/datasets/timeseries/AAC.csv"),
+//!         Broker::new("Optimizer", 100_000.0, 0.0, 1.0, false, false),
+//!         |period: &u32| Box::new(SMACrossover::new(*period)) as Box<dyn Strategy>,
+//!     )
+//!     .grid(vec![5, 10, 20])
+//!     .metric(Metric::SharpeRatio(252.0))
+//!     .run()?;
+//!
+//!     if let Some((period, score)) = optimization.best() {
+//!         println!("Best period: {} ({})", period, score);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use crate::{
+    backtest::{Backtest, BacktestBuilder, BacktestError, BacktestResult},
+    broker::Broker,
+    strategy::Strategy,
+    timeseries::TimeSeries,
+};
+use chrono::{DateTime, Duration, Utc};
+
+/// The statistic of a `BacktestResult` that the `Optimizer` maximizes.
+#[derive(Debug, Clone, Copy)]
+pub enum Metric {
+    TotalReturn,
+    /// Annualized with the given number of tickers per year.
+    SharpeRatio(f32),
+    /// Drawdowns are negative, so maximizing picks the shallowest one.
+    MaxDrawdown,
+}
+
+impl Metric {
+    pub fn evaluate(&self, result: &BacktestResult) -> f32 {
+        match self {
+            Metric::TotalReturn => result.total_return(),
+            Metric::SharpeRatio(periods_per_year) => result.sharpe_ratio(*periods_per_year),
+            Metric::MaxDrawdown => result.max_drawdown(),
+        }
+    }
+}
+
+/// Runs a strategy once for every parameter set in a grid.
+pub struct Optimizer<P, F>
+where
+    F: Fn(&P) -> Box<dyn Strategy>,
+{
+    feed: TimeSeries,
+    broker: Broker,
+    make_strategy: F,
+    grid: Vec<P>,
+    metric: Metric,
+}
+
+impl<P, F> Optimizer<P, F>
+where
+    F: Fn(&P) -> Box<dyn Strategy>,
+{
+    /// `make_strategy` constructs the strategy under test from a single parameter set.
+    /// The metric defaults to `Metric::TotalReturn`.
+    pub fn new(feed: TimeSeries, broker: Broker, make_strategy: F) -> Self {
+        Self {
+            feed,
+            broker,
+            make_strategy,
+            grid: Vec::new(),
+            metric: Metric::TotalReturn,
+        }
+    }
+
+    /// The parameter sets to evaluate. For several parameters, use a tuple per combination.
+    pub fn grid(mut self, grid: Vec<P>) -> Self {
+        self.grid = grid;
+        self
+    }
+
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Runs and scores one backtest per parameter set, in the order of the grid.
+    ///
+    /// The runs are sequential. Brokers and orders hold their callbacks in `Rc`s, so neither
+    /// they nor the backtests that own them are `Send`, and a run cannot move to another thread.
+    pub fn run(self) -> Result<Optimization<P>, BacktestError> {
+        if self.grid.is_empty() {
+            return Ok(Optimization { scores: Vec::new() });
+        }
+        let mut builder = BacktestBuilder::new()
+            .add_feed(self.feed)
+            .add_broker(self.broker);
+        for params in &self.grid {
+            builder = builder.add_strategy((self.make_strategy)(params));
+        }
+
+        // With a single feed and broker, the builder yields one backtest per strategy, in order.
+        let mut scores = Vec::new();
+        for (params, backtest) in self.grid.into_iter().zip(builder.build()?) {
+            let result = backtest.run()?;
+            scores.push((params, self.metric.evaluate(&result)));
+        }
+        Ok(Optimization { scores })
+    }
+}
+
+/// The score of every parameter set evaluated by an `Optimizer`.
+pub struct Optimization<P> {
+    pub scores: Vec<(P, f32)>,
+}
+
+impl<P> Optimization<P> {
+    /// The parameter set with the highest score, ignoring NaN scores.
+    pub fn best(&self) -> Option<&(P, f32)> {
+        self.scores
+            .iter()
+            .filter(|(_, score)| !score.is_nan())
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+/// One fold of a walk-forward analysis.
+///
+/// The in-sample period is `[in_sample_start, out_of_sample_start)` and the
+/// out-of-sample period is `[out_of_sample_start, out_of_sample_end)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub in_sample_start: DateTime<Utc>,
+    pub out_of_sample_start: DateTime<Utc>,
+    pub out_of_sample_end: DateTime<Utc>,
+}
+
+impl Window {
+    /// Creates `folds` windows starting at `start`, each rolled forward by `out_of_sample`
+    /// so that consecutive out-of-sample periods are contiguous.
+    pub fn rolling(
+        start: DateTime<Utc>,
+        in_sample: Duration,
+        out_of_sample: Duration,
+        folds: usize,
+    ) -> Vec<Window> {
+        (0..folds)
+            .map(|fold| {
+                let in_sample_start = start + out_of_sample * fold as i32;
+                let out_of_sample_start = in_sample_start + in_sample;
+                Window {
+                    in_sample_start,
+                    out_of_sample_start,
+                    out_of_sample_end: out_of_sample_start + out_of_sample,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The out-of-sample evaluation of a single `Window`.
+pub struct Fold {
+    pub window: Window,
+    pub result: BacktestResult,
+    pub score: f32,
+}
+
+/// The folds of a walk-forward analysis. See `walk_forward`.
+pub struct WalkForward {
+    pub folds: Vec<Fold>,
+}
+
+impl WalkForward {
+    /// The out-of-sample equity curves of every fold, chained together so that each
+    /// fold starts from the equity that the previous fold finished with.
+    pub fn equity_curve(&self) -> Vec<(DateTime<Utc>, f32)> {
+        let mut curve = Vec::new();
+        let mut growth = 1.0;
+        for fold in &self.folds {
+            let initial = fold.result.get_broker().get_initial_cash();
+            for (datetime, equity) in fold.result.get_equity_curve() {
+                curve.push((*datetime, equity * growth));
+            }
+            if let Some((_, last)) = fold.result.get_equity_curve().last() {
+                growth *= last / initial;
+            }
+        }
+        curve
+    }
+}
+
+/// Collects the tickers in `[start, end)` into an in-memory feed.
+fn slice(feed: &TimeSeries, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<TimeSeries, BacktestError> {
+    let tickers = feed
+        .clone()
+        .between(start, end - Duration::nanoseconds(1))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| {
+            let line = err.position().map(|pos| pos.line()).unwrap_or(0);
+            BacktestError::TickerParseError(line, err)
+        })?;
+    Ok(TimeSeries::from_vec(tickers))
+}
+
+/// Runs a [walk-forward analysis](https://www.investopedia.com/terms/w/walk-forward-testing.asp).
+///
+/// For each window, `optimize` receives the in-sample slice of `feed` and the `metric`,
+/// and returns the strategy to trade. That strategy is then run on the out-of-sample
+/// slice with a fresh copy of `broker` and scored with `metric`.
+pub fn walk_forward<F>(
+    feed: TimeSeries,
+    broker: Broker,
+    windows: &[Window],
+    optimize: F,
+    metric: Metric,
+) -> Result<WalkForward, BacktestError>
+where
+    F: Fn(TimeSeries, Metric) -> Result<Box<dyn Strategy>, BacktestError>,
+{
+    let mut folds = Vec::new();
+    for window in windows {
+        let in_sample = slice(&feed, window.in_sample_start, window.out_of_sample_start)?;
+        let out_of_sample = slice(&feed, window.out_of_sample_start, window.out_of_sample_end)?;
+        let strategy = optimize(in_sample, metric)?;
+        let result = Backtest::new(out_of_sample, broker.clone(), strategy).run()?;
+        folds.push(Fold {
+            window: *window,
+            score: metric.evaluate(&result),
+            result,
+        });
+    }
+    Ok(WalkForward { folds })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{strategy::SMACrossover, types::Ticker};
+    use chrono::TimeZone;
+
+    /// A trending series with an oscillation, so that different SMA periods trade differently.
+    fn crafted_feed() -> TimeSeries {
+        TimeSeries::from_vec(
+            (0..200)
+                .map(|day| {
+                    let close = 100.0 + 0.1 * day as f32 + 5.0 * (day as f32 / 6.0).sin();
+                    Ticker {
+                        open: close,
+                        high: close + 0.5,
+                        low: close - 0.5,
+                        close,
+                        volume: 1000,
+                        datetime: Utc.timestamp_opt(day * 86_400, 0).unwrap(),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    fn broker() -> Broker {
+        Broker::new("Optimizer", 100_000.0, 0.0, 1.0, false, false)
+    }
+
+    #[test]
+    fn optimize_sma_crossover_period() {
+        let optimization = Optimizer::new(crafted_feed(), broker(), |period: &u32| {
+            Box::new(SMACrossover::new(*period)) as Box<dyn Strategy>
+        })
+        .grid(vec![5, 10, 20])
+        .run()
+        .unwrap();
+
+        let periods = optimization.scores.iter().map(|(period, _)| *period).collect::<Vec<_>>();
+        assert_eq!(periods, vec![5, 10, 20]);
+        // The shorter averages trade the oscillation and lose the most to it.
+        assert!(optimization.scores[0].1 < optimization.scores[1].1);
+        assert_eq!(optimization.best().map(|(period, _)| *period), Some(20));
+    }
+
+    #[test]
+    fn optimize_empty_grid() {
+        let optimization = Optimizer::new(crafted_feed(), broker(), |period: &u32| {
+            Box::new(SMACrossover::new(*period)) as Box<dyn Strategy>
+        })
+        .run()
+        .unwrap();
+        assert!(optimization.best().is_none());
+    }
+
+    #[test]
+    fn walk_forward_folds_are_contiguous() {
+        let windows = Window::rolling(
+            Utc.timestamp_opt(0, 0).unwrap(),
+            Duration::days(60),
+            Duration::days(40),
+            2,
+        );
+        let analysis = walk_forward(crafted_feed(), broker(), &windows, |in_sample, metric| {
+            let optimization = Optimizer::new(in_sample, broker(), |period: &u32| {
+                Box::new(SMACrossover::new(*period)) as Box<dyn Strategy>
+            })
+            .grid(vec![5, 10, 20])
+            .metric(metric)
+            .run()?;
+            let (period, _) = optimization.best().unwrap();
+            Ok(Box::new(SMACrossover::new(*period)))
+        }, Metric::TotalReturn)
+        .unwrap();
+
+        assert_eq!(analysis.folds.len(), 2);
+        for fold in &analysis.folds {
+            let curve = fold.result.get_equity_curve();
+            assert_eq!(curve.len(), 40);
+            assert_eq!(curve.first().unwrap().0, fold.window.out_of_sample_start);
+            assert!(curve.last().unwrap().0 < fold.window.out_of_sample_end);
+        }
+
+        // The second out-of-sample period picks up exactly where the first left off.
+        let first = analysis.folds[0].result.get_equity_curve();
+        let second = analysis.folds[1].result.get_equity_curve();
+        assert_eq!(second.first().unwrap().0, first.last().unwrap().0 + Duration::days(1));
+
+        let stitched = analysis.equity_curve();
+        assert_eq!(stitched.len(), 80);
+        assert!(stitched.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+}
@@ -0,0 +1,137 @@
+//! Side-by-side comparison of many backtests, e.g. the runs of a `BacktestBuilder`.
+//!
+//! ```no_run
+//! use backtester::prelude::*;
+//! use backtester::strategy::{BuyAndHold, SMACrossover};
+//!
+//! fn main() -> Result<(), BacktestError> {
+//!     let results = BacktestBuilder::new()
+//!         .add_feed(TimeSeries::from_csv("./benches/datasets/timeseries/AAC.csv"))
+//!         .add_broker(Broker::new("Simple", 100_000.0, 0.0, 1.0, false, false))
+//!         .add_strategy(Box::new(BuyAndHold::default()))
+//!         .add_strategy(Box::new(SMACrossover::new(20)))
+//!         .build()?
+//!         .into_iter()
+//!         .map(Backtest::run)
+//!         .collect::<Result<Vec<_>, _>>()?;
+//!     println!("{}", RunReport::from_results(results));
+//!     Ok(())
+//! }
+//! ```
+use crate::{backtest::BacktestResult, optimizer::Metric};
+use std::fmt;
+
+/// The results of many backtests, ranked from best to worst by a `Metric`.
+pub struct RunReport {
+    results: Vec<BacktestResult>,
+}
+
+impl RunReport {
+    /// Ranks `results` by their total return.
+    pub fn from_results(results: Vec<BacktestResult>) -> Self {
+        Self { results }.ranked_by(Metric::TotalReturn)
+    }
+
+    /// Re-ranks the results by `metric`, best first. Ties keep their order.
+    pub fn ranked_by(mut self, metric: Metric) -> Self {
+        self.results.sort_by(|a, b| metric.evaluate(b).total_cmp(&metric.evaluate(a)));
+        self
+    }
+
+    /// The results in their ranked order.
+    pub fn get_results(&self) -> &[BacktestResult] {
+        &self.results
+    }
+}
+
+/// Formats the report as a table of every run's strategy, feed and broker with its
+/// total return, Sharpe ratio (annualized from the spacing of its tickers) and maximum drawdown.
+impl fmt::Display for RunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = ["Rank", "Strategy", "Feed", "Broker", "Total Return", "Sharpe Ratio", "Max Drawdown"]
+            .map(String::from);
+        let mut rows = vec![header];
+        for (rank, result) in self.results.iter().enumerate() {
+            rows.push([
+                (rank + 1).to_string(),
+                result.get_strategy_name().to_string(),
+                result.get_feed_path().to_string_lossy().into_owned(),
+                result.get_broker_name().to_string(),
+                format!("{:.4}", result.total_return()),
+                format!("{:.4}", result.sharpe_ratio(result.periods_per_year())),
+                format!("{:.4}", result.max_drawdown()),
+            ]);
+        }
+
+        let mut widths = [0; 7];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        for row in &rows {
+            let cells = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<_>>();
+            writeln!(f, "{}", cells.join(" | ").trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backtest::BacktestBuilder,
+        broker::{BrokerBuilder, CommissionModel},
+        strategy::BuyAndHold,
+        timeseries::TimeSeries,
+        types::Ticker,
+    };
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn ranks_runs_by_total_return() {
+        let feed = TimeSeries::from_vec(
+            (0..20)
+                .map(|day| Ticker {
+                    open: 100.0 + day as f32,
+                    high: 100.0 + day as f32,
+                    low: 100.0 + day as f32,
+                    close: 100.0 + day as f32,
+                    volume: 1000,
+                    datetime: Utc.timestamp_opt(day * 86_400, 0).unwrap(),
+                })
+                .collect(),
+        );
+        let broker = |name, fee| BrokerBuilder::new(name).commission_model(CommissionModel::Fixed(fee)).build();
+        let results = BacktestBuilder::new()
+            .add_feed(feed)
+            .add_broker(broker("Expensive", 500.0))
+            .add_broker(broker("Free", 0.0))
+            .add_broker(broker("Cheap", 50.0))
+            .add_strategy(Box::new(BuyAndHold::default()))
+            .build()
+            .unwrap()
+            .into_iter()
+            .map(|backtest| backtest.run().unwrap())
+            .collect();
+
+        let report = RunReport::from_results(results);
+        let names = report
+            .get_results()
+            .iter()
+            .map(|result| result.get_broker().get_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Free", "Cheap", "Expensive"]);
+
+        let table = report.to_string();
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("Rank | Strategy     | Feed     | Broker    | Total Return"));
+        assert!(lines[1].starts_with("1    | Buy and Hold | <memory> | Free      | 0.0180"));
+    }
+}
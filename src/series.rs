@@ -1,7 +1,7 @@
 //! Data streams for backtesting.
 //!
 //! ## Limitations
-//! Currently, the only supported data source is CSV files.
+//! Currently, the only supported data sources are CSV files and in-memory vectors (`Series::from_vec`).
 //! Furthermore, the CSV file must contain the following columns:
 //!
 //! - open
@@ -12,13 +12,93 @@
 //! - datetime
 //!
 //! If any of these columns are omitted, deserialization will fail.
+//! Files that use different header names can be mapped with a `SeriesSchema`.
 use std::fs::File;
 use std::path::{Path, PathBuf};
-use crate::util::serde_ext::*;
+use std::sync::Arc;
 
-/// Provides a stream of 'Tickers' from a CSV file.
+/// The default size in bytes of the read buffer of a CSV-backed `Series`, that of the
+/// underlying CSV reader. See `Series::with_buffer_capacity`.
+pub const DEFAULT_BUFFER_CAPACITY: usize = 1 << 13;
+
+#[derive(Debug)]
+pub enum SeriesError {
+    /// The CSV file does not exist or could not be opened.
+    FileNotFound(PathBuf),
+    /// The CSV reader failed while opening the file.
+    CsvError(csv::Error),
+}
+
+/// Describes which CSV header maps onto each `Ticker` field.
+///
+/// Headers that do not appear in the schema are passed through unchanged.
+///
+/// # Example
+///
+/// ```no_run
+/// use backtester::prelude::*;
+///
+/// let schema = SeriesSchema {
+///     open: "Open".to_string(),
+///     close: "Close".to_string(),
+///     datetime: "timestamp".to_string(),
+///     ..Default::default()
+/// };
+/// let timeseries = TimeSeries::from_csv_with_schema("data/SPY.csv", schema);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SeriesSchema {
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub volume: String,
+    pub datetime: String,
+}
+
+impl Default for SeriesSchema {
+    fn default() -> Self {
+        Self {
+            open: "open".to_string(),
+            high: "high".to_string(),
+            low: "low".to_string(),
+            close: "close".to_string(),
+            volume: "volume".to_string(),
+            datetime: "datetime".to_string(),
+        }
+    }
+}
+
+impl SeriesSchema {
+    /// Returns the name of the field that the CSV `header` represents.
+    fn field_for<'a>(&self, header: &'a str) -> &'a str {
+        if header == self.open {
+            "open"
+        } else if header == self.high {
+            "high"
+        } else if header == self.low {
+            "low"
+        } else if header == self.close {
+            "close"
+        } else if header == self.volume {
+            "volume"
+        } else if header == self.datetime {
+            "datetime"
+        } else {
+            header
+        }
+    }
+}
+
+impl From<csv::Error> for SeriesError {
+    fn from(err: csv::Error) -> Self {
+        SeriesError::CsvError(err)
+    }
+}
+
+/// Provides a stream of 'Tickers' from a CSV file or from memory.
 /// ## Notice:
-/// The timeseries is lazily evaluated. Rather than loading the whole
+/// A CSV-backed timeseries is lazily evaluated. Rather than loading the whole
 /// file into memory upon initialization, it creates a deserialized
 /// reader that can be turned into an iterator to load the data.
 ///
@@ -34,8 +114,23 @@ use crate::util::serde_ext::*;
 /// ```
 #[derive(Clone)]
 pub struct Series<T: serde::de::DeserializeOwned> {
-    path: PathBuf,
-    _phantom: std::marker::PhantomData<T>,
+    source: Source<T>,
+}
+
+/// Where the records of a `Series` come from.
+#[derive(Clone)]
+enum Source<T> {
+    Csv {
+        path: PathBuf,
+        schema: Option<SeriesSchema>,
+        buffer_capacity: usize,
+    },
+    Memory(Vec<T>),
+    /// Records shared with a `LoadedSeries`, so cloning the series does not copy them.
+    Loaded {
+        path: Option<PathBuf>,
+        records: Arc<[T]>,
+    },
 }
 
 impl<T> Series<T>
@@ -46,45 +141,430 @@ where T: serde::de::DeserializeOwned {
     /// Otherwise, deserialization will fail.
     pub fn from_csv<P: AsRef<Path>>(path: P) -> Self {
         Self {
-            path: path.as_ref().to_path_buf(),
-            _phantom: std::marker::PhantomData,
+            source: Source::Csv {
+                path: path.as_ref().to_path_buf(),
+                schema: None,
+                buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+            },
+        }
+    }
+
+    /// Initializes a new TimeSeries from a CSV file, returning an error
+    /// if the file is missing or cannot be read rather than panicking
+    /// once the series is iterated.
+    pub fn try_from_csv<P: AsRef<Path>>(path: P) -> Result<Self, SeriesError> {
+        let path = path.as_ref().to_path_buf();
+        if File::open(&path).is_err() {
+            return Err(SeriesError::FileNotFound(path));
+        }
+        Ok(Self::from_csv(path))
+    }
+
+    /// Initializes a new TimeSeries from records that are already in memory.
+    /// Useful for tests and programmatically generated data.
+    pub fn from_vec(records: Vec<T>) -> Self {
+        Self {
+            source: Source::Memory(records),
+        }
+    }
+
+    /// Renames the CSV headers according to `schema` before deserializing.
+    /// Has no effect on in-memory series.
+    pub fn with_schema(mut self, schema: SeriesSchema) -> Self {
+        if let Source::Csv { schema: current, .. } = &mut self.source {
+            *current = Some(schema);
         }
+        self
     }
 
-    pub fn get_path(&self) -> &PathBuf {
-        &self.path
+    /// Reads the CSV file in chunks of `capacity` bytes, `DEFAULT_BUFFER_CAPACITY` by default.
+    /// A larger buffer means fewer reads from disk. Only a buffer of the order of 1 MiB
+    /// measurably speeds up iterating very large files, see the `series` benchmark.
+    /// Has no effect on in-memory series.
+    ///
+    /// # Panics
+    /// If `capacity` is zero.
+    pub fn with_buffer_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "Buffer capacity should be positive.");
+        if let Source::Csv { buffer_capacity, .. } = &mut self.source {
+            *buffer_capacity = capacity;
+        }
+        self
+    }
+
+    /// Returns the path of the CSV file, or `None` for in-memory series.
+    pub fn get_path(&self) -> Option<&PathBuf> {
+        match &self.source {
+            Source::Csv { path, .. } => Some(path),
+            Source::Memory(_) => None,
+            Source::Loaded { path, .. } => path.as_ref(),
+        }
+    }
+
+    /// Returns the number of records without deserializing them, or `None` if the CSV file
+    /// cannot be read. Rows are only split into fields, so rows that would fail to
+    /// deserialize are still counted. Useful e.g. to size a progress bar.
+    pub fn len_hint(&self) -> Option<usize> {
+        match &self.source {
+            Source::Csv { path, buffer_capacity, .. } => {
+                let mut reader = csv::ReaderBuilder::new().buffer_capacity(*buffer_capacity).from_path(path).ok()?;
+                Some(reader.byte_records().count())
+            }
+            Source::Memory(records) => Some(records.len()),
+            Source::Loaded { records, .. } => Some(records.len()),
+        }
+    }
+
+    /// Returns `true` if the series has no records, e.g. a CSV file with only a header.
+    /// Only the first row of a CSV file is read. A file that cannot be read has no records either.
+    pub fn is_empty(&self) -> bool {
+        match &self.source {
+            Source::Csv { path, .. } => match csv::Reader::from_path(path) {
+                Ok(reader) => reader.into_byte_records().next().is_none(),
+                Err(_) => true,
+            },
+            Source::Memory(records) => records.is_empty(),
+            Source::Loaded { records, .. } => records.is_empty(),
+        }
+    }
+
+    /// Parses every record up front, so that the series can be iterated any number of
+    /// times without re-reading the file, e.g. across the runs of an `Optimizer`.
+    /// The first row that cannot be parsed is reported as an error.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use backtester::prelude::*;
+    ///
+    /// let loaded = TimeSeries::from_csv("data/SPY.csv").load().unwrap();
+    /// for ticker in loaded.iter() {
+    ///    println!("{:?}", ticker);
+    /// }
+    /// let timeseries = loaded.to_series();
+    /// ```
+    pub fn load(self) -> Result<LoadedSeries<T>, SeriesError>
+    where T: Clone {
+        let path = self.get_path().cloned();
+        let records = match self.source {
+            Source::Loaded { records, .. } => records,
+            source => Self { source }.try_into_iter()?.collect::<Result<Vec<T>, _>>()?.into(),
+        };
+        Ok(LoadedSeries { path, records })
+    }
+
+    /// Opens the underlying CSV file and returns an iterator over its records.
+    /// Unlike `into_iter`, a missing or unreadable file is reported as an error.
+    pub fn try_into_iter(self) -> Result<SeriesIntoIterator<T>, SeriesError> {
+        let (path, schema, buffer_capacity) = match self.source {
+            Source::Csv { path, schema, buffer_capacity } => (path, schema, buffer_capacity),
+            Source::Memory(records) => {
+                return Ok(SeriesIntoIterator {
+                    records: Records::Memory(records.into_iter()),
+                })
+            }
+            Source::Loaded { records, .. } => {
+                return Ok(SeriesIntoIterator {
+                    records: Records::Loaded(LoadedIter { records, index: 0 }),
+                })
+            }
+        };
+        // The reader buffers the file itself, so it must not be wrapped in a `BufReader`.
+        let reader = csv::ReaderBuilder::new().buffer_capacity(buffer_capacity).from_path(&path);
+        let mut reader = match reader {
+            Ok(reader) => reader,
+            Err(err) => match err.kind() {
+                csv::ErrorKind::Io(_) => return Err(SeriesError::FileNotFound(path)),
+                _ => return Err(SeriesError::CsvError(err)),
+            },
+        };
+        if let Some(schema) = &schema {
+            let headers = reader
+                .headers()?
+                .iter()
+                .map(|header| schema.field_for(header))
+                .collect::<csv::StringRecord>();
+            reader.set_headers(headers);
+        }
+        Ok(SeriesIntoIterator {
+            records: Records::Csv {
+                reader: Box::new(reader.into_deserialize::<T>()),
+                path,
+                schema,
+                buffer_capacity,
+                consumed: 0,
+            },
+        })
     }
 }
 
 impl<T> IntoIterator for Series<T>
-where T: serde::de::DeserializeOwned {
+where T: serde::de::DeserializeOwned + Clone {
     type Item = Result<T, csv::Error>;
     type IntoIter = SeriesIntoIterator<T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        let reader: csv::DeserializeRecordsIntoIter<File, T> =
-            csv::Reader::from_path(self.path.clone())
-                .expect(&format!("Cannot not find file"))
-                .into_deserialize::<T>();
-        SeriesIntoIterator {
-            deserialized_reader: reader,
-        }
+        self.try_into_iter().expect("Cannot not find file")
     }
 }
 
+/// Iterates the records of a `Series`.
+///
+/// Cloning the iterator of a CSV-backed series re-opens the file and skips the records
+/// that were already read, so the clone resumes at the same position.
+///
+/// # Panics
+/// Cloning panics if the CSV file can no longer be opened.
 pub struct SeriesIntoIterator<T> {
-    deserialized_reader: csv::DeserializeRecordsIntoIter<File, T>,
+    records: Records<T>,
+}
+
+enum Records<T> {
+    Csv {
+        reader: Box<csv::DeserializeRecordsIntoIter<File, T>>,
+        path: PathBuf,
+        schema: Option<SeriesSchema>,
+        buffer_capacity: usize,
+        /// The number of records read so far, which a clone skips.
+        consumed: usize,
+    },
+    Memory(std::vec::IntoIter<T>),
+    Loaded(LoadedIter<T>),
+}
+
+impl<T> Clone for SeriesIntoIterator<T>
+where T: serde::de::DeserializeOwned + Clone {
+    fn clone(&self) -> Self {
+        let records = match &self.records {
+            Records::Csv { path, schema, buffer_capacity, consumed, .. } => {
+                let series = Series::<T> {
+                    source: Source::Csv {
+                        path: path.clone(),
+                        schema: schema.clone(),
+                        buffer_capacity: *buffer_capacity,
+                    },
+                };
+                let mut iter = series.try_into_iter().expect("Cannot not find file");
+                if *consumed > 0 {
+                    iter.nth(consumed - 1);
+                }
+                return iter;
+            }
+            Records::Memory(records) => Records::Memory(records.clone()),
+            Records::Loaded(records) => Records::Loaded(records.clone()),
+        };
+        Self { records }
+    }
 }
 
 impl<T> Iterator for SeriesIntoIterator<T> 
-where T: serde::de::DeserializeOwned {
+where T: serde::de::DeserializeOwned + Clone {
     type Item = Result<T, csv::Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(ticker) = self.deserialized_reader.next() {
-            Some(ticker)
-        } else {
-            None
+        match &mut self.records {
+            Records::Csv { reader, consumed, .. } => {
+                let record = reader.next()?;
+                *consumed += 1;
+                Some(record)
+            }
+            Records::Memory(records) => records.next().map(Ok),
+            Records::Loaded(records) => records.next().map(Ok),
+        }
+    }
+}
+
+/// The fully parsed records of a `Series`. See `Series::load`.
+///
+/// The records are shared rather than copied, so cloning a `LoadedSeries`, iterating it,
+/// or turning it back into a `Series` with `to_series` is cheap.
+#[derive(Debug)]
+pub struct LoadedSeries<T> {
+    path: Option<PathBuf>,
+    records: Arc<[T]>,
+}
+
+impl<T> Clone for LoadedSeries<T> {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            records: self.records.clone(),
+        }
+    }
+}
+
+impl<T> LoadedSeries<T>
+where T: serde::de::DeserializeOwned + Clone {
+    /// Returns an iterator over clones of the records.
+    pub fn iter(&self) -> LoadedIter<T> {
+        LoadedIter {
+            records: self.records.clone(),
+            index: 0,
         }
     }
-}
\ No newline at end of file
+
+    /// Returns a `Series` over the same records, e.g. to hand to a `Backtest` or an `Optimizer`.
+    /// The series keeps the path of the file the records were loaded from.
+    pub fn to_series(&self) -> Series<T> {
+        Series {
+            source: Source::Loaded {
+                path: self.path.clone(),
+                records: self.records.clone(),
+            },
+        }
+    }
+
+    /// Returns the path of the CSV file the records were loaded from, or `None` for in-memory series.
+    pub fn get_path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    pub fn get_records(&self) -> &[T] {
+        &self.records
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+/// Yields clones of the records of a `LoadedSeries`. See `LoadedSeries::iter`.
+#[derive(Debug)]
+pub struct LoadedIter<T> {
+    records: Arc<[T]>,
+    index: usize,
+}
+
+impl<T> Clone for LoadedIter<T> {
+    fn clone(&self) -> Self {
+        Self {
+            records: self.records.clone(),
+            index: self.index,
+        }
+    }
+}
+
+impl<T: Clone> Iterator for LoadedIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.records.get(self.index)?.clone();
+        self.index += 1;
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Ticker;
+
+    #[test]
+    fn try_from_csv_missing_file() {
+        let series = Series::<Ticker>::try_from_csv("./does/not/exist.csv");
+        assert!(matches!(series, Err(SeriesError::FileNotFound(_))));
+    }
+
+    #[test]
+    fn loaded_series_outlives_file() {
+        let path = std::env::temp_dir().join(format!("backtester_loaded_series_{}.csv", std::process::id()));
+        std::fs::copy("./benches/datasets/timeseries/AAC.csv", &path).unwrap();
+        let loaded = Series::<Ticker>::from_csv(&path).load().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let expected = Series::<Ticker>::from_csv("./benches/datasets/timeseries/AAC.csv")
+            .into_iter()
+            .map(|ticker| ticker.unwrap().datetime)
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+        for _ in 0..2 {
+            let datetimes = loaded.iter().map(|ticker| ticker.datetime).collect::<Vec<_>>();
+            assert_eq!(datetimes, expected);
+        }
+        let series = loaded.to_series();
+        assert_eq!(series.get_path(), Some(&path));
+        assert_eq!(series.clone().into_iter().count(), expected.len());
+        assert_eq!(series.load().unwrap().len(), expected.len());
+    }
+
+    #[test]
+    fn load_reports_malformed_row() {
+        let path = std::env::temp_dir().join(format!("backtester_load_malformed_{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "open,close,high,low,volume,datetime\n\
+             9.83,not_a_price,9.84,9.82,1997,1654867800\n",
+        )
+        .unwrap();
+        let loaded = Series::<Ticker>::from_csv(&path).load();
+        assert!(matches!(loaded, Err(SeriesError::CsvError(_))));
+    }
+
+    #[test]
+    fn clone_partially_consumed_iterator() {
+        let path = "./benches/datasets/timeseries/AAC.csv";
+        let loaded = Series::<Ticker>::from_csv(path).load().unwrap();
+        let memory = Series::<Ticker>::from_vec(loaded.get_records().to_vec());
+        for series in [Series::<Ticker>::from_csv(path), memory, loaded.to_series()] {
+            let mut first = series.into_iter();
+            first.by_ref().take(5).for_each(drop);
+            let second = first.clone();
+            let first = first.map(|ticker| ticker.unwrap().datetime).collect::<Vec<_>>();
+            let second = second.map(|ticker| ticker.unwrap().datetime).collect::<Vec<_>>();
+            assert!(!first.is_empty());
+            assert_eq!(first, second);
+        }
+    }
+
+    #[test]
+    fn buffer_capacity_does_not_change_records() {
+        let path = "./benches/datasets/timeseries/AAC.csv";
+        let expected = Series::<Ticker>::from_csv(path).load().unwrap();
+        // Buffers smaller than a row force the reader to refill mid-record.
+        for capacity in [1, 7, 1 << 20] {
+            let tickers = Series::<Ticker>::from_csv(path)
+                .with_buffer_capacity(capacity)
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(tickers.len(), expected.len());
+            for (ticker, expected) in tickers.iter().zip(expected.iter()) {
+                assert_eq!(
+                    (ticker.open, ticker.high, ticker.low, ticker.close, ticker.volume, ticker.datetime),
+                    (expected.open, expected.high, expected.low, expected.close, expected.volume, expected.datetime)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn empty_and_len_hint() {
+        let path = std::env::temp_dir().join(format!("backtester_empty_feed_{}.csv", std::process::id()));
+        std::fs::write(&path, "open,close,high,low,volume,datetime\n").unwrap();
+        let empty = Series::<Ticker>::from_csv(&path);
+        assert!(empty.is_empty());
+        assert_eq!(empty.len_hint(), Some(0));
+
+        let aac = Series::<Ticker>::from_csv("./benches/datasets/timeseries/AAC.csv");
+        assert!(!aac.is_empty());
+        let len = aac.clone().into_iter().count();
+        assert!(len > 0);
+        assert_eq!(aac.len_hint(), Some(len));
+        assert_eq!(aac.load().unwrap().to_series().len_hint(), Some(len));
+
+        let missing = Series::<Ticker>::from_csv("./does/not/exist.csv");
+        assert!(missing.is_empty());
+        assert_eq!(missing.len_hint(), None);
+        assert!(Series::<Ticker>::from_vec(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn try_from_csv_existing_file() {
+        let series = Series::<Ticker>::try_from_csv("./benches/datasets/timeseries/AAC.csv");
+        assert!(series.is_ok());
+    }
+}
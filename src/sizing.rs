@@ -0,0 +1,55 @@
+//! Position sizing: how many shares to trade, or how much of the equity to put at risk.
+//!
+//! These are plain functions, so strategies can feed their results straight into
+//! `Broker::submit_order`. Share counts are fractional; floor them for brokers that
+//! do not allow fractional shares.
+
+/// The number of shares to trade so that being stopped out at `stop` after entering at `entry`
+/// loses `risk_per_trade` (e.g. `0.01` for 1%) of `equity`, i.e. the fixed fractional
+/// position size. Works for both long (stop below entry) and short (stop above) positions.
+///
+/// Returns `0.0` if `entry` and `stop` are equal, since the risk per share is then unknown.
+pub fn fixed_fractional(equity: f32, risk_per_trade: f32, entry: f32, stop: f32) -> f32 {
+    let risk_per_share = (entry - stop).abs();
+    if risk_per_share == 0.0 {
+        return 0.0;
+    }
+    equity * risk_per_trade / risk_per_share
+}
+
+/// The fraction of the equity to bet according to the
+/// [Kelly criterion](https://www.investopedia.com/articles/trading/04/091504.asp), given the
+/// fraction of trades that win and the ratio of the average win to the average loss:
+/// `win_rate - (1 - win_rate) / win_loss_ratio`.
+///
+/// A negative fraction means that the strategy has no edge and should not be traded.
+/// Many traders bet a fraction of it (e.g. "half Kelly") to lower the variance.
+/// Returns `0.0` unless `win_loss_ratio` is positive.
+pub fn kelly_fraction(win_rate: f32, win_loss_ratio: f32) -> f32 {
+    if win_loss_ratio <= 0.0 {
+        return 0.0;
+    }
+    win_rate - (1.0 - win_rate) / win_loss_ratio
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_fractional_of_known_stop() {
+        // Risking 1% of 100,000 with $2 of risk per share.
+        assert_eq!(fixed_fractional(100_000.0, 0.01, 50.0, 48.0), 500.0);
+        // A short stopped out above the entry.
+        assert_eq!(fixed_fractional(100_000.0, 0.01, 50.0, 52.0), 500.0);
+        assert_eq!(fixed_fractional(100_000.0, 0.01, 50.0, 50.0), 0.0);
+    }
+
+    #[test]
+    fn kelly_of_known_edge() {
+        assert!((kelly_fraction(0.6, 1.0) - 0.2).abs() < 1e-6);
+        assert!((kelly_fraction(0.5, 2.0) - 0.25).abs() < 1e-6);
+        assert!(kelly_fraction(0.4, 1.0) < 0.0);
+        assert_eq!(kelly_fraction(0.5, 0.0), 0.0);
+    }
+}
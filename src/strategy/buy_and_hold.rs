@@ -35,7 +35,6 @@ impl Strategy for BuyAndHold {
             false => {
                 self.bought = true;
                 broker.submit_order(
-                    0,
                     Order {
                         symbol: "AAPL".to_string(),
                         quantity: 100.0,
@@ -43,6 +42,7 @@ impl Strategy for BuyAndHold {
                         order_type: OrderType::Market,
                         datetime: ticker.datetime.clone(),
                         execution: OrderExecutionStrategy::GTC,
+                        reduce_only: false,
                         on_execute: None,
                         on_cancel: None,
                     },
@@ -5,17 +5,6 @@ use crate::{
 };
 use std::cmp::{max, min};
 
-// The root cause of many Clone implementations is the dependency on the Series type, whose iterator is not clonable
-// Either 
-// 1) Make the seriese iterator cloneable
-// 2) Builder Route
-// - StrategyBuilder
-// - IndicatorBuilder
-// These will be clonable and allow a cartesian product to be performed in the intialization of the backtester.
-// Then, we can move to non-clonable versions of the strategies and indicators.
-// This might have performance gains since clone implementations no longer clone internal types which might be expensive
-// 3) ...
-
 /// # EFFR Trading
 /// 
 /// This is a simple strategy that trades based on the Effective Federal Funds Rate.
@@ -34,7 +23,6 @@ pub struct EFFRTrading {
 	long_threshold: f32, // The threshold to go 100% long all capital
 	short_threshold: f32, // The threshold to go 100% short all capital
 	starting_capital: f32, // Used to calculate the percent allocated
-	order_id: usize
 }
 
 impl Default for EFFRTrading {
@@ -44,7 +32,6 @@ impl Default for EFFRTrading {
 			long_threshold: 0.0,
 			short_threshold: 2.0,
 			starting_capital: 0.0,
-			order_id: 0
 		}
 	}
 }
@@ -56,7 +43,6 @@ impl EFFRTrading {
 			long_threshold,
 			short_threshold,
 			starting_capital: 0.0,
-			order_id: 0
 		}
 	}
 }
@@ -88,18 +74,18 @@ impl Strategy for EFFRTrading {
 				quantity = -quantity;
 				OrderSide::Sell 
 			};
-			broker.submit_order(self.order_id, Order { 
+			broker.submit_order(Order { 
 					symbol: "AAPL".to_string(),
 					quantity, 
 					side,
 					order_type: OrderType::Market, 
 					datetime: ticker.datetime.clone(), 
 					execution: OrderExecutionStrategy::GTC,
+					reduce_only: false,
 					on_execute: None, 
 					on_cancel: None 
 				}
-			).err();
-			self.order_id += 1;
+			)?;
 		}
 		Ok(())
 	}
@@ -0,0 +1,173 @@
+use super::*;
+use crate::indicators::EMA;
+
+/// # EMA Crossover Strategy
+///
+/// Follows trends with a fast and a slow `EMA`, which react to trend changes sooner than the
+/// `SMACrossover`.
+///
+/// ## States
+///
+/// - `Waiting` - Waiting for both EMAs to be calculated.
+/// - `No Position` - Once the fast EMA crosses above the slow EMA, an uptrend has started,
+///   so the strategy buys `quantity` shares.
+/// - `Long` - Once the fast EMA crosses back below the slow EMA, the strategy flattens its position.
+#[derive(Clone)]
+pub struct EMACrossover {
+    symbol: String,
+    quantity: f32,
+    fast: EMA,
+    slow: EMA,
+    fast_period: u32,
+    slow_period: u32,
+    /// The fast EMA less the slow EMA at the previous ticker.
+    previous_gap: Option<f32>,
+    long: bool,
+}
+
+impl Default for EMACrossover {
+    fn default() -> Self {
+        Self::new(12, 26)
+    }
+}
+
+impl EMACrossover {
+    /// Trades `100` shares of `"AAPL"` using EMAs of `fast_period` and `slow_period` tickers.
+    pub fn new(fast_period: u32, slow_period: u32) -> Self {
+        Self::with_params(fast_period, slow_period, "AAPL", 100.0)
+    }
+
+    /// Trades `quantity` shares of `symbol` using EMAs of `fast_period` and `slow_period` tickers.
+    pub fn with_params(fast_period: u32, slow_period: u32, symbol: &str, quantity: f32) -> Self {
+        if fast_period >= slow_period {
+            panic!("EMACrossover: fast_period should be shorter than slow_period.");
+        }
+        Self {
+            symbol: symbol.to_string(),
+            quantity,
+            fast: EMA::new(fast_period),
+            slow: EMA::new(slow_period),
+            fast_period,
+            slow_period,
+            previous_gap: None,
+            long: false,
+        }
+    }
+
+    pub fn get_fast_period(&self) -> u32 {
+        self.fast_period
+    }
+
+    pub fn get_slow_period(&self) -> u32 {
+        self.slow_period
+    }
+
+    pub fn get_symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn get_quantity(&self) -> f32 {
+        self.quantity
+    }
+
+    fn order(&self, side: OrderSide, ticker: &Ticker) -> Order {
+        Order {
+            symbol: self.symbol.clone(),
+            quantity: self.quantity,
+            reduce_only: side == OrderSide::Sell,
+            side,
+            order_type: OrderType::Market,
+            datetime: ticker.datetime,
+            execution: OrderExecutionStrategy::GTC,
+            on_execute: None,
+            on_cancel: None,
+        }
+    }
+}
+
+impl fmt::Display for EMACrossover {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "EMA Crossover(Fast: {}, Slow: {}, Symbol: {}, Quantity: {})",
+            self.fast_period, self.slow_period, self.symbol, self.quantity
+        )
+    }
+}
+
+impl Strategy for EMACrossover {
+    fn prepare(&mut self, _broker: &mut Broker) -> Result<(), StrategyError> {
+        Ok(())
+    }
+
+    fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+        self.fast.update(ticker).ok();
+        self.slow.update(ticker).ok();
+        let gap = match (self.fast.get_value(), self.slow.get_value()) {
+            (Ok(fast), Ok(slow)) => fast - slow,
+            _ => return Ok(()),
+        };
+
+        if let Some(previous_gap) = self.previous_gap {
+            if !self.long && previous_gap <= 0.0 && gap > 0.0 {
+                broker.submit_order(self.order(OrderSide::Buy, ticker))?;
+                self.long = true;
+            } else if self.long && previous_gap >= 0.0 && gap < 0.0 {
+                broker.submit_order(self.order(OrderSide::Sell, ticker))?;
+                self.long = false;
+            }
+        }
+        self.previous_gap = Some(gap);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backtest::Backtest, timeseries::TimeSeries};
+    use chrono::{TimeZone, Utc};
+
+    fn feed(closes: &[f32]) -> TimeSeries {
+        TimeSeries::from_vec(
+            closes
+                .iter()
+                .enumerate()
+                .map(|(day, &close)| Ticker {
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: 1000,
+                    datetime: Utc.timestamp_opt(day as i64 * 86_400, 0).unwrap(),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn trades_on_trend_changes() {
+        // Falls until day 9, rallies until day 16, then falls again.
+        let closes = [
+            20.0, 19.0, 18.0, 17.0, 16.0, 15.0, 14.0, 13.0, 12.0, 11.0,
+            13.0, 15.0, 17.0, 19.0, 21.0, 23.0, 25.0, 22.0, 19.0, 16.0, 13.0, 10.0,
+        ];
+        let backtest = Backtest::new(
+            feed(&closes),
+            Broker::new("EMA Crossover", 100_000.0, 0.0, 1.0, false, false),
+            Box::new(EMACrossover::with_params(3, 6, "MSFT", 10.0)),
+        );
+        let result = backtest.run().unwrap();
+        let trades = result
+            .get_broker()
+            .get_trades()
+            .iter()
+            .map(|trade| (trade.symbol.as_str(), trade.side.clone(), trade.datetime.timestamp() / 86_400))
+            .collect::<Vec<_>>();
+        // The fast EMA crosses above the slow one on day 12 and below it on day 19,
+        // and each order fills the day after.
+        assert_eq!(trades, vec![("MSFT", OrderSide::Buy, 13), ("MSFT", OrderSide::Sell, 20)]);
+        assert!(result.get_broker().get_position("MSFT").is_none());
+    }
+}
@@ -12,6 +12,7 @@ use crate::{
     types::{Order, OrderExecutionStrategy, OrderSide, OrderType, Ticker},
 };
 use dyn_clone::DynClone;
+use std::any::Any;
 use std::fmt;
 
 #[derive(Debug)]
@@ -29,18 +30,46 @@ impl From<BrokerError> for StrategyError {
 /// Sends orders to a broker based on decisions made from the ticker data.
 /// Contains indicators that are updated with the ticker data and used to make
 /// trading decisions.
-pub trait Strategy: fmt::Display + DynClone {
-    /// Called by the broker before the start of the backtest. The strategy should
-    /// initialize any indicators that it needs to make trading decisions.
+///
+/// A boxed strategy can be downcast back to its concrete type through `Any`,
+/// e.g. to inspect its state after a run (see `BacktestResult::get_strategy_as`).
+pub trait Strategy: fmt::Display + DynClone + Any {
+    /// Called by the broker before the start of the backtest, with the broker's clock at the
+    /// datetime of the first ticker. The strategy should initialize any indicators that it
+    /// needs to make trading decisions.
     fn prepare(&mut self, broker: &mut Broker) -> Result<(), StrategyError>;
     /// Called by the broker for each step in the backtest. The strategy should
     /// use the ticker data to make trading decisions and send orders to the broker.
     fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError>;
+    /// Called instead of `on_ticker` when the backtest is fed several symbols at once
+    /// (see `MultiFeed`), with the symbol that `ticker` belongs to.
+    /// Strategies that trade more than one symbol should override it.
+    fn on_symbol_ticker(&mut self, _symbol: &str, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+        self.on_ticker(ticker, broker)
+    }
+    /// Called before the start of the backtest if the backtest was given a seed.
+    /// Stochastic strategies should seed their random number generator with it
+    /// (e.g. `rand_chacha::ChaCha8Rng::seed_from_u64(seed)`) so that runs are reproducible.
+    fn seed(&mut self, _seed: u64) {}
 }
 
+dyn_clone::clone_trait_object!(Strategy);
+
 mod buy_and_hold;
+mod ema_crossover;
 mod sma_crossover;
 mod effr_trading;
+mod pairs_trading;
+mod portfolio;
+mod rsi_reversion;
+mod scheduler;
+mod vol_target;
 pub use buy_and_hold::BuyAndHold;
+pub use ema_crossover::EMACrossover;
 pub use sma_crossover::SMACrossover;
+pub use pairs_trading::PairsTrading;
+pub use portfolio::PortfolioStrategy;
+pub use rsi_reversion::RSIReversion;
+pub use scheduler::{Schedule, Scheduler};
+pub use vol_target::VolTargetStrategy;
 // pub use effr_trading::EFFRTrading;
\ No newline at end of file
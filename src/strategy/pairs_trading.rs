@@ -0,0 +1,230 @@
+use super::*;
+use crate::indicators::ZScore;
+use chrono::{DateTime, Utc};
+
+/// # [Pairs Trading](https://www.investopedia.com/terms/p/pairstrade.asp)
+///
+/// Trades the spread `first - hedge_ratio * second` between two related symbols, and so must
+/// be run on a `MultiFeed` of both (see `Backtest::run_multi_feed`). The broker must allow shorting.
+///
+/// ## States
+///
+/// - `Flat` - No position. Once both symbols have a price for the same timestamp, the z-score
+///   of the spread over the last `period` timestamps is updated. If it rises above `entry_z`,
+///   `first` is rich relative to `second`, so the strategy sells `first` and buys `second`.
+///   If it falls below `-entry_z`, it does the opposite.
+/// - `Long` / `Short` - Long or short the spread. Once the z-score reverts back across
+///   `exit_z` towards the mean, both legs are closed with reduce-only orders.
+#[derive(Clone)]
+pub struct PairsTrading {
+    first: String,
+    second: String,
+    hedge_ratio: f32,
+    quantity: f32,
+    entry_z: f32,
+    exit_z: f32,
+    zscore: ZScore,
+    prices: [Option<(DateTime<Utc>, f32)>; 2], // The latest price of `first` and `second`
+    position: SpreadPosition,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SpreadPosition {
+    Flat,
+    Long,
+    Short,
+}
+
+impl PairsTrading {
+    /// Trades `quantity` shares of each leg, entering at a z-score of `2` and exiting
+    /// at `0` over a `20` timestamp window, with a hedge ratio of `1`.
+    pub fn new(first: &str, second: &str, quantity: f32) -> Self {
+        Self::with_params(first, second, 1.0, quantity, 20, 2.0, 0.0)
+    }
+
+    pub fn with_params(
+        first: &str,
+        second: &str,
+        hedge_ratio: f32,
+        quantity: f32,
+        period: u32,
+        entry_z: f32,
+        exit_z: f32,
+    ) -> Self {
+        Self {
+            first: first.to_string(),
+            second: second.to_string(),
+            hedge_ratio,
+            quantity,
+            entry_z,
+            exit_z,
+            zscore: ZScore::new(period),
+            prices: [None, None],
+            position: SpreadPosition::Flat,
+        }
+    }
+
+    /// Submits market orders that buy `first` and sell `second` if `side` is `Buy`,
+    /// or the other way around if it is `Sell`.
+    fn trade_spread(&self, side: OrderSide, reduce_only: bool, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+        let opposite = match side {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        };
+        for (symbol, side, quantity) in [
+            (&self.first, side, self.quantity),
+            (&self.second, opposite, self.quantity * self.hedge_ratio),
+        ] {
+            broker.submit_order(Order {
+                symbol: symbol.clone(),
+                quantity,
+                side,
+                order_type: OrderType::Market,
+                datetime: ticker.datetime,
+                execution: OrderExecutionStrategy::GTC,
+                reduce_only,
+                on_execute: None,
+                on_cancel: None,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PairsTrading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Pairs Trading({} / {}, Hedge Ratio: {}, Quantity: {}, {}, Entry: {}, Exit: {})",
+            self.first, self.second, self.hedge_ratio, self.quantity, self.zscore, self.entry_z, self.exit_z
+        )
+    }
+}
+
+impl Strategy for PairsTrading {
+    fn prepare(&mut self, _broker: &mut Broker) -> Result<(), StrategyError> {
+        Ok(())
+    }
+
+    /// Without symbols, the legs cannot be told apart, so nothing is traded.
+    fn on_ticker(&mut self, _ticker: &Ticker, _broker: &mut Broker) -> Result<(), StrategyError> {
+        Ok(())
+    }
+
+    fn on_symbol_ticker(&mut self, symbol: &str, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+        let leg = if symbol == self.first {
+            0
+        } else if symbol == self.second {
+            1
+        } else {
+            return Ok(());
+        };
+        self.prices[leg] = Some((ticker.datetime, ticker.close));
+
+        // The spread is only defined once both legs have a price for the same timestamp.
+        let (first, second) = match self.prices {
+            [Some((first_datetime, first)), Some((second_datetime, second))] if first_datetime == second_datetime => {
+                (first, second)
+            }
+            _ => return Ok(()),
+        };
+        self.prices = [None, None];
+        let spread = Ticker {
+            close: first - self.hedge_ratio * second,
+            ..ticker.clone()
+        };
+        self.zscore.update(&spread).ok();
+        let z = match self.zscore.get_value() {
+            Ok(z) => z,
+            Err(_) => return Ok(()),
+        };
+
+        match self.position {
+            SpreadPosition::Flat if z > self.entry_z => {
+                self.trade_spread(OrderSide::Sell, false, ticker, broker)?;
+                self.position = SpreadPosition::Short;
+            }
+            SpreadPosition::Flat if z < -self.entry_z => {
+                self.trade_spread(OrderSide::Buy, false, ticker, broker)?;
+                self.position = SpreadPosition::Long;
+            }
+            SpreadPosition::Short if z <= self.exit_z => {
+                self.trade_spread(OrderSide::Buy, true, ticker, broker)?;
+                self.position = SpreadPosition::Flat;
+            }
+            SpreadPosition::Long if z >= -self.exit_z => {
+                self.trade_spread(OrderSide::Sell, true, ticker, broker)?;
+                self.position = SpreadPosition::Flat;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backtest::Backtest, broker::BrokerBuilder, feed::MultiFeed, timeseries::TimeSeries};
+    use chrono::TimeZone;
+
+    fn feed(closes: impl Iterator<Item = f32>) -> TimeSeries {
+        TimeSeries::from_vec(
+            closes
+                .enumerate()
+                .map(|(day, close)| Ticker {
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: 1000,
+                    datetime: Utc.timestamp_opt(day as i64 * 86_400, 0).unwrap(),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn opens_and_closes_spread_trade() {
+        // The pair moves together, except for a brief dislocation of the first leg on days 20 to 22.
+        let second = (0..40).map(|day| 100.0 + 0.5 * day as f32).collect::<Vec<_>>();
+        let first = second
+            .iter()
+            .enumerate()
+            .map(|(day, close)| match day {
+                20..=22 => close + 3.0,
+                _ if day % 2 == 0 => close + 0.1,
+                _ => close - 0.1,
+            })
+            .collect::<Vec<_>>();
+        let multi_feed = MultiFeed::new()
+            .add("AAA", feed(first.clone().into_iter()).into_iter())
+            .add("BBB", feed(second.clone().into_iter()).into_iter());
+        let strategy = PairsTrading::with_params("AAA", "BBB", 1.0, 10.0, 10, 2.0, 0.0);
+        let broker = BrokerBuilder::new("Pairs").allow_short(true).build();
+        let result = Backtest::new(TimeSeries::from_vec(Vec::new()), broker, Box::new(strategy))
+            .run_multi_feed(multi_feed)
+            .unwrap();
+
+        let broker = result.get_broker();
+        let trades = broker
+            .get_trades()
+            .iter()
+            .map(|trade| (trade.symbol.as_str(), trade.side.clone(), trade.price))
+            .collect::<Vec<_>>();
+        // The spread is sold on day 20 and bought back once it reverts on day 23,
+        // each filling on the following day.
+        assert_eq!(
+            trades,
+            vec![
+                ("AAA", OrderSide::Sell, first[21]),
+                ("BBB", OrderSide::Buy, second[21]),
+                ("AAA", OrderSide::Buy, first[24]),
+                ("BBB", OrderSide::Sell, second[24]),
+            ]
+        );
+        assert!(broker.get_position("AAA").is_none());
+        assert!(broker.get_position("BBB").is_none());
+        assert!(broker.snapshot().realized_pnl > 0.0);
+    }
+}
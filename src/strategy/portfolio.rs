@@ -0,0 +1,237 @@
+use super::*;
+
+/// # Portfolio
+///
+/// Runs several strategies against a single shared `Broker`, so that their orders,
+/// positions and P&L combine in one account rather than in one backtest each.
+///
+/// Every ticker is forwarded to the strategies in the order they were added. Order ids
+/// are allocated by the broker on submission, so the ids of different strategies never collide.
+///
+/// Strategies may be given a weight, in which case each one only sees its slice of the equity:
+/// `buying_power`, `max_shares` and `can_execute` account for the positions the strategy already
+/// holds, and its orders are rejected with `RiskLimitExceeded` once its gross exposure, long and
+/// short, would exceed the slice.
+#[derive(Clone)]
+pub struct PortfolioStrategy {
+    strategies: Vec<Box<dyn Strategy>>,
+    weights: Option<Vec<f32>>,
+}
+
+impl PortfolioStrategy {
+    /// Every strategy trades against the whole account.
+    pub fn new(strategies: Vec<Box<dyn Strategy>>) -> Self {
+        Self {
+            strategies,
+            weights: None,
+        }
+    }
+
+    /// Every strategy trades against its weight's share of the equity.
+    /// The weights must be non-negative and sum to at most `1`.
+    pub fn with_weights(strategies: Vec<(Box<dyn Strategy>, f32)>) -> Self {
+        let (strategies, weights): (Vec<_>, Vec<_>) = strategies.into_iter().unzip();
+        if weights.iter().any(|weight| *weight < 0.0) || weights.iter().sum::<f32>() > 1.0 + f32::EPSILON {
+            panic!("Weights must be non-negative and sum to at most 1");
+        }
+        Self {
+            strategies,
+            weights: Some(weights),
+        }
+    }
+
+    pub fn get_strategies(&self) -> &[Box<dyn Strategy>] {
+        &self.strategies
+    }
+
+    pub fn get_weights(&self) -> Option<&[f32]> {
+        self.weights.as_deref()
+    }
+
+    /// Calls `f` with every strategy, within its allocation if it has a weight.
+    fn for_each<F>(&mut self, broker: &mut Broker, mut f: F) -> Result<(), StrategyError>
+    where
+        F: FnMut(&mut Box<dyn Strategy>, &mut Broker) -> Result<(), StrategyError>,
+    {
+        for (slot, strategy) in self.strategies.iter_mut().enumerate() {
+            if let Some(weights) = &self.weights {
+                broker.enter_allocation(slot, weights[slot]);
+            }
+            let result = f(strategy, broker);
+            broker.exit_allocation();
+            result?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PortfolioStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = self
+            .strategies
+            .iter()
+            .map(|strategy| strategy.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "Portfolio({})", names.join(", "))
+    }
+}
+
+impl Strategy for PortfolioStrategy {
+    fn prepare(&mut self, broker: &mut Broker) -> Result<(), StrategyError> {
+        self.for_each(broker, |strategy, broker| strategy.prepare(broker))
+    }
+
+    fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+        self.for_each(broker, |strategy, broker| strategy.on_ticker(ticker, broker))
+    }
+
+    fn on_symbol_ticker(&mut self, symbol: &str, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+        self.for_each(broker, |strategy, broker| strategy.on_symbol_ticker(symbol, ticker, broker))
+    }
+
+    fn seed(&mut self, seed: u64) {
+        for strategy in &mut self.strategies {
+            strategy.seed(seed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backtest::Backtest, broker::BrokerBuilder, timeseries::TimeSeries};
+    use chrono::{TimeZone, Utc};
+
+    fn feed(closes: &[f32]) -> TimeSeries {
+        TimeSeries::from_vec(
+            closes
+                .iter()
+                .enumerate()
+                .map(|(day, &close)| Ticker {
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: 1000,
+                    datetime: Utc.timestamp_opt(day as i64 * 86_400, 0).unwrap(),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn strategies_share_one_account() {
+        let portfolio = PortfolioStrategy::new(vec![
+            Box::new(BuyAndHold::default()),
+            Box::new(SMACrossover::with_params(3, "MSFT", 25.0)),
+        ]);
+        assert_eq!(
+            portfolio.to_string(),
+            "Portfolio(Buy and Hold, SMA Crossover(Period: 3, Symbol: MSFT, Quantity: 25))"
+        );
+        let backtest = Backtest::new(
+            feed(&[10.0, 11.0, 12.0, 13.0, 9.0, 9.0]),
+            Broker::new("Portfolio", 100_000.0, 0.0, 1.0, false, false),
+            Box::new(portfolio),
+        );
+        let result = backtest.run().unwrap();
+        let broker = result.get_broker();
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, 100.0);
+        assert_eq!(broker.get_position("MSFT").unwrap().amount, 25.0);
+        assert_eq!(broker.get_trades().len(), 2);
+        assert_eq!(broker.get_cash(), 100_000.0 - 100.0 * 11.0 - 25.0 * 9.0);
+    }
+
+    /// Buys, or sells short, as many shares of `symbol` as it can afford on the first ticker.
+    #[derive(Clone)]
+    struct AllIn {
+        symbol: String,
+        side: OrderSide,
+        bought: bool,
+    }
+
+    impl fmt::Display for AllIn {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "All In({})", self.symbol)
+        }
+    }
+
+    impl Strategy for AllIn {
+        fn prepare(&mut self, _broker: &mut Broker) -> Result<(), StrategyError> {
+            Ok(())
+        }
+
+        fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+            if self.bought {
+                return Ok(());
+            }
+            self.bought = true;
+            let order = |quantity| Order {
+                symbol: self.symbol.clone(),
+                quantity,
+                side: self.side.clone(),
+                order_type: OrderType::Market,
+                datetime: ticker.datetime,
+                execution: OrderExecutionStrategy::GTC,
+                reduce_only: false,
+                on_execute: None,
+                on_cancel: None,
+            };
+            let quantity = broker.max_shares(ticker.close);
+            assert_eq!(broker.can_execute(&order(quantity + 1.0)), Err(BrokerError::RiskLimitExceeded));
+            broker.submit_order(order(quantity))?;
+            Ok(())
+        }
+    }
+
+    fn all_in(symbol: &str, side: OrderSide) -> Box<dyn Strategy> {
+        Box::new(AllIn {
+            symbol: symbol.to_string(),
+            side,
+            bought: false,
+        })
+    }
+
+    #[test]
+    fn weights_limit_each_strategy() {
+        let backtest = Backtest::new(
+            feed(&[100.0, 100.0, 100.0]),
+            Broker::new("Weighted", 10_000.0, 0.0, 1.0, false, false),
+            Box::new(PortfolioStrategy::with_weights(vec![
+                (all_in("AAPL", OrderSide::Buy), 0.6),
+                (all_in("MSFT", OrderSide::Buy), 0.4),
+            ])),
+        );
+        let result = backtest.run().unwrap();
+        let broker = result.get_broker();
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, 60.0);
+        assert_eq!(broker.get_position("MSFT").unwrap().amount, 40.0);
+        assert_eq!(broker.buying_power(), 0.0);
+    }
+
+    /// A short counts against its strategy's slice like a long does.
+    #[test]
+    fn weights_limit_shorts() {
+        let backtest = Backtest::new(
+            feed(&[100.0, 100.0, 100.0]),
+            BrokerBuilder::new("Weighted").initial_cash(10_000.0).allow_short(true).build(),
+            Box::new(PortfolioStrategy::with_weights(vec![
+                (all_in("AAPL", OrderSide::Buy), 0.6),
+                (all_in("MSFT", OrderSide::Sell), 0.4),
+            ])),
+        );
+        let result = backtest.run().unwrap();
+        let broker = result.get_broker();
+        assert_eq!(broker.get_position("AAPL").unwrap().amount, 60.0);
+        assert_eq!(broker.get_position("MSFT").unwrap().amount, -40.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn weights_above_one() {
+        PortfolioStrategy::with_weights(vec![
+            (Box::new(BuyAndHold::default()), 0.6),
+            (Box::new(BuyAndHold::default()), 0.6),
+        ]);
+    }
+}
@@ -0,0 +1,173 @@
+use super::*;
+use crate::indicators::RSI;
+
+/// # RSI Mean Reversion
+///
+/// Bets that oversold prices revert upwards, using the `RSI` indicator.
+///
+/// ## States
+///
+/// - `No Position` - Once the RSI crosses up through the `oversold` threshold, the selling
+///   pressure is fading, so the strategy buys `quantity` shares.
+/// - `Long` - Once the RSI crosses down through the `overbought` threshold, the strategy
+///   flattens its position.
+#[derive(Clone)]
+pub struct RSIReversion {
+    symbol: String,
+    quantity: f32,
+    oversold: f32,
+    overbought: f32,
+    rsi: RSI,
+    previous_rsi: Option<f32>,
+    long: bool,
+}
+
+impl Default for RSIReversion {
+    fn default() -> Self {
+        Self::new(14)
+    }
+}
+
+impl RSIReversion {
+    /// Trades `100` shares of `"AAPL"` using a smoothed RSI of `period` tickers,
+    /// with an oversold threshold of `30` and an overbought threshold of `70`.
+    pub fn new(period: u32) -> Self {
+        Self::with_params(period, "AAPL", 100.0, 30.0, 70.0)
+    }
+
+    /// Trades `quantity` shares of `symbol` using a smoothed RSI of `period` tickers.
+    pub fn with_params(period: u32, symbol: &str, quantity: f32, oversold: f32, overbought: f32) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            quantity,
+            oversold,
+            overbought,
+            rsi: RSI::new(period, true),
+            previous_rsi: None,
+            long: false,
+        }
+    }
+
+    pub fn get_symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn get_quantity(&self) -> f32 {
+        self.quantity
+    }
+
+    pub fn get_oversold(&self) -> f32 {
+        self.oversold
+    }
+
+    pub fn get_overbought(&self) -> f32 {
+        self.overbought
+    }
+
+    fn order(&self, side: OrderSide, ticker: &Ticker) -> Order {
+        Order {
+            symbol: self.symbol.clone(),
+            quantity: self.quantity,
+            reduce_only: side == OrderSide::Sell,
+            side,
+            order_type: OrderType::Market,
+            datetime: ticker.datetime,
+            execution: OrderExecutionStrategy::GTC,
+            on_execute: None,
+            on_cancel: None,
+        }
+    }
+}
+
+impl fmt::Display for RSIReversion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "RSI Reversion({}, Symbol: {}, Quantity: {}, Oversold: {}, Overbought: {})",
+            self.rsi, self.symbol, self.quantity, self.oversold, self.overbought
+        )
+    }
+}
+
+impl Strategy for RSIReversion {
+    fn prepare(&mut self, _broker: &mut Broker) -> Result<(), StrategyError> {
+        Ok(())
+    }
+
+    fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+        self.rsi.update(ticker).ok();
+        let rsi = match self.rsi.get_value() {
+            Ok(rsi) => rsi,
+            Err(_) => return Ok(()),
+        };
+
+        if let Some(previous_rsi) = self.previous_rsi {
+            if !self.long && previous_rsi < self.oversold && rsi >= self.oversold {
+                broker.submit_order(self.order(OrderSide::Buy, ticker))?;
+                self.long = true;
+            } else if self.long && previous_rsi > self.overbought && rsi <= self.overbought {
+                broker.submit_order(self.order(OrderSide::Sell, ticker))?;
+                self.long = false;
+            }
+        }
+        self.previous_rsi = Some(rsi);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backtest::Backtest, timeseries::TimeSeries};
+    use chrono::{TimeZone, Utc};
+
+    /// Oscillates around `100` with troughs on days 15, 35 and 55 and peaks on days 5, 25 and 45.
+    fn closes() -> Vec<f32> {
+        (0..60)
+            .map(|day| 100.0 + 10.0 * (day as f32 * std::f32::consts::PI / 10.0).sin())
+            .collect()
+    }
+
+    fn feed(closes: &[f32]) -> TimeSeries {
+        TimeSeries::from_vec(
+            closes
+                .iter()
+                .enumerate()
+                .map(|(day, &close)| Ticker {
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: 1000,
+                    datetime: Utc.timestamp_opt(day as i64 * 86_400, 0).unwrap(),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn enters_long_after_oversold() {
+        let closes = closes();
+        let backtest = Backtest::new(
+            feed(&closes),
+            Broker::new("RSI Reversion", 100_000.0, 0.0, 1.0, false, false),
+            Box::new(RSIReversion::with_params(5, "AAPL", 10.0, 30.0, 70.0)),
+        );
+        let result = backtest.run().unwrap();
+        let trades = result
+            .get_broker()
+            .get_trades()
+            .iter()
+            .map(|trade| {
+                let day = trade.datetime.timestamp() / 86_400;
+                (trade.side.clone(), day)
+            })
+            .collect::<Vec<_>>();
+        // The RSI recovers through 30 two days after each trough, and the buy fills the day after.
+        // It falls back through 70 three days after each peak.
+        let expected = [(OrderSide::Buy, 18), (OrderSide::Sell, 28), (OrderSide::Buy, 38), (OrderSide::Sell, 48), (OrderSide::Buy, 58)];
+        assert_eq!(trades, expected.to_vec());
+        assert!(result.get_broker().get_trades()[0].price < closes[10]);
+    }
+}
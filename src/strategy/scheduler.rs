@@ -0,0 +1,111 @@
+use chrono::{DateTime, Datelike, Utc};
+
+/// When a `Scheduler` fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// On the first bar and every `n`th bar after it.
+    EveryBars(u32),
+    /// On the first bar of every ISO week.
+    Weekly,
+    /// On the first bar of every month.
+    Monthly,
+}
+
+/// Tells a strategy when to rebalance, e.g. on the first bar of every month.
+///
+/// The strategy holds a `Scheduler` and asks it whether a rebalance `is_due` with the datetime
+/// of every ticker it receives. The first ticker always starts a period, so the scheduler
+/// fires on it.
+///
+/// ```
+/// use backtester::strategy::Scheduler;
+/// use chrono::{TimeZone, Utc};
+///
+/// let mut scheduler = Scheduler::monthly();
+/// assert!(scheduler.is_due(Utc.with_ymd_and_hms(2023, 1, 30, 0, 0, 0).unwrap()));
+/// assert!(!scheduler.is_due(Utc.with_ymd_and_hms(2023, 1, 31, 0, 0, 0).unwrap()));
+/// assert!(scheduler.is_due(Utc.with_ymd_and_hms(2023, 2, 1, 0, 0, 0).unwrap()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    schedule: Schedule,
+    /// The bars seen since the scheduler last fired.
+    bars: u32,
+    previous: Option<DateTime<Utc>>,
+}
+
+impl Scheduler {
+    pub fn new(schedule: Schedule) -> Self {
+        if schedule == Schedule::EveryBars(0) {
+            panic!("Scheduler: EveryBars should be at least 1.");
+        }
+        Self {
+            schedule,
+            bars: 0,
+            previous: None,
+        }
+    }
+
+    /// Fires on the first bar and every `n`th bar after it.
+    pub fn every_bars(n: u32) -> Self {
+        Self::new(Schedule::EveryBars(n))
+    }
+
+    /// Fires on the first bar of every ISO week.
+    pub fn weekly() -> Self {
+        Self::new(Schedule::Weekly)
+    }
+
+    /// Fires on the first bar of every month.
+    pub fn monthly() -> Self {
+        Self::new(Schedule::Monthly)
+    }
+
+    pub fn get_schedule(&self) -> Schedule {
+        self.schedule
+    }
+
+    /// Records a bar at `datetime` and returns whether a rebalance is due on it.
+    /// Call it exactly once per bar, in chronological order.
+    pub fn is_due(&mut self, datetime: DateTime<Utc>) -> bool {
+        let previous = self.previous.replace(datetime);
+        let due = match (previous, self.schedule) {
+            (None, _) => true,
+            (Some(_), Schedule::EveryBars(n)) => self.bars + 1 >= n,
+            (Some(previous), Schedule::Weekly) => previous.iso_week() != datetime.iso_week(),
+            (Some(previous), Schedule::Monthly) => (previous.year(), previous.month()) != (datetime.year(), datetime.month()),
+        };
+        self.bars = if due { 0 } else { self.bars + 1 };
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn monthly_fires_on_first_bar_of_each_month() {
+        let mut scheduler = Scheduler::monthly();
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 16, 0, 0).unwrap();
+        let fired = (0..120)
+            .map(|day| start + Duration::days(day))
+            // Weekdays only, so that some months start on the 2nd or the 3rd.
+            .filter(|datetime| datetime.weekday().number_from_monday() <= 5)
+            .filter(|datetime| scheduler.is_due(*datetime))
+            .map(|datetime| datetime.date_naive().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(fired, vec!["2023-01-02", "2023-02-01", "2023-03-01", "2023-04-03"]);
+    }
+
+    #[test]
+    fn every_bars() {
+        let mut scheduler = Scheduler::every_bars(3);
+        let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        let fired = (0..8)
+            .filter(|day| scheduler.is_due(start + Duration::days(*day)))
+            .collect::<Vec<_>>();
+        assert_eq!(fired, vec![0, 3, 6]);
+    }
+}
@@ -12,7 +12,9 @@ use crate::indicators::SMA;
 /// - `Long` - The SMA has crossed about the ticker price, so we execute a market buy order
 #[derive(Clone)]
 pub struct SMACrossover {
-    order_id: usize,
+    period: u32,
+    symbol: String,
+    quantity: f32,
     previous_sma: f32,
     previous_ticker: Option<Ticker>,
     sma_indicator: SMA,
@@ -25,19 +27,43 @@ impl Default for SMACrossover {
 }
 
 impl SMACrossover {
+    /// Trades `100` shares of `"AAPL"` using an SMA of `period` tickers.
     pub fn new(period: u32) -> Self {
+        Self::with_params(period, "AAPL", 100.0)
+    }
+
+    /// Trades `quantity` shares of `symbol` using an SMA of `period` tickers.
+    pub fn with_params(period: u32, symbol: &str, quantity: f32) -> Self {
         Self {
-            order_id: 0,
+            period,
+            symbol: symbol.to_string(),
+            quantity,
             previous_sma: 0.0,
             previous_ticker: None,
             sma_indicator: SMA::new(period),
         }
     }
+
+    pub fn get_period(&self) -> u32 {
+        self.period
+    }
+
+    pub fn get_symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn get_quantity(&self) -> f32 {
+        self.quantity
+    }
 }
 
 impl fmt::Display for SMACrossover {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "SMA Crossover(Period: {})", self.sma_indicator)
+        write!(
+            f,
+            "SMA Crossover(Period: {}, Symbol: {}, Quantity: {})",
+            self.period, self.symbol, self.quantity
+        )
     }
 }
 
@@ -55,37 +81,34 @@ impl Strategy for SMACrossover {
             {
                 broker
                     .submit_order(
-                        self.order_id,
                         Order {
-                            symbol: "AAPL".to_string(),
-                            quantity: 100.0,
+                            symbol: self.symbol.clone(),
+                            quantity: self.quantity,
                             side: OrderSide::Buy,
                             order_type: OrderType::Market,
                             datetime: ticker.datetime.clone(),
                             execution: OrderExecutionStrategy::GTC,
+                            reduce_only: false,
                             on_execute: None,
                             on_cancel: None,
                         },
-                    )
-                    .err();
-                self.order_id += 1;
+                    )?;
             } else if sma < ticker.close
                 && self.previous_sma > self.previous_ticker.as_ref().unwrap().close
             {
                 broker.submit_order(
-                    self.order_id,
                     Order {
-                        symbol: "AAPL".to_string(),
-                        quantity: 100.0,
+                        symbol: self.symbol.clone(),
+                        quantity: self.quantity,
                         side: OrderSide::Sell,
                         order_type: OrderType::Market,
                         datetime: ticker.datetime.clone(),
                         execution: OrderExecutionStrategy::GTC,
+                        reduce_only: false,
                         on_execute: None,
                         on_cancel: None,
                     },
                 )?;
-                self.order_id += 1;
             }
 
             self.previous_sma = sma;
@@ -95,3 +118,68 @@ impl Strategy for SMACrossover {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backtest::Backtest, timeseries::TimeSeries};
+    use chrono::{TimeZone, Utc};
+
+    fn feed(closes: &[f32]) -> TimeSeries {
+        TimeSeries::from_vec(
+            closes
+                .iter()
+                .enumerate()
+                .map(|(day, &close)| Ticker {
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: 1000,
+                    datetime: Utc.timestamp_opt(day as i64 * 86_400, 0).unwrap(),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn with_params() {
+        let strategy = SMACrossover::with_params(3, "MSFT", 25.0);
+        assert_eq!(strategy.get_period(), 3);
+        assert_eq!(strategy.get_symbol(), "MSFT");
+        assert_eq!(strategy.get_quantity(), 25.0);
+    }
+
+    #[test]
+    fn smoke() {
+        // The close crosses below the SMA on the fifth bar and back above it on the eighth.
+        let backtest = Backtest::new(
+            feed(&[10.0, 11.0, 12.0, 13.0, 9.0, 9.0, 8.0, 14.0, 15.0, 15.0]),
+            Broker::new("SMA Crossover", 100_000.0, 0.0, 1.0, false, false),
+            Box::new(SMACrossover::new(3)),
+        );
+        let result = backtest.run().unwrap();
+        let sides = result
+            .get_broker()
+            .get_trades()
+            .iter()
+            .map(|trade| trade.side.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(sides, vec![OrderSide::Buy, OrderSide::Sell]);
+        assert!(result.get_broker().get_position("AAPL").is_none());
+    }
+
+    #[test]
+    fn orders_use_configured_symbol_and_quantity() {
+        // The close crosses below the SMA on the fifth bar, and the buy fills on the sixth.
+        let backtest = Backtest::new(
+            feed(&[10.0, 11.0, 12.0, 13.0, 9.0, 9.0]),
+            Broker::new("SMA Crossover", 100_000.0, 0.0, 1.0, false, false),
+            Box::new(SMACrossover::with_params(3, "MSFT", 25.0)),
+        );
+        let result = backtest.run().unwrap();
+        let position = result.get_broker().get_position("MSFT").unwrap();
+        assert_eq!(position.amount, 25.0);
+        assert!(result.get_broker().get_position("AAPL").is_none());
+    }
+}
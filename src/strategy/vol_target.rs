@@ -0,0 +1,143 @@
+use super::*;
+use crate::indicators::ATR;
+use crate::sizing;
+
+/// # Volatility Targeting
+///
+/// Holds a long position sized so that a move of one `ATR` against it loses `risk_fraction`
+/// of the broker's equity, i.e. `equity * risk_fraction / ATR` shares. The position shrinks
+/// as volatility rises and grows as it falls, which keeps the risk of the position roughly
+/// constant, as in risk parity.
+///
+/// To avoid trading on every ticker, the position is only rebalanced once it is more than
+/// `10%` away from its target. Buys are capped at what the broker can afford.
+#[derive(Clone)]
+pub struct VolTargetStrategy {
+    symbol: String,
+    risk_fraction: f32,
+    atr: ATR,
+}
+
+impl Default for VolTargetStrategy {
+    fn default() -> Self {
+        Self::new("AAPL", 0.01, 14)
+    }
+}
+
+impl VolTargetStrategy {
+    /// Trades `symbol`, risking `risk_fraction` (e.g. `0.01` for 1%) of the equity per `ATR`
+    /// of `period` tickers.
+    pub fn new(symbol: &str, risk_fraction: f32, period: u32) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            risk_fraction,
+            atr: ATR::new(period),
+        }
+    }
+
+    pub fn get_symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn get_risk_fraction(&self) -> f32 {
+        self.risk_fraction
+    }
+
+    fn order(&self, side: OrderSide, quantity: f32, ticker: &Ticker) -> Order {
+        Order {
+            symbol: self.symbol.clone(),
+            quantity,
+            reduce_only: side == OrderSide::Sell,
+            side,
+            order_type: OrderType::Market,
+            datetime: ticker.datetime,
+            execution: OrderExecutionStrategy::GTC,
+            on_execute: None,
+            on_cancel: None,
+        }
+    }
+}
+
+impl fmt::Display for VolTargetStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Vol Target({}, Symbol: {}, Risk Fraction: {})",
+            self.atr, self.symbol, self.risk_fraction
+        )
+    }
+}
+
+impl Strategy for VolTargetStrategy {
+    fn prepare(&mut self, _broker: &mut Broker) -> Result<(), StrategyError> {
+        Ok(())
+    }
+
+    fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
+        self.atr.update(ticker).ok();
+        let atr = match self.atr.get_value() {
+            Ok(atr) => atr,
+            Err(_) => return Ok(()),
+        };
+
+        let stop = ticker.close - atr;
+        let target = sizing::fixed_fractional(broker.get_equity(), self.risk_fraction, ticker.close, stop).floor();
+        let current = broker.get_position(&self.symbol).map_or(0.0, |position| position.amount);
+        if (target - current).abs() <= 0.1 * target {
+            return Ok(());
+        }
+
+        if target > current {
+            let quantity = (target - current).min(broker.max_shares(ticker.close));
+            if quantity > 0.0 {
+                broker.submit_order(self.order(OrderSide::Buy, quantity, ticker))?;
+            }
+        } else {
+            broker.submit_order(self.order(OrderSide::Sell, current - target, ticker))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backtest::Backtest, timeseries::TimeSeries};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn position_shrinks_as_volatility_rises() {
+        // Swings of 2 around 100 for 40 days, then swings of 10.
+        let tickers = (0..80)
+            .map(|day| {
+                let swing = if day < 40 { 1.0 } else { 5.0 };
+                let close = if day % 2 == 0 { 100.0 + swing } else { 100.0 - swing };
+                Ticker {
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: 1000,
+                    datetime: Utc.timestamp_opt(day * 86_400, 0).unwrap(),
+                }
+            })
+            .collect();
+        let backtest = Backtest::new(
+            TimeSeries::from_vec(tickers),
+            Broker::new("Vol Target", 100_000.0, 0.0, 1.0, false, false),
+            Box::new(VolTargetStrategy::new("AAPL", 0.01, 10)),
+        );
+        let mut positions = Vec::new();
+        backtest
+            .run_with_observer(|_, broker| {
+                positions.push(broker.get_position("AAPL").map_or(0.0, |position| position.amount));
+            })
+            .unwrap();
+
+        // An ATR of 2 targets about 100,000 * 1% / 2 = 500 shares, and an ATR of 10 about 100.
+        let calm = positions[39];
+        let volatile = positions[79];
+        assert!((450.0..=550.0).contains(&calm), "calm position {}", calm);
+        assert!((90.0..=110.0).contains(&volatile), "volatile position {}", volatile);
+    }
+}
@@ -6,9 +6,10 @@
 ///! If you are looking to create a stream of ticker data, use the `TimeSeries` struct.
 use std::path::Path;
 use std::fs::read_dir;
+use chrono::{DateTime, Duration, TimeZone, Utc};
 
 use crate::{
-	series::Series,
+	series::{Series, SeriesIntoIterator, SeriesSchema},
 	types::Ticker,
 };
 
@@ -22,6 +23,12 @@ use crate::{
 pub type TimeSeries = Series<Ticker>;
 
 impl TimeSeries {
+  /// Initializes a new TimeSeries from a CSV file whose headers differ
+  /// from the `Ticker` field names. See `SeriesSchema`.
+  pub fn from_csv_with_schema<P: AsRef<Path>>(path: P, schema: SeriesSchema) -> Self {
+      Self::from_csv(path).with_schema(schema)
+  }
+
   /// Initializes a set of TimeSeries from a directory.
   /// This function uses `from_csv` for each CSV file, so
   /// ensure that the format of each CSV file is correct.
@@ -42,4 +49,541 @@ impl TimeSeries {
       }
       result
   }
-}
\ No newline at end of file
+
+  /// Aggregates the bars into buckets of `period`, aligned to the unix epoch.
+  ///
+  /// Each emitted bar takes the open of the first bar in the bucket, the highest high,
+  /// the lowest low, the close of the last bar, the summed volume, and the datetime of
+  /// the start of the bucket. A partial trailing bucket is still emitted.
+  ///
+  /// # Panics
+  /// If `period` is not at least one second long.
+  pub fn resample(self, period: Duration) -> Resample<SeriesIntoIterator<Ticker>> {
+      assert!(period.num_seconds() > 0, "Resampling period should be at least one second.");
+      Resample {
+          inner: self.into_iter(),
+          period: period.num_seconds(),
+          bucket: None,
+      }
+  }
+
+  /// Yields only the bars whose datetime falls within `[start, end]`.
+  ///
+  /// Bars before `start` are skipped lazily, and iteration stops at the first bar
+  /// after `end`, so the feed is assumed to be in chronological order.
+  pub fn between(self, start: DateTime<Utc>, end: DateTime<Utc>) -> Between<SeriesIntoIterator<Ticker>> {
+      Between {
+          inner: self.into_iter(),
+          start,
+          end,
+          done: start > end,
+      }
+  }
+
+  /// Fills gaps longer than `step` with synthetic bars spaced `step` apart.
+  ///
+  /// Synthetic bars carry the previous close into their open, high, low and close,
+  /// and have zero volume. Feeds without gaps pass through unchanged.
+  ///
+  /// # Panics
+  /// If `step` is not positive.
+  pub fn forward_fill(self, step: Duration) -> ForwardFill<SeriesIntoIterator<Ticker>> {
+      assert!(step > Duration::zero(), "Forward fill step should be positive.");
+      ForwardFill {
+          inner: self.into_iter(),
+          step,
+          previous: None,
+          pending: None,
+      }
+  }
+
+  /// Transforms the bars into [Heikin-Ashi](https://www.investopedia.com/trading/heikin-ashi-better-candlestick/)
+  /// candles, which smooth out noise to make trends easier to see.
+  ///
+  /// Each candle closes at the average of the bar's open, high, low and close, and opens at the
+  /// midpoint of the previous candle's open and close. The first candle opens at the midpoint of
+  /// the bar's own open and close. The high and low extend to cover the candle's open and close.
+  /// Volumes and datetimes are unchanged. The candles are a `Feed`, e.g. for `Backtest::run_feed`.
+  pub fn heikin_ashi(self) -> HeikinAshi<SeriesIntoIterator<Ticker>> {
+      HeikinAshi {
+          inner: self.into_iter(),
+          previous: None,
+      }
+  }
+
+  /// Collapses consecutive bars with the same datetime, e.g. corrected ticks, into the last
+  /// of them, so that the broker's clock never stalls on a repeated datetime.
+  /// The bars are a `Feed`, e.g. for `Backtest::run_feed`.
+  pub fn dedup_timestamps(self) -> DedupTimestamps<SeriesIntoIterator<Ticker>> {
+      DedupTimestamps {
+          inner: self.into_iter(),
+          pending: None,
+      }
+  }
+
+  /// Checks every bar for inconsistent prices, reporting all the offending rows.
+  ///
+  /// A bar is flagged if any price is NaN or negative, if `high < low`,
+  /// or if the open or close fall outside `[low, high]`.
+  pub fn validate(self) -> Result<(), Vec<ValidationError>> {
+      let mut errors = Vec::new();
+      for (row, ticker) in self.into_iter().enumerate() {
+          match ticker {
+              Ok(ticker) => errors.extend(violations(&ticker)),
+              Err(err) => {
+                  let line = err.position().map(|pos| pos.line()).unwrap_or(row as u64 + 2);
+                  errors.push(ValidationError::Unparseable(line));
+              }
+          }
+      }
+      if errors.is_empty() {
+          Ok(())
+      } else {
+          Err(errors)
+      }
+  }
+}
+
+/// A bar that failed `TimeSeries::validate`, identified by its datetime.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+	/// The row could not be parsed. Contains the line number of the row.
+	Unparseable(u64),
+	NaNPrice(DateTime<Utc>),
+	NegativePrice(DateTime<Utc>),
+	HighBelowLow(DateTime<Utc>),
+	OpenOutsideRange(DateTime<Utc>),
+	CloseOutsideRange(DateTime<Utc>),
+}
+
+fn violations(ticker: &Ticker) -> Vec<ValidationError> {
+	let prices = [ticker.open, ticker.high, ticker.low, ticker.close];
+	if prices.iter().any(|price| price.is_nan()) {
+		// Comparisons against NaN are meaningless, so there is nothing else to check.
+		return vec![ValidationError::NaNPrice(ticker.datetime)];
+	}
+
+	let mut errors = Vec::new();
+	if prices.iter().any(|price| *price < 0.0) {
+		errors.push(ValidationError::NegativePrice(ticker.datetime));
+	}
+	if ticker.high < ticker.low {
+		errors.push(ValidationError::HighBelowLow(ticker.datetime));
+	}
+	if ticker.open < ticker.low || ticker.open > ticker.high {
+		errors.push(ValidationError::OpenOutsideRange(ticker.datetime));
+	}
+	if ticker.close < ticker.low || ticker.close > ticker.high {
+		errors.push(ValidationError::CloseOutsideRange(ticker.datetime));
+	}
+	errors
+}
+
+/// Lazily fills gaps between bars. See `TimeSeries::forward_fill`.
+pub struct ForwardFill<I> {
+	inner: I,
+	step: Duration,
+	previous: Option<Ticker>,
+	pending: Option<Ticker>,
+}
+
+impl<I> Iterator for ForwardFill<I>
+where I: Iterator<Item = Result<Ticker, csv::Error>> {
+	type Item = Result<Ticker, csv::Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let ticker = match self.pending.take() {
+			Some(ticker) => ticker,
+			None => match self.inner.next()? {
+				Ok(ticker) => ticker,
+				Err(err) => return Some(Err(err)),
+			},
+		};
+
+		if let Some(previous) = &self.previous {
+			let expected = previous.datetime + self.step;
+			if ticker.datetime > expected {
+				let filled = Ticker {
+					open: previous.close,
+					high: previous.close,
+					low: previous.close,
+					close: previous.close,
+					volume: 0,
+					datetime: expected,
+				};
+				self.pending = Some(ticker);
+				self.previous = Some(filled.clone());
+				return Some(Ok(filled));
+			}
+		}
+
+		self.previous = Some(ticker.clone());
+		Some(Ok(ticker))
+	}
+}
+
+/// Lazily transforms bars into Heikin-Ashi candles. See `TimeSeries::heikin_ashi`.
+pub struct HeikinAshi<I> {
+	inner: I,
+	/// The open and close of the previous candle.
+	previous: Option<(f32, f32)>,
+}
+
+impl<I> Iterator for HeikinAshi<I>
+where I: Iterator<Item = Result<Ticker, csv::Error>> {
+	type Item = Result<Ticker, csv::Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let ticker = match self.inner.next()? {
+			Ok(ticker) => ticker,
+			Err(err) => return Some(Err(err)),
+		};
+
+		let close = (ticker.open + ticker.high + ticker.low + ticker.close) / 4.0;
+		let open = match self.previous {
+			Some((open, close)) => (open + close) / 2.0,
+			None => (ticker.open + ticker.close) / 2.0,
+		};
+		self.previous = Some((open, close));
+		Some(Ok(Ticker {
+			open,
+			high: ticker.high.max(open).max(close),
+			low: ticker.low.min(open).min(close),
+			close,
+			..ticker
+		}))
+	}
+}
+
+/// Lazily drops bars that are followed by a bar with the same datetime.
+/// See `TimeSeries::dedup_timestamps`.
+pub struct DedupTimestamps<I> {
+	inner: I,
+	/// The latest bar, held back until a bar with another datetime arrives.
+	pending: Option<Ticker>,
+}
+
+impl<I> Iterator for DedupTimestamps<I>
+where I: Iterator<Item = Result<Ticker, csv::Error>> {
+	type Item = Result<Ticker, csv::Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			let ticker = match self.inner.next() {
+				Some(Ok(ticker)) => ticker,
+				Some(Err(err)) => return Some(Err(err)),
+				None => return self.pending.take().map(Ok),
+			};
+			match self.pending.take() {
+				Some(previous) if previous.datetime != ticker.datetime => {
+					self.pending = Some(ticker);
+					return Some(Ok(previous));
+				}
+				_ => self.pending = Some(ticker),
+			}
+		}
+	}
+}
+
+/// Lazily filters bars to a date range. See `TimeSeries::between`.
+pub struct Between<I> {
+	inner: I,
+	start: DateTime<Utc>,
+	end: DateTime<Utc>,
+	done: bool,
+}
+
+impl<I> Iterator for Between<I>
+where I: Iterator<Item = Result<Ticker, csv::Error>> {
+	type Item = Result<Ticker, csv::Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		while !self.done {
+			match self.inner.next() {
+				Some(Ok(ticker)) if ticker.datetime < self.start => continue,
+				Some(Ok(ticker)) if ticker.datetime > self.end => self.done = true,
+				Some(result) => return Some(result),
+				None => self.done = true,
+			}
+		}
+		None
+	}
+}
+
+/// Lazily aggregates bars into fixed-length buckets. See `TimeSeries::resample`.
+pub struct Resample<I> {
+	inner: I,
+	period: i64,
+	bucket: Option<Ticker>,
+}
+
+impl<I> Resample<I> {
+	fn bucket_start(&self, datetime: &DateTime<Utc>) -> DateTime<Utc> {
+		let timestamp = datetime.timestamp();
+		Utc.timestamp_opt(timestamp - timestamp.rem_euclid(self.period), 0).unwrap()
+	}
+}
+
+impl<I> Iterator for Resample<I>
+where I: Iterator<Item = Result<Ticker, csv::Error>> {
+	type Item = Result<Ticker, csv::Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			let ticker = match self.inner.next() {
+				Some(Ok(ticker)) => ticker,
+				Some(Err(err)) => return Some(Err(err)),
+				None => return self.bucket.take().map(Ok),
+			};
+
+			let start = self.bucket_start(&ticker.datetime);
+			match &mut self.bucket {
+				Some(bucket) if bucket.datetime == start => {
+					bucket.high = bucket.high.max(ticker.high);
+					bucket.low = bucket.low.min(ticker.low);
+					bucket.close = ticker.close;
+					bucket.volume = bucket.volume.saturating_add(ticker.volume);
+				}
+				_ => {
+					let next = Ticker { datetime: start, ..ticker };
+					if let Some(finished) = self.bucket.replace(next) {
+						return Some(Ok(finished));
+					}
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(rows: &[(f32, f32, f32, f32, u32, i64)]) -> TimeSeries {
+        TimeSeries::from_vec(
+            rows.iter()
+                .map(|&(open, high, low, close, volume, timestamp)| Ticker {
+                    open,
+                    high,
+                    low,
+                    close,
+                    volume,
+                    datetime: Utc.timestamp_opt(timestamp, 0).unwrap(),
+                })
+                .collect(),
+        )
+    }
+
+    const AAC: &str = "./benches/datasets/timeseries/AAC.csv";
+
+    #[test]
+    fn between_mid_file() {
+        let start = Utc.timestamp_opt(1656077400000, 0).unwrap();
+        let end = Utc.timestamp_opt(1656682200000, 0).unwrap();
+        let tickers = TimeSeries::from_csv(AAC)
+            .between(start, end)
+            .collect::<Result<Vec<Ticker>, _>>()
+            .unwrap();
+        assert_eq!(tickers.len(), 6);
+        assert_eq!(tickers.first().unwrap().datetime, start);
+        assert_eq!(tickers.first().unwrap().volume, 5);
+        assert_eq!(tickers.last().unwrap().datetime, end);
+        assert_eq!(tickers.last().unwrap().volume, 928);
+    }
+
+    #[test]
+    fn between_empty_range() {
+        let start = Utc.timestamp_opt(1656682200000, 0).unwrap();
+        let end = Utc.timestamp_opt(1656077400000, 0).unwrap();
+        assert_eq!(TimeSeries::from_csv(AAC).between(start, end).count(), 0);
+    }
+
+    #[test]
+    fn between_outside_data() {
+        let start = Utc.timestamp_opt(0, 0).unwrap();
+        let end = Utc.timestamp_opt(86_400, 0).unwrap();
+        assert_eq!(TimeSeries::from_csv(AAC).between(start, end).count(), 0);
+    }
+
+    #[test]
+    fn heikin_ashi_recurrence() {
+        let candles = series(&[
+            (10.0, 12.0, 9.0, 11.0, 100, 0),
+            (11.0, 14.0, 11.0, 13.0, 100, 60),
+            (13.0, 13.0, 8.0, 9.0, 100, 120),
+        ])
+        .heikin_ashi()
+        .collect::<Result<Vec<Ticker>, _>>()
+        .unwrap();
+        let ohlc = candles
+            .iter()
+            .map(|ticker| (ticker.open, ticker.high, ticker.low, ticker.close))
+            .collect::<Vec<_>>();
+        // Closes: (10 + 12 + 9 + 11) / 4 = 10.5, (11 + 14 + 11 + 13) / 4 = 12.25, (13 + 13 + 8 + 9) / 4 = 10.75.
+        // Opens: (10 + 11) / 2 = 10.5, (10.5 + 10.5) / 2 = 10.5, (10.5 + 12.25) / 2 = 11.375.
+        assert_eq!(
+            ohlc,
+            vec![(10.5, 12.0, 9.0, 10.5), (10.5, 14.0, 10.5, 12.25), (11.375, 13.0, 8.0, 10.75)]
+        );
+        assert_eq!(candles[2].datetime.timestamp(), 120);
+    }
+
+    #[test]
+    fn dedup_timestamps_keeps_last_row() {
+        let bars = series(&[
+            (10.0, 11.0, 9.5, 10.5, 100, 0),
+            (10.5, 12.0, 10.0, 11.5, 200, 60),
+            (10.5, 12.0, 10.0, 11.0, 250, 60),
+            (11.0, 11.8, 9.0, 9.5, 300, 120),
+            (11.0, 11.8, 9.0, 9.6, 300, 120),
+        ])
+        .dedup_timestamps()
+        .collect::<Result<Vec<Ticker>, _>>()
+        .unwrap();
+        let rows = bars
+            .iter()
+            .map(|ticker| (ticker.datetime.timestamp(), ticker.close))
+            .collect::<Vec<_>>();
+        assert_eq!(rows, vec![(0, 10.5), (60, 11.0), (120, 9.6)]);
+    }
+
+    #[test]
+    fn forward_fill_missing_day() {
+        let day = 86_400;
+        let feed = series(&[
+            (10.0, 11.0, 9.5, 10.5, 100, 0),
+            (10.5, 12.0, 10.0, 11.5, 200, day),
+            (11.5, 11.8, 9.0, 9.5, 300, 3 * day),
+        ]);
+        let bars = feed
+            .forward_fill(Duration::days(1))
+            .collect::<Result<Vec<Ticker>, _>>()
+            .unwrap();
+        assert_eq!(bars.len(), 4);
+        let filled = &bars[2];
+        assert_eq!(filled.datetime.timestamp(), 2 * day);
+        assert_eq!(filled.open, 11.5);
+        assert_eq!(filled.high, 11.5);
+        assert_eq!(filled.low, 11.5);
+        assert_eq!(filled.close, 11.5);
+        assert_eq!(filled.volume, 0);
+        assert_eq!(bars[3].datetime.timestamp(), 3 * day);
+    }
+
+    #[test]
+    fn forward_fill_dense() {
+        let day = 86_400;
+        let feed = series(&[
+            (10.0, 11.0, 9.5, 10.5, 100, 0),
+            (10.5, 12.0, 10.0, 11.5, 200, day),
+            (11.5, 11.8, 9.0, 9.5, 300, 2 * day),
+        ]);
+        let bars = feed
+            .forward_fill(Duration::days(1))
+            .collect::<Result<Vec<Ticker>, _>>()
+            .unwrap();
+        assert_eq!(bars.len(), 3);
+        assert!(bars.iter().all(|bar| bar.volume > 0));
+    }
+
+    #[test]
+    fn validate_clean() {
+        assert!(TimeSeries::from_csv(AAC).validate().is_ok());
+    }
+
+    #[test]
+    fn validate_corrupt_row() {
+        let day = 86_400;
+        let feed = series(&[
+            (10.0, 11.0, 9.5, 10.5, 100, 0),
+            (10.5, 12.0, 10.0, 12.5, 200, day),
+            (11.5, 9.0, 11.8, 9.5, 300, 2 * day),
+        ]);
+        let errors = feed.validate().unwrap_err();
+        let second = Utc.timestamp_opt(day, 0).unwrap();
+        let third = Utc.timestamp_opt(2 * day, 0).unwrap();
+        assert_eq!(errors, vec![
+            ValidationError::CloseOutsideRange(second),
+            ValidationError::HighBelowLow(third),
+            ValidationError::OpenOutsideRange(third),
+            ValidationError::CloseOutsideRange(third),
+        ]);
+    }
+
+    #[test]
+    fn validate_nan_price() {
+        let feed = series(&[(f32::NAN, 11.0, 9.5, 10.5, 100, 0)]);
+        let errors = feed.validate().unwrap_err();
+        assert_eq!(errors, vec![ValidationError::NaNPrice(Utc.timestamp_opt(0, 0).unwrap())]);
+    }
+
+    #[test]
+    fn resample_hourly() {
+        let hour = 1_654_779_600; // 2022-06-09 13:00:00
+        let feed = series(&[
+            (10.0, 11.0, 9.5, 10.5, 100, hour),
+            (10.5, 12.0, 10.0, 11.5, 200, hour + 900),
+            (11.5, 11.8, 9.0, 9.5, 300, hour + 1800),
+            (9.5, 10.2, 9.2, 10.0, 400, hour + 2700),
+        ]);
+        let bars = feed
+            .resample(Duration::hours(1))
+            .collect::<Result<Vec<Ticker>, _>>()
+            .unwrap();
+        assert_eq!(bars.len(), 1);
+        assert_eq!(bars[0].open, 10.0);
+        assert_eq!(bars[0].high, 12.0);
+        assert_eq!(bars[0].low, 9.0);
+        assert_eq!(bars[0].close, 10.0);
+        assert_eq!(bars[0].volume, 1000);
+        assert_eq!(bars[0].datetime.timestamp(), hour);
+    }
+
+    #[test]
+    fn resample_partial_trailing_bucket() {
+        let hour = 1_654_779_600;
+        let feed = series(&[
+            (10.0, 11.0, 9.5, 10.5, 100, hour),
+            (10.5, 12.0, 10.0, 11.5, 200, hour + 900),
+            (11.5, 11.8, 9.0, 9.5, 300, hour + 3600),
+        ]);
+        let bars = feed
+            .resample(Duration::hours(1))
+            .collect::<Result<Vec<Ticker>, _>>()
+            .unwrap();
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[1].open, 11.5);
+        assert_eq!(bars[1].volume, 300);
+        assert_eq!(bars[1].datetime.timestamp(), hour + 3600);
+    }
+
+    #[test]
+    fn from_csv_with_schema() {
+        let path = std::env::temp_dir().join(format!("backtester_schema_{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "Open,High,Low,Close,Volume,timestamp\n\
+             9.83,9.84,9.82,9.82,26,1654781400\n",
+        )
+        .unwrap();
+        let schema = SeriesSchema {
+            open: "Open".to_string(),
+            high: "High".to_string(),
+            low: "Low".to_string(),
+            close: "Close".to_string(),
+            volume: "Volume".to_string(),
+            datetime: "timestamp".to_string(),
+        };
+        let tickers = TimeSeries::from_csv_with_schema(&path, schema)
+            .into_iter()
+            .collect::<Result<Vec<Ticker>, _>>()
+            .unwrap();
+        assert_eq!(tickers.len(), 1);
+        assert_eq!(tickers[0].open, 9.83);
+        assert_eq!(tickers[0].high, 9.84);
+        assert_eq!(tickers[0].low, 9.82);
+        assert_eq!(tickers[0].close, 9.82);
+        assert_eq!(tickers[0].volume, 26);
+        assert_eq!(tickers[0].datetime.timestamp(), 1654781400);
+    }
+}
@@ -3,30 +3,101 @@ use crate::{
     util::serde_ext::*,
 };
 use serde_derive::{Deserialize, Serialize};
+use std::cell::RefCell;
 use std::fmt;
-use chrono::{DateTime, Utc};
+use std::rc::Rc;
+use chrono::{DateTime, NaiveDate, Utc};
 
 
 /// Represents a position that a strategy has opened.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Position {
     pub symbol: String,
     pub amount: f32,
     pub price: f32,
 }
 
+impl Position {
+    /// The value of the position at `current_price`. Negative for short positions.
+    pub fn market_value(&self, current_price: f32) -> f32 {
+        self.amount * current_price
+    }
+
+    /// The profit (or loss, if negative) of closing the position at `current_price`.
+    pub fn unrealized_pnl(&self, current_price: f32) -> f32 {
+        (current_price - self.price) * self.amount
+    }
+}
+
+/// A block of shares opened by a single fill, kept when the broker accounts for positions in lots.
+/// See `Accounting`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lot {
+    /// Negative for short lots.
+    pub amount: f32,
+    pub price: f32,
+}
+
+/// An event that changes the holders of `symbol` on its ex-date `date`.
+/// See `Broker::add_corporate_actions`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CorporateAction {
+    /// Pays `per_share` in cash for every share held. Short positions pay it instead.
+    /// `per_share` is in the symbol's currency, like its prices.
+    Dividend { symbol: String, date: NaiveDate, per_share: f32 },
+    /// Multiplies every holding by `ratio` and divides its price by it, e.g. `2.0` for a 2:1 split.
+    Split { symbol: String, date: NaiveDate, ratio: f32 },
+}
+
+impl CorporateAction {
+    pub fn get_date(&self) -> NaiveDate {
+        match self {
+            CorporateAction::Dividend { date, .. } | CorporateAction::Split { date, .. } => *date,
+        }
+    }
+}
+
+/// Cash added to (or, if negative, withdrawn from) an account from outside of it.
+/// See `Broker::deposit`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CashFlow {
+    pub amount: f32,
+    #[serde(with = "yyyy_mm_dd_hh_mm_ss")]
+    pub datetime: DateTime<Utc>,
+}
 
 /// When an order is filled a `Trade` is results.
 ///
 /// This struct is mostly used for bookkeeping purposes.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Trade {
     pub symbol: String,
+    pub side: OrderSide,
     pub quantity: f32,
+    /// The executed price, including slippage.
     pub price: f32,
+    /// The commission paid for the trade.
     pub commission: f32,
     #[serde(with = "yyyy_mm_dd_hh_mm_ss")]
     pub datetime: DateTime<Utc>,
+    /// The market price that the order filled against, before slippage and tick rounding.
+    #[serde(default)]
+    pub market_price: f32,
+    /// Whether the order made liquidity, which sets its commission under `CommissionModel::MakerTaker`.
+    #[serde(default)]
+    pub maker: bool,
+    /// The value of one unit of the symbol's currency in the account currency when the trade
+    /// filled. The price and commission are in the symbol's currency.
+    #[serde(default = "unit_fx_rate")]
+    pub fx_rate: f32,
+    /// The limit price of the order, rounded to the tick size. The price does not cross it.
+    #[serde(default)]
+    pub limit: Option<f32>,
+}
+
+/// Trades recorded before FX rates were supported are in the account currency.
+fn unit_fx_rate() -> f32 {
+    1.0
 }
 
 /// Represents an update in the market state
@@ -41,6 +112,28 @@ pub struct Ticker {
     pub datetime: DateTime<Utc>,
 }
 
+impl Ticker {
+    /// The [typical price](https://www.investopedia.com/terms/t/typicalprice.asp): `(high + low + close) / 3`.
+    pub fn typical_price(&self) -> f32 {
+        (self.high + self.low + self.close) / 3.0
+    }
+
+    /// The midpoint of the bar's range: `(high + low) / 2`.
+    pub fn median_price(&self) -> f32 {
+        (self.high + self.low) / 2.0
+    }
+
+    /// The average of the bar's prices: `(open + high + low + close) / 4`.
+    pub fn ohlc_average(&self) -> f32 {
+        (self.open + self.high + self.low + self.close) / 4.0
+    }
+}
+
+/// The fractional close-to-close return from `prev` to `cur`.
+pub fn pct_return(prev: &Ticker, cur: &Ticker) -> f32 {
+    (cur.close - prev.close) / prev.close
+}
+
 impl fmt::Display for Ticker {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -53,7 +146,7 @@ impl fmt::Display for Ticker {
 
 pub type OrderId = usize;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum OrderSide {
     Buy,
     Sell,
@@ -86,6 +179,11 @@ pub enum OrderType {
     LOC(f32),
     /// [Limit On Open](https://www.investopedia.com/terms/l/limitonopenorder.asp)
     LOO(f32),
+    /// [Trailing Stop](https://www.investopedia.com/terms/t/trailingstop.asp) that trails the
+    /// best close since submission by a fraction of it, e.g. `0.1` for 10%. A sell fires once the
+    /// close falls that far below its high-water mark, and a buy once it rises that far above
+    /// its low-water mark. It then turns into a Market order.
+    TrailingStopPct(f32),
 }
 
 impl fmt::Display for OrderType {
@@ -99,6 +197,7 @@ impl fmt::Display for OrderType {
             OrderType::MOO => write!(f, "MOO"),
             OrderType::LOC(limit) => write!(f, "LOC({})", limit),
             OrderType::LOO(limit) => write!(f, "LOO({})", limit),
+            OrderType::TrailingStopPct(pct) => write!(f, "TrailingStopPct({})", pct),
         }
     }
 }
@@ -129,13 +228,58 @@ impl fmt::Display for OrderExecutionStrategy {
     }
 }
 
+type OrderCallbackFn = dyn FnMut(&mut Broker) -> Result<(), BrokerError>;
+
+/// A callback attached to an `Order`. Closures can capture state, such as the quantity
+/// that a contingency order should protect. Clones of an order share its callbacks.
+///
+/// Callbacks are shared through `Rc`, so an `Order`, and a `Broker` holding orders, is not
+/// `Send`. Run backtests in parallel by building each one on the thread that runs it.
+#[derive(Clone)]
+pub struct OrderCallback(Rc<RefCell<OrderCallbackFn>>);
+
+impl OrderCallback {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: FnMut(&mut Broker) -> Result<(), BrokerError> + 'static,
+    {
+        Self(Rc::new(RefCell::new(callback)))
+    }
+
+    pub fn call(&self, broker: &mut Broker) -> Result<(), BrokerError> {
+        (self.0.borrow_mut())(broker)
+    }
+}
+
+type FillCallbackFn = dyn FnMut(&mut Broker, &Trade) -> Result<(), BrokerError>;
+
+/// A callback that runs when an `Order` is filled. It receives the resulting `Trade`,
+/// so contingency orders can be priced relative to the actual fill price.
+#[derive(Clone)]
+pub struct FillCallback(Rc<RefCell<FillCallbackFn>>);
+
+impl FillCallback {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: FnMut(&mut Broker, &Trade) -> Result<(), BrokerError> + 'static,
+    {
+        Self(Rc::new(RefCell::new(callback)))
+    }
+
+    pub fn call(&self, broker: &mut Broker, trade: &Trade) -> Result<(), BrokerError> {
+        (self.0.borrow_mut())(broker, trade)
+    }
+}
+
 /// Represents an order
 ///
 /// One can place orders within a strategy by calling `Broker::submit_order`.
-/// A log of all the current active orders can be found in `Broker::active_orders`.
+/// The current active orders can be found with `Broker::get_active_orders`.
 /// Orders that are executed result in a `Trade`.
 ///
-/// If you seek to update an order, cancel the existing order, and place a new one.
+/// Orders are not `Send`, because their callbacks are not (see `OrderCallback`).
+///
+/// If you seek to update an order, swap it for a new one with `Broker::replace_order`.
 /// 
 /// ## Dynamic Orders
 /// 
@@ -167,7 +311,6 @@ impl fmt::Display for OrderExecutionStrategy {
 /// 
 ///     fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
 ///         broker.submit_order(
-///             0,
 ///             Order {
 ///                 symbol: "AAPL".to_string(),
 ///                 quantity: 100.0,
@@ -175,22 +318,23 @@ impl fmt::Display for OrderExecutionStrategy {
 ///                 order_type: OrderType::Market,
 ///                 datetime: ticker.datetime.clone(),
 ///                 execution: OrderExecutionStrategy::GTC,
-///                 on_execute: Some(|broker| {
+///                 reduce_only: false,
+///                 on_execute: Some(FillCallback::new(|broker, trade| {
 ///                     broker.submit_order(
-///                         1,
 ///                         Order {  
 ///                             symbol: "AAPL".to_string(),
 ///                             quantity: 100.0, 
 ///                             side: OrderSide::Sell,
-///                             order_type: OrderType::Stop(90.0), // -$10 Profit at 100 Shares = -$1000
+///                             order_type: OrderType::Stop(trade.price * 0.9), // Limit the loss to 10% of the fill price
 ///                             datetime: broker.get_datetime(),
 ///                             execution: OrderExecutionStrategy::GTC,
+///                             reduce_only: false,
 ///                             on_execute: None,
 ///                             on_cancel: None,
 ///                         }
 ///                     )?;
 ///                     Ok(())
-///                 }),
+///                 })),
 ///                 on_cancel: None,
 ///             },
 ///         )?;
@@ -224,7 +368,6 @@ impl fmt::Display for OrderExecutionStrategy {
 /// 
 ///     fn on_ticker(&mut self, ticker: &Ticker, broker: &mut Broker) -> Result<(), StrategyError> {
 ///         broker.submit_order(
-///             0,
 ///             Order {
 ///                 symbol: "AAPL".to_string(),
 ///                 quantity: 100.0,
@@ -232,22 +375,23 @@ impl fmt::Display for OrderExecutionStrategy {
 ///                 order_type: OrderType::Market,
 ///                 datetime: ticker.datetime.clone(),
 ///                 execution: OrderExecutionStrategy::GTC,
-///                 on_execute: Some(|broker| {
+///                 reduce_only: false,
+///                 on_execute: Some(FillCallback::new(|broker, trade| {
 ///                     broker.submit_order(
-///                         1,
 ///                         Order {  
 ///                             symbol: "AAPL".to_string(),
 ///                             quantity: 100.0, 
 ///                             side: OrderSide::Sell,
-///                             order_type: OrderType::Stop(110.0), // $10 Profit * 100 Shares = $1000
+///                             order_type: OrderType::Limit(trade.price * 1.1), // Take a 10% profit over the fill price
 ///                             datetime: broker.get_datetime(),
 ///                             execution: OrderExecutionStrategy::GTC,
+///                             reduce_only: false,
 ///                             on_execute: None,
 ///                             on_cancel: None,
 ///                         }
 ///                     )?;
 ///                     Ok(())
-///                 }),
+///                 })),
 ///                 on_cancel: None,
 ///             },
 ///         )?;
@@ -255,20 +399,26 @@ impl fmt::Display for OrderExecutionStrategy {
 ///     }
 /// }
 /// ```
-/// 
-
-#[derive(Clone)]
+///
+/// Callbacks cannot be serialized, so they are skipped and deserialize as `None`.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Order {
     pub symbol: String,
     pub quantity: f32,
     pub side: OrderSide,
     pub order_type: OrderType,
+    #[serde(with = "yyyy_mm_dd_hh_mm_ss")]
     pub datetime: DateTime<Utc>,
     pub execution: OrderExecutionStrategy,
+    /// If set, the order may only shrink an existing position. The filled quantity is clamped
+    /// to the size of the position and an order with no position to reduce does nothing.
+    pub reduce_only: bool,
     /// If provided, this function is executed when the order is executed.
-    pub on_execute: Option<fn(&mut Broker) -> Result<(), BrokerError>>,
+    #[serde(skip)]
+    pub on_execute: Option<FillCallback>,
     /// If provided, this function is executed when the order is cancelled.
-    pub on_cancel: Option<fn(&mut Broker) -> Result<(), BrokerError>>,
+    #[serde(skip)]
+    pub on_cancel: Option<OrderCallback>,
 }
 
 impl fmt::Display for Order {
@@ -279,4 +429,117 @@ impl fmt::Display for Order {
             self.side, self.quantity, self.symbol, self.order_type, self.datetime
         )
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(amount: f32, price: f32) -> Position {
+        Position {
+            symbol: "AAPL".to_string(),
+            amount,
+            price,
+        }
+    }
+
+    #[test]
+    fn long_position_value() {
+        let long = position(100.0, 50.0);
+        assert_eq!(long.market_value(55.0), 5_500.0);
+        assert_eq!(long.unrealized_pnl(55.0), 500.0);
+        assert_eq!(long.unrealized_pnl(45.0), -500.0);
+    }
+
+    #[test]
+    fn short_position_value() {
+        let short = position(-100.0, 50.0);
+        assert_eq!(short.market_value(55.0), -5_500.0);
+        assert_eq!(short.unrealized_pnl(55.0), -500.0);
+        assert_eq!(short.unrealized_pnl(45.0), 500.0);
+    }
+    use chrono::TimeZone;
+
+    fn parse_ticker(datetime: &str) -> Ticker {
+        let csv = format!("open,high,low,close,volume,datetime\n1.0,2.0,0.5,1.5,100,{}\n", datetime);
+        csv::Reader::from_reader(csv.as_bytes())
+            .deserialize::<Ticker>()
+            .next()
+            .unwrap()
+            .unwrap()
+    }
+
+    fn ticker(open: f32, high: f32, low: f32, close: f32) -> Ticker {
+        Ticker {
+            open,
+            high,
+            low,
+            close,
+            volume: 100,
+            datetime: Utc.timestamp_opt(0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn typical_price() {
+        assert_eq!(ticker(10.0, 12.0, 9.0, 11.0).typical_price(), 32.0 / 3.0);
+    }
+
+    #[test]
+    fn median_price() {
+        assert_eq!(ticker(10.0, 12.0, 9.0, 11.0).median_price(), 10.5);
+    }
+
+    #[test]
+    fn ohlc_average() {
+        assert_eq!(ticker(10.0, 12.0, 9.0, 11.0).ohlc_average(), 10.5);
+        assert_eq!(ticker(10.0, 14.0, 9.0, 11.0).ohlc_average(), 11.0);
+    }
+
+    #[test]
+    fn pct_return_up_and_down() {
+        let prev = ticker(10.0, 12.0, 9.0, 10.0);
+        let up = ticker(10.0, 12.0, 9.0, 11.0);
+        let down = ticker(10.0, 12.0, 9.0, 9.0);
+        assert!((pct_return(&prev, &up) - 0.1).abs() < 1e-6);
+        assert!((pct_return(&prev, &down) + 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn deserialize_unix_timestamp() {
+        let expected = Utc.with_ymd_and_hms(2022, 6, 9, 13, 30, 0).unwrap();
+        assert_eq!(parse_ticker("1654781400").datetime, expected);
+    }
+
+    #[test]
+    fn deserialize_datetime() {
+        let expected = Utc.with_ymd_and_hms(2022, 6, 9, 13, 30, 0).unwrap();
+        assert_eq!(parse_ticker("2022-06-09 13:30:00").datetime, expected);
+    }
+
+    #[test]
+    fn deserialize_date() {
+        let expected = Utc.with_ymd_and_hms(2022, 6, 9, 0, 0, 0).unwrap();
+        assert_eq!(parse_ticker("2022-06-09").datetime, expected);
+    }
+
+    #[test]
+    fn deserialize_rfc3339() {
+        let expected = Utc.with_ymd_and_hms(2022, 6, 9, 13, 30, 0).unwrap();
+        assert_eq!(parse_ticker("2022-06-09T09:30:00-04:00").datetime, expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        let ticker = parse_ticker("2022-06-09 13:30:00");
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(&ticker).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let round_tripped = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize::<Ticker>()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(round_tripped.datetime, ticker.datetime);
+    }
+}
@@ -1,2 +1 @@
-pub mod config;
-pub mod serde_ext;
\ No newline at end of file
+pub mod serde_ext;
@@ -1,40 +1,17 @@
-pub mod yyyy_mm_dd {
-	use chrono::{NaiveDate, NaiveTime, DateTime, Utc, TimeZone, Date};
-	use serde::{self, Deserialize, Serializer, Deserializer};
-
-	const FORMAT: &'static str = "%Y-%m-%d";
-
-	pub fn serialize<S>(
-			date: &DateTime<Utc>,
-			serializer: S,
-	) -> Result<S::Ok, S::Error>
-	where
-			S: Serializer,
-	{
-			let s = format!("{}", date.format(FORMAT));
-			serializer.serialize_str(&s)
-	}
-
-	pub fn deserialize<'de, D>(
-			deserializer: D,
-	) -> Result<DateTime<Utc>, D::Error>
-	where
-			D: Deserializer<'de>,
-	{
-		let s: &str = Deserialize::deserialize(deserializer)?;
-		// There is probably a better way to do this...
-    let parsed_date = NaiveDate::parse_from_str(s, FORMAT)
-      .map_err(serde::de::Error::custom)?;
-		let default_time = NaiveTime::from_hms_opt(0, 0, 0).unwrap();
-     Ok(DateTime::from_utc(parsed_date.and_time(default_time), Utc))
-	}
-}
-
+/// Serializes datetimes as `%Y-%m-%d %H:%M:%S`.
+///
+/// Deserialization is lenient and accepts, in order:
+/// - a unix timestamp in seconds (integer)
+/// - `%Y-%m-%d %H:%M:%S`
+/// - `%Y-%m-%d` (midnight UTC)
+/// - [RFC 3339](https://www.rfc-editor.org/rfc/rfc3339)
 pub mod yyyy_mm_dd_hh_mm_ss {
-	use chrono::{DateTime, Utc, TimeZone};
-	use serde::{self, Deserialize, Serializer, Deserializer};
+	use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+	use serde::{self, de, Serializer, Deserializer};
+	use std::fmt;
 
-  const FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
+  const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+  const DATE_FORMAT: &str = "%Y-%m-%d";
 
   pub fn serialize<S>(
       date: &DateTime<Utc>,
@@ -53,8 +30,49 @@ pub mod yyyy_mm_dd_hh_mm_ss {
   where
       D: Deserializer<'de>,
   {
-      let timestamp: i64 = Deserialize::deserialize(deserializer)?;
-      let naive_datetime = Utc.timestamp_opt(timestamp, 0).unwrap();
-      Ok(naive_datetime)
+      deserializer.deserialize_any(DateTimeVisitor)
+  }
+
+  /// Tries each supported representation in turn.
+  pub fn parse(s: &str) -> Option<DateTime<Utc>> {
+      if let Ok(timestamp) = s.parse::<i64>() {
+          return Utc.timestamp_opt(timestamp, 0).single();
+      }
+      if let Ok(datetime) = NaiveDateTime::parse_from_str(s, FORMAT) {
+          return Some(datetime.and_utc());
+      }
+      if let Ok(date) = NaiveDate::parse_from_str(s, DATE_FORMAT) {
+          return date.and_hms_opt(0, 0, 0).map(|datetime| datetime.and_utc());
+      }
+      if let Ok(datetime) = DateTime::parse_from_rfc3339(s) {
+          return Some(datetime.with_timezone(&Utc));
+      }
+      None
+  }
+
+  struct DateTimeVisitor;
+
+  impl<'de> de::Visitor<'de> for DateTimeVisitor {
+      type Value = DateTime<Utc>;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+          write!(f, "a unix timestamp or a datetime string")
+      }
+
+      fn visit_i64<E: de::Error>(self, timestamp: i64) -> Result<Self::Value, E> {
+          Utc.timestamp_opt(timestamp, 0)
+              .single()
+              .ok_or_else(|| E::custom(format!("invalid timestamp: {}", timestamp)))
+      }
+
+      fn visit_u64<E: de::Error>(self, timestamp: u64) -> Result<Self::Value, E> {
+          let timestamp = i64::try_from(timestamp)
+              .map_err(|_| E::custom(format!("invalid timestamp: {}", timestamp)))?;
+          self.visit_i64(timestamp)
+      }
+
+      fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+          parse(s).ok_or_else(|| E::custom(format!("invalid datetime: {}", s)))
+      }
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,13 @@
+use std::process::Command;
+
+#[test]
+fn runs_a_backtest() {
+    let output = Command::new(env!("CARGO_BIN_EXE_backtester"))
+        .arg("./benches/datasets/timeseries/AAC.csv")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Strategy: SMA Crossover"));
+    assert!(stdout.contains("Total Return"));
+}